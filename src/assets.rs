@@ -27,7 +27,8 @@ impl Plugin for AssetsPlugin {
     }
 }
 
-#[derive(AssetCollection, Resource)]
+// Default is blank handles, for running without a window (see headless.rs)
+#[derive(AssetCollection, Resource, Default)]
 pub struct ImageAssets {
     #[asset(path = "request_new.png")]
     pub request: Handle<Image>,
@@ -35,6 +36,8 @@ pub struct ImageAssets {
     pub server: Handle<Image>,
     #[asset(path = "server_proxy.png")]
     pub server_proxy: Handle<Image>,
+    #[asset(path = "load_balancer.png")]
+    pub ingress: Handle<Image>,
     #[asset(path = "indiegameslogo.png")]
     pub logo: Handle<Image>,
     #[asset(path = "tutorial_new.png")]
@@ -75,6 +78,7 @@ fn handle_play_sounds(
     mut commands: Commands,
     mut evs: EventReader<PlaySound>,
     sounds: Res<SoundAssets>,
+    save: Res<SaveGame>,
 ) {
     for ev in evs.read() {
         commands.spawn(AudioBundle {
@@ -86,6 +90,7 @@ fn handle_play_sounds(
             },
             settings: PlaybackSettings {
                 mode: bevy::audio::PlaybackMode::Despawn,
+                volume: bevy::audio::Volume::new(save.settings.effective_volume()),
                 ..default()
             },
             ..default()
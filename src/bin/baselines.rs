@@ -0,0 +1,55 @@
+#![feature(new_range_api)]
+
+// Plays every level with the baseline agents of the gym environment and
+// prints how they did, something for learned agents to beat:
+//
+//     cargo run --release --bin baselines -- [episodes per level]
+
+use indie_games_website_simulator::prelude::*;
+
+const DEFAULT_EPISODES: u64 = 5;
+
+fn main() {
+    let episodes = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_EPISODES);
+
+    let mut env = Env::default();
+    let mut agents: Vec<Box<dyn Agent>> =
+        vec![Box::new(RandomAgent::new(0)), Box::new(GreedyAgent)];
+
+    println!(
+        "{:<16} {:<8} {:>8} {:>8} {:>8} {:>8}",
+        "level", "agent", "reward", "handled", "avg ms", "passed"
+    );
+    for level in 0..env.levels.len() {
+        let title = env.levels[level].title.clone();
+        for agent in agents.iter_mut() {
+            let mut reward = 0.0;
+            let mut handled = 0.0;
+            let mut response_time = 0.0;
+            let mut passed = 0;
+            for seed in 0..episodes {
+                let step = run_episode(&mut env, agent.as_mut(), level, seed);
+                let outcome = step.outcome.expect("episodes end with a run");
+                reward += step.reward;
+                handled += outcome.results.current_percentage;
+                response_time += outcome.stats.avg_response_time;
+                passed += outcome.results.passed as u64;
+            }
+            let episodes_f = episodes as f32;
+            println!(
+                "{:<16} {:<8} {:>8.3} {:>7.1}% {:>8.1} {:>5}/{}",
+                title,
+                agent.name(),
+                reward / episodes_f,
+                handled / episodes_f * 100.0,
+                // Same ms as the game shows
+                response_time / episodes_f * 10.0,
+                passed,
+                episodes
+            );
+        }
+    }
+}
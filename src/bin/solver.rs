@@ -0,0 +1,84 @@
+#![feature(new_range_api)]
+
+// Checks every level against the rules in TODO.md: searches for the best
+// layout, and tries the trivial ones that shouldn't pass.
+//
+//     cargo run --release --bin solver -- [level number]
+//
+// The best layouts are printed as blueprints, paste them into the game with
+// Ctrl+V during planning to watch them.
+
+use indie_games_website_simulator::prelude::*;
+
+fn describe(evaluation: &Evaluation) -> String {
+    format!(
+        "passed {}/{} seeds, {:.1}% handled, {:.0}ms avg",
+        evaluation.passed,
+        evaluation.runs,
+        evaluation.handled * 100.0,
+        // Same ms as the game shows
+        evaluation.avg_response_time * 10.0
+    )
+}
+
+fn main() {
+    let only = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse::<usize>().ok());
+    let levels = GameLevels::default().levels;
+    let settings = SolverSettings::default();
+
+    for (index, level) in levels.iter().enumerate() {
+        if only.is_some_and(|number| number != index + 1) {
+            continue;
+        }
+        println!("== Level {}: {} ==", index + 1, level.title);
+
+        let solution = solve(index, level, &settings);
+        println!("Best layout: {}", describe(&solution.evaluation));
+        println!("{}", solution.blueprint.to_text());
+        println!(
+            "Passable: {}",
+            if solution.evaluation.passed_all() {
+                "yes"
+            } else if solution.evaluation.passed > 0 {
+                "only with some seeds"
+            } else {
+                "not that we could find"
+            }
+        );
+
+        let no_proxies = solve(
+            index,
+            level,
+            &SolverSettings {
+                allow_proxies: false,
+                ..settings.clone()
+            },
+        );
+        println!(
+            "Without proxies: {}{}",
+            describe(&no_proxies.evaluation),
+            if no_proxies.evaluation.passed > 0 {
+                ", a proxy isn't required!"
+            } else {
+                ""
+            }
+        );
+
+        let passing: Vec<String> = trivial_strategies(index, level)
+            .into_iter()
+            .filter(|(_, blueprint)| evaluate(level, blueprint, &settings.seeds).passed > 0)
+            .map(|(name, _)| name)
+            .collect();
+        if passing.is_empty() {
+            println!("No single server strategy passes");
+        } else {
+            println!("Single server strategies that pass:");
+            for name in passing {
+                println!("  {name}");
+            }
+        }
+        println!();
+    }
+}
@@ -0,0 +1,444 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct BlueprintPlugin;
+
+impl Plugin for BlueprintPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ExportBlueprintEvent>()
+            .add_event::<ImportBlueprintEvent>()
+            .add_systems(
+                Update,
+                (
+                    blueprint_shortcuts,
+                    export_blueprint.run_if(on_event::<ExportBlueprintEvent>()),
+                    import_blueprint.run_if(on_event::<ImportBlueprintEvent>()),
+                )
+                    .run_if(in_state(GameState::Planning)),
+            );
+    }
+}
+
+/// A server layout that can be shared as a string and loaded back in
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Blueprint {
+    // Which level this was made for, only informative
+    #[serde(rename = "l", default)]
+    pub level: usize,
+    #[serde(rename = "s")]
+    pub servers: Vec<BlueprintServer>,
+    // Indexes into `servers` that the Internet points at
+    #[serde(rename = "i", default)]
+    pub ingress: Vec<usize>,
+    #[serde(rename = "a", default = "default_algorithm")]
+    pub ingress_algorithm: LoadBalancingAlgorithm,
+    #[serde(rename = "b", default)]
+    pub backpressure: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlueprintServer {
+    pub x: f32,
+    pub y: f32,
+    #[serde(rename = "m")]
+    pub mode: ServerMode,
+    #[serde(rename = "p")]
+    pub processing_power: usize,
+    #[serde(rename = "q")]
+    pub queue_size: usize,
+    #[serde(rename = "d", default)]
+    pub discipline: QueueDiscipline,
+    // Indexes into `Blueprint::servers`
+    #[serde(rename = "o", default)]
+    pub outputs: Vec<usize>,
+}
+
+/// Which entry in the run's blueprint a server is, so changes made during a
+/// run can be pointed at the same server again when replaying it
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct BlueprintSlot(pub usize);
+
+/// Sort key for servers in blueprint order: the free servers first, then
+/// the ones that came locked with the level, each in the order they spawned
+pub fn slot_key(locked: bool, order: Option<&SpawnOrder>) -> (bool, u64) {
+    (locked, order.map_or(u64::MAX, |order| order.0))
+}
+
+/// What systems need to put servers in blueprint order, see `slot_order`
+pub type SlotOrderQuery<'w, 's> = Query<'w, 's, (&'static SpawnOrder, Has<Locked>)>;
+
+pub fn slot_order(q_slot: &SlotOrderQuery, e_server: Entity) -> (bool, u64) {
+    match q_slot.get(e_server) {
+        Ok((order, locked)) => slot_key(locked, Some(order)),
+        Err(_) => slot_key(false, None),
+    }
+}
+
+fn default_algorithm() -> LoadBalancingAlgorithm {
+    LoadBalancingAlgorithm::RoundRobin
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BlueprintError {
+    Malformed(String),
+    TooManyServers { used: usize, available: usize },
+    TooManyPoints { used: usize, available: usize },
+    TooManyDnsRecords { used: usize, available: usize },
+    NoProcessingPower(usize),
+    UnknownServer(usize),
+    LockedServerChanged(usize),
+}
+
+impl std::fmt::Display for BlueprintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlueprintError::Malformed(err) => write!(f, "Not a valid blueprint: {err}"),
+            BlueprintError::TooManyServers { used, available } => {
+                write!(
+                    f,
+                    "Blueprint uses {used} servers, level only has {available}"
+                )
+            }
+            BlueprintError::TooManyPoints { used, available } => {
+                write!(
+                    f,
+                    "Blueprint needs {used} upgrade points, level only has {available}"
+                )
+            }
+            BlueprintError::TooManyDnsRecords { used, available } => {
+                write!(
+                    f,
+                    "Blueprint uses {used} DNS records, level only allows {available}"
+                )
+            }
+            BlueprintError::NoProcessingPower(index) => {
+                write!(f, "Server #{index} has no processing power")
+            }
+            BlueprintError::UnknownServer(index) => {
+                write!(
+                    f,
+                    "Blueprint connects to server #{index} which doesn't exist"
+                )
+            }
+            BlueprintError::LockedServerChanged(index) => {
+                write!(
+                    f,
+                    "Server #{index} came with the level, it can't be changed"
+                )
+            }
+        }
+    }
+}
+
+impl Blueprint {
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("blueprints always serialize")
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, BlueprintError> {
+        serde_json::from_str(text.trim()).map_err(|err| BlueprintError::Malformed(err.to_string()))
+    }
+
+    /// Total upgrade points the blueprint needs
+    pub fn cost(&self) -> usize {
+        self.servers
+            .iter()
+            .map(|server| spent_points(server.processing_power, server.queue_size))
+            .sum()
+    }
+
+    /// Checks that the blueprint is well formed and fits within the level limits
+    pub fn validate(&self, level: &Level) -> Result<(), BlueprintError> {
+        // The level's locked servers are in the blueprint too
+        let available = level.available_servers + level.locked_servers.len();
+        if self.servers.len() > available {
+            return Err(BlueprintError::TooManyServers {
+                used: self.servers.len(),
+                available,
+            });
+        }
+        let cost = self.cost();
+        if cost > level.upgrade_points {
+            return Err(BlueprintError::TooManyPoints {
+                used: cost,
+                available: level.upgrade_points,
+            });
+        }
+        for (index, server) in self.servers.iter().enumerate() {
+            if server.processing_power == 0 {
+                return Err(BlueprintError::NoProcessingPower(index));
+            }
+            if let Some(output) = server.outputs.iter().find(|o| **o >= self.servers.len()) {
+                return Err(BlueprintError::UnknownServer(*output));
+            }
+        }
+        if let Some(output) = self.ingress.iter().find(|o| **o >= self.servers.len()) {
+            return Err(BlueprintError::UnknownServer(*output));
+        }
+        if !level.allows_dns_records(self.ingress.len()) {
+            return Err(BlueprintError::TooManyDnsRecords {
+                used: self.ingress.len(),
+                available: level.max_dns_records.unwrap_or_default(),
+            });
+        }
+        if let Some(index) = changed_locked_server(level, self) {
+            return Err(BlueprintError::LockedServerChanged(index));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Event)]
+pub struct ExportBlueprintEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for ExportBlueprintEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        ExportBlueprintEvent
+    }
+}
+
+#[derive(Event)]
+pub struct ImportBlueprintEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for ImportBlueprintEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        ImportBlueprintEvent
+    }
+}
+
+// Ctrl+C / Ctrl+V (or Cmd on mac)
+fn blueprint_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut evs_export: EventWriter<ExportBlueprintEvent>,
+    mut evs_import: EventWriter<ImportBlueprintEvent>,
+) {
+    let modifier = keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    if !modifier {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyC) {
+        evs_export.send(ExportBlueprintEvent);
+    }
+    if keys.just_pressed(KeyCode::KeyV) {
+        evs_import.send(ImportBlueprintEvent);
+    }
+}
+
+/// Whether `blueprint` leaves the level's locked servers like they came,
+/// they can only get outputs when they're proxies. Expects them after the
+/// free servers, like `ApplyBlueprint` puts them.
+pub fn respects_locked_servers(level: &Level, blueprint: &Blueprint) -> bool {
+    changed_locked_server(level, blueprint).is_none()
+}
+
+// Index of the first locked server the blueprint changes
+fn changed_locked_server(level: &Level, blueprint: &Blueprint) -> Option<usize> {
+    blueprint
+        .servers
+        .iter()
+        .enumerate()
+        .skip(level.available_servers)
+        .zip(&level.locked_servers)
+        .find(|((_, server), locked)| {
+            server.mode != locked.mode
+                || server.processing_power != 1
+                || server.queue_size != 0
+                || server.discipline != QueueDiscipline::Fifo
+                || (locked.mode == ServerMode::Process && !server.outputs.is_empty())
+        })
+        .map(|((index, _), _)| index)
+}
+
+/// Captures the current servers, in blueprint order (see `slot_order`) so
+/// indexes stay stable
+pub fn capture_blueprint(
+    level: usize,
+    servers: &[(Entity, Vec3, &Server)],
+    ingress: Option<&Ingress>,
+) -> Blueprint {
+    let index_of = |entity: &Entity| servers.iter().position(|(e, _, _)| e == entity);
+    Blueprint {
+        level,
+        servers: servers
+            .iter()
+            .map(|(_, translation, server)| BlueprintServer {
+                x: translation.x,
+                y: translation.y,
+                mode: server.mode,
+                processing_power: server.processing_power,
+                queue_size: server.queue_size,
+                discipline: server.discipline,
+                outputs: server.outputs.iter().filter_map(index_of).collect(),
+            })
+            .collect(),
+        ingress: match ingress {
+            Some(ingress) => ingress.outputs.iter().filter_map(index_of).collect(),
+            None => vec![],
+        },
+        backpressure: ingress.map_or(false, |ingress| ingress.backpressure),
+        ingress_algorithm: match ingress {
+            Some(ingress) => ingress.algorithm,
+            None => LoadBalancingAlgorithm::RoundRobin,
+        },
+    }
+}
+
+fn export_blueprint(
+    q_servers: Query<(Entity, &Transform, &Server)>,
+    q_slot: SlotOrderQuery,
+    q_ingress: Query<&Ingress>,
+    game_levels: Res<GameLevels>,
+) {
+    let mut servers: Vec<(Entity, Vec3, &Server)> = q_servers
+        .iter()
+        .map(|(e, t, server)| (e, t.translation, server))
+        .collect();
+    servers.sort_by_key(|(e, _, _)| slot_order(&q_slot, *e));
+
+    let blueprint = capture_blueprint(game_levels.current, &servers, q_ingress.get_single().ok());
+    let text = blueprint.to_text();
+    println!("Blueprint: {text}");
+    if copy_text("Copy your blueprint", &text) {
+        println!("Copied blueprint");
+    }
+}
+
+fn import_blueprint(mut commands: Commands, game_levels: Res<GameLevels>) {
+    let Some(text) = paste_text("Paste a blueprint") else {
+        return;
+    };
+    let blueprint = match Blueprint::from_text(&text) {
+        Ok(blueprint) => blueprint,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    if let Err(err) = blueprint.validate(game_levels.active_level()) {
+        println!("Can't use blueprint: {err}");
+        return;
+    }
+    commands.add(ApplyBlueprint(blueprint));
+}
+
+/// Rebuilds the current servers from a (validated) blueprint. Servers the
+/// blueprint doesn't mention are reset to defaults, and if there are fewer
+/// servers than the blueprint needs, new ones get spawned. Locked servers are
+/// left like the level placed them, apart from a locked proxy's outputs.
+pub struct ApplyBlueprint(pub Blueprint);
+
+impl Command for ApplyBlueprint {
+    fn apply(self, world: &mut World) {
+        let blueprint = self.0;
+        let (proxy_image, server_image) = {
+            let image_assets = world.resource::<ImageAssets>();
+            (
+                image_assets.server_proxy.clone(),
+                image_assets.server.clone(),
+            )
+        };
+
+        let mut free: Vec<(SpawnOrder, Entity)> = world
+            .query_filtered::<(&SpawnOrder, Entity), (With<Server>, Without<Locked>)>()
+            .iter(world)
+            .map(|(order, entity)| (*order, entity))
+            .collect();
+        free.sort();
+        let mut free: Vec<Entity> = free.into_iter().map(|(_, entity)| entity).collect();
+        let mut locked: Vec<(SpawnOrder, Entity)> = world
+            .query_filtered::<(&SpawnOrder, Entity), (With<Server>, With<Locked>)>()
+            .iter(world)
+            .map(|(order, entity)| (*order, entity))
+            .collect();
+        locked.sort();
+        let locked: Vec<Entity> = locked.into_iter().map(|(_, entity)| entity).collect();
+        // Missing servers are free ones, the level places all locked ones
+        while free.len() + locked.len() < blueprint.servers.len() {
+            let planned = &blueprint.servers[free.len()];
+            free.push(spawn_server(world, Vec3::new(planned.x, planned.y, 5.0)));
+        }
+        let servers: Vec<Entity> = free.into_iter().chain(locked).collect();
+
+        for (index, e_server) in servers.iter().enumerate() {
+            let mut entity = world.entity_mut(*e_server);
+            entity.insert(BlueprintSlot(index));
+            if entity.contains::<Locked>() {
+                // Came with the level, all a blueprint gets to do is wire up
+                // a locked proxy
+                let outputs: Vec<Entity> = blueprint
+                    .servers
+                    .get(index)
+                    .map(|planned| {
+                        planned
+                            .outputs
+                            .iter()
+                            .filter_map(|output| servers.get(*output).copied())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let mut server = entity.get_mut::<Server>().unwrap();
+                if server.mode == ServerMode::Proxy {
+                    server.outputs = outputs;
+                }
+                continue;
+            }
+            entity.remove::<Animator<Transform>>();
+            let default = BlueprintServer {
+                x: entity.get::<Transform>().unwrap().translation.x,
+                y: entity.get::<Transform>().unwrap().translation.y,
+                mode: ServerMode::Process,
+                processing_power: 1,
+                queue_size: 0,
+                discipline: QueueDiscipline::Fifo,
+                outputs: vec![],
+            };
+            let planned = blueprint.servers.get(index).unwrap_or(&default);
+
+            let mut transform = entity.get_mut::<Transform>().unwrap();
+            transform.translation.x = planned.x;
+            transform.translation.y = planned.y;
+
+            *entity.get_mut::<Handle<Image>>().unwrap() = match planned.mode {
+                ServerMode::Process => server_image.clone(),
+                ServerMode::Proxy => proxy_image.clone(),
+            };
+
+            let mut server = entity.get_mut::<Server>().unwrap();
+            server.mode = planned.mode;
+            server.processing_power = planned.processing_power;
+            server.queue_size = planned.queue_size;
+            server.discipline = planned.discipline;
+            server.outputs = planned
+                .outputs
+                .iter()
+                .filter_map(|output| servers.get(*output).copied())
+                .collect();
+            server.clear_queue();
+            server.current_request = None;
+            server.reset_progress();
+        }
+
+        let mut q_ingress = world.query::<&mut Ingress>();
+        if let Ok(mut ingress) = q_ingress.get_single_mut(world) {
+            ingress.algorithm = blueprint.ingress_algorithm;
+            ingress.backpressure = blueprint.backpressure;
+            ingress.set_outputs(
+                blueprint
+                    .ingress
+                    .iter()
+                    .filter_map(|output| servers.get(*output).copied())
+                    .collect(),
+            );
+        }
+
+        let mut points = world.resource_mut::<UpgradePoints>();
+        points.assigned = blueprint.cost();
+        points.clamp_reserved();
+        println!("Applied blueprint from level {}", blueprint.level + 1);
+    }
+}
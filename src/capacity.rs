@@ -0,0 +1,280 @@
+use crate::prelude::*;
+use bevy::color::palettes::{css::WHITE_SMOKE, tailwind::*};
+
+pub struct CapacityPlugin;
+
+impl Plugin for CapacityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Planning), spawn_capacity_ui)
+            .add_systems(
+                OnExit(GameState::Planning),
+                (
+                    clear_entity_with::<CapacityUI>,
+                    clear_entity_with::<CapacityLabel>,
+                ),
+            )
+            .add_systems(
+                Update,
+                update_capacity_planner
+                    .after(analyse_topology)
+                    .run_if(in_state(GameState::Planning)),
+            );
+    }
+}
+
+/// How one server is expected to hold up at a given load
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerCapacity {
+    // Requests per second it can get through
+    pub service_rate: f32,
+    // Requests per second sent its way
+    pub arrival_rate: f32,
+    // 0.0 <> 1.0+, how busy it would be if nothing got dropped
+    pub utilisation: f32,
+    // Seconds spent waiting in the queue, on average
+    pub queueing_delay: f32,
+    // 0.0 <> 1.0 chance a request shows up to a full queue and gets dropped
+    pub drop_chance: f32,
+}
+
+/// Treats a server as an M/M/1/K queue, one request being worked on and
+/// `queue_size` waiting. Real processing times are fixed rather than random,
+/// so this is a bit pessimistic, but close enough to plan with.
+pub fn server_capacity(
+    mode: ServerMode,
+    processing_power: usize,
+    queue_size: usize,
+    arrival_rate: f32,
+) -> ServerCapacity {
+    let service_rate = service_rate(mode, processing_power);
+    let rho = arrival_rate / service_rate;
+    // Most requests that can be in the server at once
+    let k = (queue_size + 1) as i32;
+
+    let (drop_chance, in_system) = if arrival_rate <= 0.0 {
+        (0.0, 0.0)
+    } else if (rho - 1.0).abs() < 0.0001 {
+        (1.0 / (k + 1) as f32, k as f32 / 2.0)
+    } else {
+        let normalise = (1.0 - rho) / (1.0 - rho.powi(k + 1));
+        let drop_chance = normalise * rho.powi(k);
+        let in_system =
+            rho / (1.0 - rho) - (k + 1) as f32 * rho.powi(k + 1) / (1.0 - rho.powi(k + 1));
+        (drop_chance, in_system)
+    };
+    // Little's law on the requests that actually get in
+    let accepted = arrival_rate * (1.0 - drop_chance);
+    let queueing_delay = if accepted > 0.0 {
+        (in_system / accepted - 1.0 / service_rate).max(0.0)
+    } else {
+        0.0
+    };
+
+    ServerCapacity {
+        service_rate,
+        arrival_rate,
+        utilisation: rho,
+        queueing_delay,
+        drop_chance,
+    }
+}
+
+/// The whole layout at a given incoming RPS
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapacityPlan {
+    pub rps: f32,
+    // Same order as the blueprint's servers
+    pub servers: Vec<ServerCapacity>,
+    // Most RPS before a server is busy all the time
+    pub max_sustainable_rps: f32,
+    // Server that hits 100% busy first
+    pub bottleneck: Option<usize>,
+    // Seconds, on average, from reaching the first server to being done
+    pub response_time: f32,
+}
+
+pub fn plan_capacity(blueprint: &Blueprint, rps: f32) -> CapacityPlan {
+    let shares = load_shares(blueprint);
+    let servers: Vec<ServerCapacity> = blueprint
+        .servers
+        .iter()
+        .zip(&shares)
+        .map(|(server, share)| {
+            server_capacity(
+                server.mode,
+                server.processing_power,
+                server.queue_size,
+                share * rps,
+            )
+        })
+        .collect();
+
+    // Whichever server has the least headroom for its share of the traffic
+    let bottleneck = shares
+        .iter()
+        .zip(&servers)
+        .enumerate()
+        .filter(|(_, (share, _))| **share > 0.0)
+        .min_by(|(_, (share_a, a)), (_, (share_b, b))| {
+            (a.service_rate / **share_a).total_cmp(&(b.service_rate / **share_b))
+        })
+        .map(|(index, _)| index);
+
+    // Every visit to a server costs its wait plus its processing time
+    let response_time = shares
+        .iter()
+        .zip(&servers)
+        .map(|(share, capacity)| share * (capacity.queueing_delay + 1.0 / capacity.service_rate))
+        .sum();
+
+    CapacityPlan {
+        rps,
+        servers,
+        max_sustainable_rps: max_throughput(blueprint),
+        bottleneck,
+        response_time,
+    }
+}
+
+#[derive(Component)]
+pub struct CapacityUI;
+
+#[derive(Component)]
+struct CapacityText;
+
+#[derive(Component)]
+pub struct CapacityLabel;
+
+fn spawn_capacity_ui(mut commands: Commands) {
+    commands
+        .spawn((
+            CapacityUI,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    width: Val::Px(380.0),
+                    padding: UiRect::all(Val::Px(15.0)),
+                    ..default()
+                },
+                background_color: Color::srgba(0.15, 0.15, 0.15, 0.8).into(),
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 18.0,
+                        color: WHITE_SMOKE.into(),
+                        ..default()
+                    },
+                ),
+                CapacityText,
+                Pickable::IGNORE,
+            ));
+        });
+}
+
+fn update_capacity_planner(
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    report: Res<TopologyReport>,
+    game_levels: Res<GameLevels>,
+    q_transforms: Query<&Transform, With<Server>>,
+    q_labels: Query<Entity, With<CapacityLabel>>,
+    mut q_text: Query<&mut Text, With<CapacityText>>,
+    mut plan: Local<Option<CapacityPlan>>,
+) {
+    let Some(blueprint) = &report.blueprint else {
+        return;
+    };
+    if report.is_changed() || plan.is_none() {
+        *plan = Some(plan_capacity(
+            blueprint,
+            game_levels.active_level().peak_rps(),
+        ));
+        let plan = plan.as_ref().unwrap();
+
+        // Same scale as the running UI, where a second of age shows as 10ms
+        let as_ms = |seconds: f32| seconds * 10.0;
+        let total_drop_chance: f32 = load_shares(blueprint)
+            .iter()
+            .zip(&plan.servers)
+            .map(|(share, capacity)| share * capacity.drop_chance)
+            .sum::<f32>()
+            .min(1.0);
+        let bottleneck = match plan.bottleneck {
+            Some(index) => format!(
+                "{:.0}% busy (circled)",
+                plan.servers[index].utilisation * 100.0
+            ),
+            None => "n/a".to_string(),
+        };
+        if let Ok(mut text) = q_text.get_single_mut() {
+            text.sections[0].value = format!(
+                "Capacity planner\n\
+                 Level peak: {:.1} RPS\n\
+                 Max sustainable: {:.1} RPS\n\
+                 Saturates first: {bottleneck}\n\
+                 Expected response time: {:.0} ms\n\
+                 Dropped at peak: ~{:.0}%",
+                plan.rps,
+                plan.max_sustainable_rps,
+                as_ms(plan.response_time),
+                total_drop_chance * 100.0,
+            );
+        }
+
+        for entity in q_labels.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        for (e_server, capacity) in report.servers.iter().zip(&plan.servers) {
+            let Ok(transform) = q_transforms.get(*e_server) else {
+                continue;
+            };
+            let color = if capacity.utilisation >= 1.0 {
+                RED_400
+            } else if capacity.utilisation >= 0.8 {
+                AMBER_400
+            } else {
+                GREEN_400
+            };
+            commands.spawn((
+                CapacityLabel,
+                Text2dBundle {
+                    text: Text::from_section(
+                        format!(
+                            "{:.0}% busy, ~{:.0} ms wait",
+                            capacity.utilisation * 100.0,
+                            as_ms(capacity.queueing_delay)
+                        ),
+                        TextStyle {
+                            font_size: 16.0,
+                            color: color.into(),
+                            ..default()
+                        },
+                    ),
+                    transform: Transform::from_translation(
+                        (transform.translation.truncate() + Vec2::new(0.0, -60.0)).extend(20.0),
+                    ),
+                    ..default()
+                },
+                Pickable::IGNORE,
+            ));
+        }
+    }
+
+    if let Some(index) = plan.as_ref().and_then(|plan| plan.bottleneck) {
+        if let Some(transform) = report
+            .servers
+            .get(index)
+            .and_then(|e| q_transforms.get(*e).ok())
+        {
+            gizmos.circle_2d(transform.translation.truncate(), 64.0, ORANGE_400);
+        }
+    }
+}
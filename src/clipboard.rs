@@ -0,0 +1,43 @@
+// Getting text in and out of the game. Native builds use the system clipboard,
+// on web we can't read the clipboard synchronously so we show a prompt with
+// the text selected instead, which the player can copy or paste into.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn copy_text(_title: &str, contents: &str) -> bool {
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(contents)) {
+        Ok(_) => true,
+        Err(err) => {
+            println!("Couldn't copy to clipboard: {err}");
+            false
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn paste_text(_title: &str) -> Option<String> {
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+        Ok(text) => Some(text),
+        Err(err) => {
+            println!("Couldn't read clipboard: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn copy_text(title: &str, contents: &str) -> bool {
+    let Some(window) = web_sys::window() else {
+        return false;
+    };
+    window
+        .prompt_with_message_and_default(title, contents)
+        .is_ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn paste_text(title: &str) -> Option<String> {
+    web_sys::window()?
+        .prompt_with_message(title)
+        .ok()?
+        .filter(|text| !text.is_empty())
+}
@@ -13,7 +13,7 @@ impl Plugin for DraggingPlugin {
             .add_systems(
                 Update,
                 (handle_dragging_server, handle_drag_start, handle_drag_end)
-                    .run_if(in_state(GameState::Planning)),
+                    .run_if(in_state(GameState::Planning).or_else(in_state(GameState::Sandbox))),
             );
     }
 }
@@ -83,7 +83,7 @@ define_drag_event!(DragOutputEndEvent, DragEnd, Entity);
 
 fn handle_dragging_server(
     mut events_drag: EventReader<DragServerEvent>,
-    mut q_transform: Query<&mut Transform, With<Server>>,
+    mut q_transform: Query<&mut Transform, (With<Server>, Without<Locked>)>,
 ) {
     for ev in events_drag.read() {
         if let Ok(mut transform) = q_transform.get_mut(ev.0) {
@@ -0,0 +1,989 @@
+use crate::prelude::*;
+use bevy::color::palettes::{css::WHITE_SMOKE, tailwind::*};
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy_mod_picking::prelude::PointerButton;
+
+// Where the level being edited is kept between sessions
+const EDITOR_STORAGE_KEY: &str = "editor-level";
+
+// Bottom left corner of the load curve, in world space
+const CURVE_ORIGIN: Vec2 = Vec2::new(-600.0, -330.0);
+// Pixels per second and per RPS on the load curve
+const CURVE_SECOND: f32 = 8.0;
+const CURVE_RPS: f32 = 10.0;
+const MAX_DURATION: f32 = 100.0;
+const MAX_RPS: usize = 30;
+// Handles snap to this many seconds
+const TIME_STEP: f32 = 0.5;
+
+const SCHEDULE_COLORS: [Srgba; 4] = [ORANGE_400, PURPLE_400, RED_400, YELLOW_400];
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorFocus>()
+            .add_event::<OpenEditorEvent>()
+            .add_event::<BackToEditorEvent>()
+            .add_event::<EditorTestPlayEvent>()
+            .add_event::<EditorSaveEvent>()
+            .add_event::<EditorBackEvent>()
+            .add_event::<AddScheduleEvent>()
+            .add_event::<RemoveScheduleEvent>()
+            .add_event::<AddLockedServerEvent>()
+            .add_event::<RemoveLockedServerEvent>()
+            .add_event::<FocusFieldEvent>()
+            .add_event::<DragCurveHandleEvent>()
+            .add_event::<DragLockedServerEvent>()
+            .add_event::<SwitchLockedModeEvent>()
+            .add_event::<RebuildEditorBoard>()
+            .add_systems(
+                OnEnter(GameState::Editor),
+                (leave_test_play, load_editor_level, spawn_editor_ui).chain(),
+            )
+            .add_systems(OnExit(GameState::Editor), clear_entity_with::<EditorUI>)
+            .add_systems(OnEnter(GameState::Planning), spawn_back_to_editor_button)
+            .add_systems(OnEnter(GameState::Results), spawn_back_to_editor_button)
+            .add_systems(OnExit(GameState::Planning), clear_entity_with::<TestPlayUI>)
+            .add_systems(OnExit(GameState::Results), clear_entity_with::<TestPlayUI>)
+            .add_systems(
+                Update,
+                (
+                    open_editor.run_if(on_event::<OpenEditorEvent>()),
+                    back_to_editor.run_if(on_event::<BackToEditorEvent>()),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        focus_field,
+                        type_into_field,
+                        drag_curve_handle,
+                        drag_locked_server,
+                        switch_locked_mode,
+                        add_schedule.run_if(on_event::<AddScheduleEvent>()),
+                        remove_schedule.run_if(on_event::<RemoveScheduleEvent>()),
+                        add_locked_server.run_if(on_event::<AddLockedServerEvent>()),
+                        remove_locked_server.run_if(on_event::<RemoveLockedServerEvent>()),
+                        save_level.run_if(on_event::<EditorSaveEvent>()),
+                        test_play.run_if(on_event::<EditorTestPlayEvent>()),
+                        leave_editor.run_if(on_event::<EditorBackEvent>()),
+                    ),
+                    (
+                        rebuild_board.run_if(on_event::<RebuildEditorBoard>()),
+                        sync_board,
+                        update_editor_texts,
+                        draw_load_curve,
+                    ),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Editor)),
+            );
+    }
+}
+
+/// The level being edited
+#[derive(Resource)]
+pub struct EditorLevel(pub LevelFile);
+
+/// Set while a level from the editor is being played, it sits at the end of
+/// `GameLevels` until we're back in the editor
+#[derive(Resource)]
+pub struct EditorTestPlay {
+    pub campaign_levels: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorField {
+    Title,
+    IntroText,
+    SuccessText,
+    // All of them at once, split by |
+    FailureTexts,
+    AvailableServers,
+    UpgradePoints,
+    // In percent
+    RequiredHandled,
+    // In ms like the game shows
+    RequiredResponseTime,
+    // In percent, 0 turns the SLO off
+    SloTarget,
+    // In ms like the game shows
+    SloResponseTime,
+    // In seconds
+    SloWindow,
+}
+
+impl EditorField {
+    const ALL: [EditorField; 11] = [
+        EditorField::Title,
+        EditorField::IntroText,
+        EditorField::SuccessText,
+        EditorField::FailureTexts,
+        EditorField::AvailableServers,
+        EditorField::UpgradePoints,
+        EditorField::RequiredHandled,
+        EditorField::RequiredResponseTime,
+        EditorField::SloTarget,
+        EditorField::SloResponseTime,
+        EditorField::SloWindow,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            EditorField::Title => "Title",
+            EditorField::IntroText => "Intro",
+            EditorField::SuccessText => "Success",
+            EditorField::FailureTexts => "Failures",
+            EditorField::AvailableServers => "Servers",
+            EditorField::UpgradePoints => "Upgrade Points",
+            EditorField::RequiredHandled => "Required Handled %",
+            EditorField::RequiredResponseTime => "Max Avg Response ms",
+            EditorField::SloTarget => "SLO Target %",
+            EditorField::SloResponseTime => "SLO Max Response ms",
+            EditorField::SloWindow => "SLO Window s",
+        }
+    }
+
+    fn value(&self, level: &LevelFile) -> String {
+        match self {
+            EditorField::Title => level.title.clone(),
+            EditorField::IntroText => level.intro_text.clone(),
+            EditorField::SuccessText => level.success_text.clone(),
+            EditorField::FailureTexts => level.failure_texts.join(" | "),
+            EditorField::AvailableServers => level.available_servers.to_string(),
+            EditorField::UpgradePoints => level.upgrade_points.to_string(),
+            EditorField::RequiredHandled => {
+                format!("{:.0}", level.required_handled_requests * 100.0)
+            }
+            EditorField::RequiredResponseTime => {
+                format!("{:.0}", level.required_avg_response_time * 10.0)
+            }
+            EditorField::SloTarget => level.slo.map_or("off".to_string(), |slo| {
+                format!("{:.1}", slo.target * 100.0)
+            }),
+            EditorField::SloResponseTime => level.slo.map_or("-".to_string(), |slo| {
+                format!("{:.0}", slo.max_response_time * 10.0)
+            }),
+            EditorField::SloWindow => level
+                .slo
+                .map_or("-".to_string(), |slo| format!("{:.0}", slo.window)),
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(
+            self,
+            EditorField::Title
+                | EditorField::IntroText
+                | EditorField::SuccessText
+                | EditorField::FailureTexts
+        )
+    }
+
+    fn apply(&self, level: &mut LevelFile, input: &str) -> Result<(), String> {
+        let number = || {
+            input
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| format!("{input:?} isn't a number"))
+        };
+        match self {
+            EditorField::Title => level.title = input.to_string(),
+            EditorField::IntroText => level.intro_text = input.to_string(),
+            EditorField::SuccessText => level.success_text = input.to_string(),
+            EditorField::FailureTexts => {
+                level.failure_texts = input
+                    .split('|')
+                    .map(|text| text.trim().to_string())
+                    .filter(|text| !text.is_empty())
+                    .collect();
+            }
+            EditorField::AvailableServers => level.available_servers = number()?.max(0.0) as usize,
+            EditorField::UpgradePoints => level.upgrade_points = number()?.max(0.0) as usize,
+            EditorField::RequiredHandled => {
+                level.required_handled_requests = (number()? / 100.0).clamp(0.0, 1.0)
+            }
+            EditorField::RequiredResponseTime => {
+                level.required_avg_response_time = (number()? / 10.0).max(0.1)
+            }
+            EditorField::SloTarget => {
+                let target = (number()? / 100.0).clamp(0.0, 1.0);
+                if target <= 0.0 {
+                    level.slo = None;
+                } else {
+                    level.slo.get_or_insert_with(Slo::default).target = target;
+                }
+            }
+            // Setting these on a level without an SLO starts from the default one
+            EditorField::SloResponseTime => {
+                level.slo.get_or_insert_with(Slo::default).max_response_time =
+                    (number()? / 10.0).max(0.1)
+            }
+            EditorField::SloWindow => {
+                level.slo.get_or_insert_with(Slo::default).window = number()?.max(1.0)
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The field being typed into, numbers only get applied once done
+#[derive(Resource, Default)]
+pub struct EditorFocus {
+    pub field: Option<EditorField>,
+    pub buffer: String,
+}
+
+/// Keeps other keyboard shortcuts out of the way while typing
+pub fn editor_typing(focus: Res<EditorFocus>) -> bool {
+    focus.field.is_some()
+}
+
+#[derive(Component)]
+pub struct EditorUI;
+
+#[derive(Component)]
+pub struct TestPlayUI;
+
+#[derive(Component)]
+struct EditorFieldText(EditorField);
+
+#[derive(Component)]
+struct LoadSummaryText;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandleKind {
+    // Top of the curve, how long the rampup is and how many RPS
+    Peak,
+    // Where the rampdown ends
+    End,
+}
+
+#[derive(Component)]
+struct CurveHandle {
+    schedule: usize,
+    kind: HandleKind,
+}
+
+#[derive(Component)]
+struct EditorLockedServer(usize);
+
+#[derive(Event)]
+pub struct OpenEditorEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for OpenEditorEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        OpenEditorEvent
+    }
+}
+
+#[derive(Event)]
+pub struct BackToEditorEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for BackToEditorEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        BackToEditorEvent
+    }
+}
+
+#[derive(Event)]
+pub struct EditorTestPlayEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for EditorTestPlayEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        EditorTestPlayEvent
+    }
+}
+
+#[derive(Event)]
+pub struct EditorSaveEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for EditorSaveEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        EditorSaveEvent
+    }
+}
+
+#[derive(Event)]
+pub struct EditorBackEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for EditorBackEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        EditorBackEvent
+    }
+}
+
+#[derive(Event)]
+pub struct AddScheduleEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for AddScheduleEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        AddScheduleEvent
+    }
+}
+
+#[derive(Event)]
+pub struct RemoveScheduleEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for RemoveScheduleEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        RemoveScheduleEvent
+    }
+}
+
+#[derive(Event)]
+pub struct AddLockedServerEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for AddLockedServerEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        AddLockedServerEvent
+    }
+}
+
+#[derive(Event)]
+pub struct RemoveLockedServerEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for RemoveLockedServerEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        RemoveLockedServerEvent
+    }
+}
+
+#[derive(Event)]
+pub struct FocusFieldEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<Click>>> for FocusFieldEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        FocusFieldEvent(event.target)
+    }
+}
+
+// Where the pointer is on screen, handles follow it rather than adding up
+// deltas so they can snap
+#[derive(Event)]
+pub struct DragCurveHandleEvent(pub Entity, pub Vec2);
+
+impl From<ListenerInput<Pointer<Drag>>> for DragCurveHandleEvent {
+    fn from(event: ListenerInput<Pointer<Drag>>) -> Self {
+        DragCurveHandleEvent(event.target, event.pointer_location.position)
+    }
+}
+
+#[derive(Event)]
+pub struct DragLockedServerEvent(pub Entity, pub Vec2);
+
+impl From<ListenerInput<Pointer<Drag>>> for DragLockedServerEvent {
+    fn from(event: ListenerInput<Pointer<Drag>>) -> Self {
+        DragLockedServerEvent(event.target, event.pointer_location.position)
+    }
+}
+
+// Right click on a locked server
+#[derive(Event)]
+pub struct SwitchLockedModeEvent(pub Entity, pub PointerButton);
+
+impl From<ListenerInput<Pointer<Click>>> for SwitchLockedModeEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        SwitchLockedModeEvent(event.target, event.button)
+    }
+}
+
+// Handles and locked servers got added or removed, respawn them all
+#[derive(Event)]
+struct RebuildEditorBoard;
+
+fn open_editor(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Editor);
+}
+
+fn back_to_editor(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Editor);
+}
+
+// Take the test level back out of the campaign and clear its servers
+pub(crate) fn leave_test_play(
+    mut commands: Commands,
+    test_play: Option<Res<EditorTestPlay>>,
+    mut game_levels: ResMut<GameLevels>,
+    query: Query<Entity, Or<(With<Server>, With<Ingress>, With<LevelOwned>)>>,
+) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    if let Some(test_play) = test_play {
+        game_levels.levels.truncate(test_play.campaign_levels);
+        game_levels.current = game_levels.current.min(test_play.campaign_levels - 1);
+        commands.remove_resource::<EditorTestPlay>();
+    }
+}
+
+// Carry on with whatever was being edited, or start from the current level
+fn load_editor_level(
+    mut commands: Commands,
+    editor_level: Option<Res<EditorLevel>>,
+    game_levels: Res<GameLevels>,
+) {
+    if editor_level.is_some() {
+        return;
+    }
+    let level = read_storage(EDITOR_STORAGE_KEY)
+        .and_then(|text| match LevelFile::from_text(&text) {
+            Ok(level) => Some(level),
+            Err(err) => {
+                println!("Couldn't load the saved editor level: {err}");
+                None
+            }
+        })
+        .unwrap_or_else(|| LevelFile::from(game_levels.active_level()));
+    commands.insert_resource(EditorLevel(level));
+}
+
+fn spawn_editor_ui(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut evs_rebuild: EventWriter<RebuildEditorBoard>,
+) {
+    println!("Spawning editor UI");
+    for (offset, field) in EditorField::ALL.iter().enumerate() {
+        spawn_text(
+            EditorUI,
+            &font_assets,
+            &mut commands,
+            field.label(),
+            offset,
+            (
+                EditorFieldText(*field),
+                PickableBundle::default(),
+                On::<Pointer<Click>>::send_event::<FocusFieldEvent>(),
+            ),
+        );
+    }
+    spawn_text(
+        EditorUI,
+        &font_assets,
+        &mut commands,
+        "Load",
+        EditorField::ALL.len(),
+        LoadSummaryText,
+    );
+    commands.spawn((
+        EditorUI,
+        TextBundle::from_section(
+            "Click a field to type into it, Enter to apply. Drag the handles to shape the load, \
+             drag locked servers to place them, right click one to switch its mode.",
+            TextStyle {
+                font_size: 18.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            right: Val::Px(10.0),
+            width: Val::Px(350.0),
+            ..default()
+        }),
+    ));
+
+    spawn_button::<(EditorTestPlayButton, EditorUI), EditorTestPlayEvent>(
+        &mut commands,
+        "Test Play",
+        0,
+        (EditorTestPlayButton, EditorUI),
+        GREEN_400,
+    );
+    spawn_button::<(EditorSaveButton, EditorUI), EditorSaveEvent>(
+        &mut commands,
+        "Save Level",
+        1,
+        (EditorSaveButton, EditorUI),
+        BLUE_400,
+    );
+    spawn_button::<(AddScheduleButton, EditorUI), AddScheduleEvent>(
+        &mut commands,
+        "Add Load Schedule",
+        2,
+        (AddScheduleButton, EditorUI),
+        GREEN_200,
+    );
+    spawn_button::<(RemoveScheduleButton, EditorUI), RemoveScheduleEvent>(
+        &mut commands,
+        "Remove Load Schedule",
+        3,
+        (RemoveScheduleButton, EditorUI),
+        ORANGE_200,
+    );
+    spawn_button::<(AddLockedServerButton, EditorUI), AddLockedServerEvent>(
+        &mut commands,
+        "Add Locked Server",
+        4,
+        (AddLockedServerButton, EditorUI),
+        GREEN_200,
+    );
+    spawn_button::<(RemoveLockedServerButton, EditorUI), RemoveLockedServerEvent>(
+        &mut commands,
+        "Remove Locked Server",
+        5,
+        (RemoveLockedServerButton, EditorUI),
+        ORANGE_200,
+    );
+    spawn_button::<(EditorBackButton, EditorUI), EditorBackEvent>(
+        &mut commands,
+        "Back to Levels",
+        6,
+        (EditorBackButton, EditorUI),
+        ORANGE_400,
+    );
+
+    evs_rebuild.send(RebuildEditorBoard);
+}
+
+fn rebuild_board(
+    mut commands: Commands,
+    editor_level: Res<EditorLevel>,
+    image_assets: Res<ImageAssets>,
+    query: Query<Entity, Or<(With<CurveHandle>, With<EditorLockedServer>)>>,
+) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    for schedule in 0..editor_level.0.schedules.len() {
+        let color = SCHEDULE_COLORS[schedule % SCHEDULE_COLORS.len()];
+        for kind in [HandleKind::Peak, HandleKind::End] {
+            commands.spawn((
+                EditorUI,
+                CurveHandle { schedule, kind },
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: color.into(),
+                        custom_size: Some(Vec2::splat(16.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, 20.0),
+                    ..default()
+                },
+                PickableBundle::default(),
+                On::<Pointer<Drag>>::send_event::<DragCurveHandleEvent>(),
+            ));
+        }
+    }
+    for (index, locked) in editor_level.0.locked_servers.iter().enumerate() {
+        commands.spawn((
+            EditorUI,
+            EditorLockedServer(index),
+            SpriteBundle {
+                texture: image_assets.server.clone(),
+                sprite: Sprite {
+                    color: Color::srgb(0.6, 0.6, 0.6),
+                    ..default()
+                },
+                transform: Transform::from_xyz(locked.x, locked.y, 5.0)
+                    .with_scale(Vec3::splat(0.25)),
+                ..default()
+            },
+            PickableBundle::default(),
+            On::<Pointer<Drag>>::send_event::<DragLockedServerEvent>(),
+            On::<Pointer<Click>>::send_event::<SwitchLockedModeEvent>(),
+        ));
+    }
+}
+
+// Keeps handles and locked servers where the level says they are
+fn sync_board(
+    editor_level: Res<EditorLevel>,
+    image_assets: Res<ImageAssets>,
+    mut q_handles: Query<(&CurveHandle, &mut Transform), Without<EditorLockedServer>>,
+    mut q_locked: Query<(&EditorLockedServer, &mut Transform, &mut Handle<Image>)>,
+) {
+    for (handle, mut transform) in q_handles.iter_mut() {
+        let Some(schedule) = editor_level.0.schedules.get(handle.schedule) else {
+            continue;
+        };
+        let position = match handle.kind {
+            HandleKind::Peak => curve_point(schedule.rampup, schedule.max_rps as f32),
+            HandleKind::End => curve_point(schedule.rampup + schedule.rampdown, 0.0),
+        };
+        transform.translation = position.extend(transform.translation.z);
+    }
+    for (locked_server, mut transform, mut texture) in q_locked.iter_mut() {
+        let Some(locked) = editor_level.0.locked_servers.get(locked_server.0) else {
+            continue;
+        };
+        transform.translation.x = locked.x;
+        transform.translation.y = locked.y;
+        *texture = match locked.mode {
+            ServerMode::Process => image_assets.server.clone(),
+            ServerMode::Proxy => image_assets.server_proxy.clone(),
+        };
+    }
+}
+
+fn curve_point(seconds: f32, rps: f32) -> Vec2 {
+    CURVE_ORIGIN + Vec2::new(seconds * CURVE_SECOND, rps * CURVE_RPS)
+}
+
+fn screen_to_world(q_camera: &Query<(&Camera, &GlobalTransform)>, position: Vec2) -> Option<Vec2> {
+    let (camera, camera_transform) = q_camera.get_single().ok()?;
+    camera.viewport_to_world_2d(camera_transform, position)
+}
+
+fn snap(value: f32, step: f32) -> f32 {
+    (value / step).round() * step
+}
+
+fn drag_curve_handle(
+    mut evs: EventReader<DragCurveHandleEvent>,
+    mut editor_level: ResMut<EditorLevel>,
+    q_handles: Query<&CurveHandle>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+) {
+    for ev in evs.read() {
+        let Ok(handle) = q_handles.get(ev.0) else {
+            continue;
+        };
+        let Some(world) = screen_to_world(&q_camera, ev.1) else {
+            continue;
+        };
+        let Some(schedule) = editor_level.0.schedules.get_mut(handle.schedule) else {
+            continue;
+        };
+        let seconds = snap((world.x - CURVE_ORIGIN.x) / CURVE_SECOND, TIME_STEP);
+        let end = schedule.rampup + schedule.rampdown;
+        match handle.kind {
+            HandleKind::Peak => {
+                // The end of the curve stays put
+                let rampup = seconds.clamp(TIME_STEP, end - TIME_STEP);
+                schedule.rampup = rampup;
+                schedule.rampdown = end - rampup;
+                let rps = ((world.y - CURVE_ORIGIN.y) / CURVE_RPS).round();
+                schedule.max_rps = (rps.max(1.0) as usize).min(MAX_RPS);
+            }
+            HandleKind::End => {
+                let end = seconds.clamp(schedule.rampup + TIME_STEP, MAX_DURATION);
+                schedule.rampdown = end - schedule.rampup;
+            }
+        }
+    }
+}
+
+fn drag_locked_server(
+    mut evs: EventReader<DragLockedServerEvent>,
+    mut editor_level: ResMut<EditorLevel>,
+    q_locked: Query<&EditorLockedServer>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+) {
+    for ev in evs.read() {
+        let Ok(locked_server) = q_locked.get(ev.0) else {
+            continue;
+        };
+        let Some(world) = screen_to_world(&q_camera, ev.1) else {
+            continue;
+        };
+        if let Some(locked) = editor_level.0.locked_servers.get_mut(locked_server.0) {
+            // Half a grid cell, the game lines them up once placed anyway
+            locked.x = snap(world.x, 50.0);
+            locked.y = snap(world.y, 50.0);
+        }
+    }
+}
+
+fn switch_locked_mode(
+    mut evs: EventReader<SwitchLockedModeEvent>,
+    mut editor_level: ResMut<EditorLevel>,
+    q_locked: Query<&EditorLockedServer>,
+) {
+    for ev in evs.read() {
+        if ev.1 != PointerButton::Secondary {
+            continue;
+        }
+        let Ok(locked_server) = q_locked.get(ev.0) else {
+            continue;
+        };
+        if let Some(locked) = editor_level.0.locked_servers.get_mut(locked_server.0) {
+            locked.mode = match locked.mode {
+                ServerMode::Process => ServerMode::Proxy,
+                ServerMode::Proxy => ServerMode::Process,
+            };
+        }
+    }
+}
+
+fn add_schedule(
+    mut editor_level: ResMut<EditorLevel>,
+    mut evs_rebuild: EventWriter<RebuildEditorBoard>,
+) {
+    editor_level.0.schedules.push(ScheduleFile {
+        rampup: 10.0,
+        max_rps: 5,
+        rampdown: 10.0,
+    });
+    evs_rebuild.send(RebuildEditorBoard);
+}
+
+fn remove_schedule(
+    mut editor_level: ResMut<EditorLevel>,
+    mut evs_rebuild: EventWriter<RebuildEditorBoard>,
+) {
+    editor_level.0.schedules.pop();
+    evs_rebuild.send(RebuildEditorBoard);
+}
+
+fn add_locked_server(
+    mut editor_level: ResMut<EditorLevel>,
+    mut evs_rebuild: EventWriter<RebuildEditorBoard>,
+) {
+    let count = editor_level.0.locked_servers.len();
+    editor_level.0.locked_servers.push(LockedServer {
+        x: -100.0 + count as f32 * 100.0,
+        y: 150.0,
+        mode: ServerMode::Process,
+    });
+    evs_rebuild.send(RebuildEditorBoard);
+}
+
+fn remove_locked_server(
+    mut editor_level: ResMut<EditorLevel>,
+    mut evs_rebuild: EventWriter<RebuildEditorBoard>,
+) {
+    editor_level.0.locked_servers.pop();
+    evs_rebuild.send(RebuildEditorBoard);
+}
+
+// Applies what's been typed so far, numbers that don't parse are thrown away
+fn commit_focus(focus: &mut EditorFocus, level: &mut LevelFile) {
+    if let Some(field) = focus.field.take() {
+        if let Err(err) = field.apply(level, &focus.buffer) {
+            println!("Couldn't set {}: {err}", field.label());
+        }
+    }
+    focus.buffer.clear();
+}
+
+fn focus_field(
+    mut evs: EventReader<FocusFieldEvent>,
+    mut focus: ResMut<EditorFocus>,
+    mut editor_level: ResMut<EditorLevel>,
+    q_fields: Query<&EditorFieldText>,
+) {
+    for ev in evs.read() {
+        let Ok(field) = q_fields.get(ev.0) else {
+            continue;
+        };
+        commit_focus(&mut focus, &mut editor_level.0);
+        focus.field = Some(field.0);
+        focus.buffer = field.0.value(&editor_level.0);
+    }
+}
+
+fn type_into_field(
+    mut evs: EventReader<KeyboardInput>,
+    mut focus: ResMut<EditorFocus>,
+    mut editor_level: ResMut<EditorLevel>,
+) {
+    for ev in evs.read() {
+        let Some(field) = focus.field else {
+            continue;
+        };
+        if ev.state != ButtonState::Pressed {
+            continue;
+        }
+        match &ev.logical_key {
+            Key::Character(text) => focus.buffer.push_str(text),
+            Key::Space => focus.buffer.push(' '),
+            Key::Backspace => {
+                focus.buffer.pop();
+            }
+            Key::Enter => {
+                commit_focus(&mut focus, &mut editor_level.0);
+                continue;
+            }
+            Key::Escape => {
+                focus.field = None;
+                focus.buffer.clear();
+                continue;
+            }
+            _ => continue,
+        }
+        // Text shows up in the level right away, numbers wait for Enter
+        if field.is_text() {
+            let _ = field.apply(&mut editor_level.0, &focus.buffer);
+        }
+    }
+}
+
+fn update_editor_texts(
+    editor_level: Res<EditorLevel>,
+    focus: Res<EditorFocus>,
+    mut q_fields: Query<(&EditorFieldText, &mut Text), Without<LoadSummaryText>>,
+    mut q_summary: Query<&mut Text, With<LoadSummaryText>>,
+) {
+    if !editor_level.is_changed() && !focus.is_changed() {
+        return;
+    }
+    for (field, mut text) in q_fields.iter_mut() {
+        text.sections[1].value = if focus.field == Some(field.0) {
+            format!("{}_", focus.buffer)
+        } else {
+            let value = field.0.value(&editor_level.0);
+            // Long texts would run over the load curve
+            match value.char_indices().nth(40) {
+                Some((cut, _)) => format!("{}...", &value[..cut]),
+                None => value,
+            }
+        };
+    }
+    if let Ok(mut text) = q_summary.get_single_mut() {
+        let level = editor_level.0.to_level();
+        let duration = level
+            .schedules
+            .iter()
+            .map(|schedule| schedule.duration())
+            .fold(0.0, f32::max);
+        text.sections[1].value = format!(
+            "{} schedules, {:.0} RPS peak, {:.0}s",
+            level.schedules.len(),
+            level.peak_rps(),
+            duration
+        );
+    }
+}
+
+fn draw_load_curve(mut gizmos: Gizmos, editor_level: Res<EditorLevel>) {
+    // Axes, ticks every 10s and 5 RPS
+    let axis_color = Srgba::new(0.9, 0.9, 0.9, 0.6);
+    gizmos.line_2d(CURVE_ORIGIN, curve_point(MAX_DURATION, 0.0), axis_color);
+    gizmos.line_2d(CURVE_ORIGIN, curve_point(0.0, MAX_RPS as f32), axis_color);
+    for seconds in (10..=MAX_DURATION as usize).step_by(10) {
+        let point = curve_point(seconds as f32, 0.0);
+        gizmos.line_2d(point, point - Vec2::new(0.0, 6.0), axis_color);
+    }
+    for rps in (5..=MAX_RPS).step_by(5) {
+        let point = curve_point(0.0, rps as f32);
+        gizmos.line_2d(point, point - Vec2::new(6.0, 0.0), axis_color);
+    }
+
+    for (index, schedule) in editor_level.0.schedules.iter().enumerate() {
+        let color = SCHEDULE_COLORS[index % SCHEDULE_COLORS.len()];
+        gizmos.linestrip_2d(
+            [
+                CURVE_ORIGIN,
+                curve_point(schedule.rampup, schedule.max_rps as f32),
+                curve_point(schedule.rampup + schedule.rampdown, 0.0),
+            ],
+            color,
+        );
+    }
+
+    // All schedules together, that's what the servers will see
+    let level = editor_level.0.to_level();
+    let points = (0..=(MAX_DURATION / TIME_STEP) as usize).map(|step| {
+        let seconds = step as f32 * TIME_STEP;
+        let rps: f32 = level.schedules.iter().map(|s| s.rps_at(seconds)).sum();
+        curve_point(seconds, rps.min(MAX_RPS as f32))
+    });
+    gizmos.linestrip_2d(points, WHITE_SMOKE);
+
+    // Where requests come in from
+    gizmos.circle_2d(INGRESS_POSITION.truncate(), 30.0, BLUE_200);
+}
+
+fn save_level(editor_level: Res<EditorLevel>) {
+    let text = editor_level.0.to_text();
+    write_storage(EDITOR_STORAGE_KEY, &text);
+    let name: String = editor_level
+        .0
+        .title
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    save_file(&format!("{name}.level.json"), "application/json", &text);
+}
+
+fn test_play(
+    mut commands: Commands,
+    mut focus: ResMut<EditorFocus>,
+    mut editor_level: ResMut<EditorLevel>,
+    mut game_levels: ResMut<GameLevels>,
+    mut evs_reset: EventWriter<ResetCurrentLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    commit_focus(&mut focus, &mut editor_level.0);
+    let level = editor_level.0.to_level();
+    if level.schedules.is_empty() {
+        println!("Can't play a level without any load");
+        return;
+    }
+    if level.available_servers + level.locked_servers.len() == 0 {
+        println!("Can't play a level without any servers");
+        return;
+    }
+    // Played like any other level, right after the campaign
+    let campaign_levels = game_levels.levels.len();
+    game_levels.levels.push(level);
+    // Same slot as the last test play, so it won't count as a level change
+    game_levels.current = campaign_levels;
+    evs_reset.send(ResetCurrentLevel);
+    commands.insert_resource(EditorTestPlay { campaign_levels });
+    println!("Test playing {}", editor_level.0.title);
+    next_state.set(GameState::Planning);
+}
+
+fn leave_editor(
+    mut focus: ResMut<EditorFocus>,
+    mut editor_level: ResMut<EditorLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    commit_focus(&mut focus, &mut editor_level.0);
+    next_state.set(GameState::LevelSelect);
+}
+
+fn spawn_back_to_editor_button(mut commands: Commands, test_play: Option<Res<EditorTestPlay>>) {
+    if test_play.is_none() {
+        return;
+    }
+    commands
+        .spawn((
+            TestPlayUI,
+            ButtonBundle {
+                style: Style {
+                    bottom: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    width: Val::Px(200.0),
+                    height: Val::Px(40.0),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: PURPLE_400.into(),
+                ..default()
+            },
+            BackToEditorButton,
+            PickableBundle::default(),
+            On::<Pointer<Click>>::send_event::<BackToEditorEvent>(),
+            Hoverable(PURPLE_400, PURPLE_500, PURPLE_500),
+            NoDeselect,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Back to Editor",
+                    TextStyle {
+                        font_size: 20.0,
+                        color: WHITE_SMOKE.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+        });
+}
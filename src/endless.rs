@@ -0,0 +1,419 @@
+use crate::prelude::*;
+use bevy::color::palettes::{css::WHITE_SMOKE, tailwind::*};
+
+// Dropped requests allowed over the whole run, across all waves
+pub const ENDLESS_ERROR_BUDGET: usize = 50;
+const ENDLESS_SERVERS: usize = 3;
+const ENDLESS_STARTING_POINTS: usize = 3;
+
+pub struct EndlessPlugin;
+
+impl Plugin for EndlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<OpenEndlessEvent>()
+            .add_event::<WaveClearedEvent>()
+            .add_systems(
+                Update,
+                start_endless
+                    .run_if(on_event::<OpenEndlessEvent>())
+                    .run_if(in_state(GameState::LevelSelect)),
+            )
+            .add_systems(
+                Update,
+                (
+                    restart_endless,
+                    next_wave.run_if(on_event::<WaveClearedEvent>()),
+                    check_error_budget.run_if(in_state(GameState::Running)),
+                    update_endless_hud,
+                )
+                    .run_if(resource_exists::<EndlessRun>),
+            )
+            .add_systems(OnEnter(GameState::Planning), spawn_endless_hud)
+            .add_systems(OnEnter(GameState::Running), spawn_endless_hud)
+            .add_systems(OnExit(GameState::Planning), clear_entity_with::<EndlessHUD>)
+            .add_systems(OnExit(GameState::Running), clear_entity_with::<EndlessHUD>)
+            .add_systems(
+                OnEnter(GameState::Results),
+                spawn_game_over_ui
+                    .after(record_endless_score)
+                    .run_if(resource_exists::<EndlessRun>),
+            )
+            .add_systems(OnExit(GameState::Results), clear_entity_with::<EndlessUI>);
+    }
+}
+
+/// Set during an endless run. Like a level from the editor, the endless
+/// level sits at the end of `GameLevels` until we're back at the level select.
+#[derive(Resource)]
+pub struct EndlessRun {
+    pub campaign_levels: usize,
+    // The wave being planned for or played, starting at 1
+    pub wave: usize,
+    // Where the run ended up in the high-score table, once it's over
+    pub high_score_place: Option<usize>,
+}
+
+impl EndlessRun {
+    pub fn error_budget_left(&self, stats: &GameStats) -> usize {
+        ENDLESS_ERROR_BUDGET.saturating_sub(stats.dropped_requests)
+    }
+}
+
+#[derive(Component)]
+pub struct EndlessHUD;
+
+#[derive(Component)]
+pub struct EndlessUI;
+
+#[derive(Component)]
+struct EndlessWaveText;
+
+#[derive(Component)]
+struct EndlessBudgetText;
+
+#[derive(Event)]
+pub struct OpenEndlessEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for OpenEndlessEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        OpenEndlessEvent
+    }
+}
+
+/// All schedules of a wave are done and every request is gone
+#[derive(Event)]
+pub struct WaveClearedEvent;
+
+/// The load for a wave, it keeps growing. From the third wave on a short
+/// spike comes with it.
+pub fn wave_schedules(wave: usize) -> Vec<ScheduleFile> {
+    let mut schedules = vec![ScheduleFile {
+        rampup: (8.0 + wave as f32).min(20.0),
+        max_rps: 3 + 2 * wave,
+        rampdown: 8.0,
+    }];
+    if wave >= 3 {
+        schedules.push(ScheduleFile {
+            rampup: 2.0,
+            max_rps: wave,
+            rampdown: 2.0,
+        });
+    }
+    schedules
+}
+
+/// Upgrade points handed out after clearing a wave
+pub fn wave_points(wave: usize) -> usize {
+    2 + wave
+}
+
+pub fn endless_level() -> Level {
+    LevelFile {
+        title: "Endless".to_string(),
+        intro_text: format!(
+            "The traffic never stops, and it keeps on growing. Every wave you get through \
+             brings more upgrade points, but once {ENDLESS_ERROR_BUDGET} requests have been \
+             dropped it's over. How long can you keep the website up?"
+        ),
+        success_text: String::new(),
+        failure_texts: vec![],
+        schedules: wave_schedules(1),
+        available_servers: ENDLESS_SERVERS,
+        upgrade_points: ENDLESS_STARTING_POINTS,
+        // Not used, the error budget decides when it's over
+        required_handled_requests: 0.0,
+        required_avg_response_time: 100.0,
+        locked_servers: vec![],
+        max_dns_records: None,
+        slo: None,
+        objectives: vec![],
+        bonus_objectives: vec![],
+    }
+    .to_level()
+}
+
+// Played like any other level, right after the campaign
+fn start_endless(
+    mut commands: Commands,
+    mut game_levels: ResMut<GameLevels>,
+    mut evs_reset: EventWriter<ResetCurrentLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let campaign_levels = game_levels.levels.len();
+    game_levels.levels.push(endless_level());
+    // Same slot as the last endless run, so it won't count as a level change
+    game_levels.current = campaign_levels;
+    evs_reset.send(ResetCurrentLevel);
+    commands.insert_resource(EndlessRun {
+        campaign_levels,
+        wave: 1,
+        high_score_place: None,
+    });
+    println!("Starting endless run");
+    next_state.set(GameState::Planning);
+}
+
+// Take the endless level back out of the campaign
+pub(crate) fn leave_endless(
+    mut commands: Commands,
+    run: Option<Res<EndlessRun>>,
+    mut game_levels: ResMut<GameLevels>,
+) {
+    if let Some(run) = run {
+        game_levels.levels.truncate(run.campaign_levels);
+        game_levels.current = game_levels.current.min(run.campaign_levels - 1);
+        commands.remove_resource::<EndlessRun>();
+    }
+}
+
+// Retrying always starts over from the first wave, reloading would keep the
+// upgrades bought with points from later waves
+fn restart_endless(
+    mut evs_reload: EventReader<ReloadCurrentLevel>,
+    mut evs_reset: ParamSet<(
+        EventReader<ResetCurrentLevel>,
+        EventWriter<ResetCurrentLevel>,
+    )>,
+    mut run: ResMut<EndlessRun>,
+) {
+    let reload = evs_reload.read().count() > 0;
+    let reset = evs_reset.p0().read().count() > 0;
+    if reload {
+        evs_reset.p1().send(ResetCurrentLevel);
+    }
+    if reset || reload {
+        run.wave = 1;
+        run.high_score_place = None;
+    }
+}
+
+fn next_wave(
+    mut commands: Commands,
+    mut run: ResMut<EndlessRun>,
+    game_stats: Res<GameStats>,
+    mut points: ResMut<UpgradePoints>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let bonus = wave_points(run.wave);
+    run.wave += 1;
+    // Made it to the end of the wave, but only just
+    if run.error_budget_left(&game_stats) == 0 {
+        next_state.set(GameState::Results);
+        return;
+    }
+    points.total += bonus;
+    println!(
+        "Wave cleared! Wave {} next, {bonus} points to spend",
+        run.wave
+    );
+    commands.spawn((
+        LoadScenario {
+            schedules: wave_schedules(run.wave)
+                .iter()
+                .map(ScheduleFile::to_schedule)
+                .collect(),
+        },
+        LevelOwned,
+    ));
+    next_state.set(GameState::Planning);
+}
+
+fn check_error_budget(
+    run: Res<EndlessRun>,
+    game_stats: Res<GameStats>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if run.error_budget_left(&game_stats) == 0 {
+        println!("Error budget exhausted in wave {}", run.wave);
+        next_state.set(GameState::Results);
+    }
+}
+
+fn spawn_endless_hud(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    run: Option<Res<EndlessRun>>,
+) {
+    if run.is_none() {
+        return;
+    }
+    spawn_text(
+        EndlessHUD,
+        &font_assets,
+        &mut commands,
+        "Wave",
+        7,
+        EndlessWaveText,
+    );
+    spawn_text(
+        EndlessHUD,
+        &font_assets,
+        &mut commands,
+        "Error Budget",
+        8,
+        EndlessBudgetText,
+    );
+}
+
+fn update_endless_hud(
+    run: Res<EndlessRun>,
+    game_stats: Res<GameStats>,
+    mut q_wave: Query<&mut Text, (With<EndlessWaveText>, Without<EndlessBudgetText>)>,
+    mut q_budget: Query<&mut Text, With<EndlessBudgetText>>,
+) {
+    if let Ok(mut text) = q_wave.get_single_mut() {
+        text.sections[1].value = run.wave.to_string();
+    }
+    if let Ok(mut text) = q_budget.get_single_mut() {
+        text.sections[1].value = format!(
+            "{} of {ENDLESS_ERROR_BUDGET} drops left",
+            run.error_budget_left(&game_stats)
+        );
+    }
+}
+
+fn spawn_game_over_ui(
+    mut commands: Commands,
+    run: Res<EndlessRun>,
+    save: Res<SaveGame>,
+    game_stats: Res<GameStats>,
+) {
+    let waves_survived = run.wave - 1;
+    let mut lines = vec![(
+        format!(
+            "Survived {waves_survived} waves, served {} requests",
+            game_stats.handled_requests
+        ),
+        28.0,
+        WHITE_SMOKE,
+    )];
+    lines.push(("High Scores".to_string(), 24.0, WHITE_SMOKE));
+    for (place, score) in save.endless_scores.iter().enumerate() {
+        let color = if Some(place) == run.high_score_place {
+            YELLOW_400
+        } else {
+            GRAY_300
+        };
+        lines.push((
+            format!(
+                "#{}  {} waves, {} requests",
+                place + 1,
+                score.waves_survived,
+                score.requests_served
+            ),
+            20.0,
+            color,
+        ));
+    }
+
+    commands
+        .spawn((
+            EndlessUI,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(8.0),
+                    ..default()
+                },
+                background_color: Color::srgba(0.0, 0.0, 0.0, 0.7).into(),
+                ..default()
+            },
+            NoDeselect,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Game Over",
+                    TextStyle {
+                        font_size: 64.0,
+                        color: RED_400.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+            for (text, font_size, color) in lines {
+                parent.spawn((
+                    TextBundle::from_section(
+                        text,
+                        TextStyle {
+                            font_size,
+                            color: color.into(),
+                            ..default()
+                        },
+                    ),
+                    Pickable::IGNORE,
+                ));
+            }
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        margin: UiRect::top(Val::Px(20.0)),
+                        column_gap: Val::Px(20.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_game_over_button::<ResetCurrentLevel, _>(
+                        parent,
+                        "Play Again",
+                        EndlessPlayAgainButton,
+                        (GREEN_400, GREEN_500),
+                    );
+                    spawn_game_over_button::<ShowLevelSelect, _>(
+                        parent,
+                        "Levels",
+                        EndlessLevelsButton,
+                        (BLUE_400, BLUE_500),
+                    );
+                });
+        });
+}
+
+fn spawn_game_over_button<E, C>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    component: C,
+    (color, hover_color): (Srgba, Srgba),
+) where
+    E: Event + From<ListenerInput<Pointer<Click>>>,
+    C: Bundle,
+{
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(200.0),
+                    height: Val::Px(50.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: color.into(),
+                ..default()
+            },
+            component,
+            PickableBundle::default(),
+            On::<Pointer<Click>>::send_event::<E>(),
+            Hoverable(color, hover_color, hover_color),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    label,
+                    TextStyle {
+                        font_size: 22.0,
+                        color: WHITE_SMOKE.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+        });
+}
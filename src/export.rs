@@ -0,0 +1,310 @@
+use crate::prelude::*;
+use serde::Serialize;
+
+// Seconds of run time between time-series samples
+const SAMPLE_INTERVAL: f32 = 0.5;
+
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MetricsSamples>()
+            .add_event::<ExportRunEvent>()
+            .add_systems(OnEnter(GameState::Running), reset_samples)
+            .add_systems(
+                FixedUpdate,
+                sample_metrics.run_if(in_state(GameState::Running)),
+            )
+            .add_systems(
+                Update,
+                export_run
+                    .run_if(on_event::<ExportRunEvent>())
+                    .run_if(in_state(GameState::Results)),
+            );
+    }
+}
+
+/// How the run was going at one point in time. Times are in simulation
+/// seconds like `Request::age`, the game shows them x10 as ms.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct MetricsSample {
+    pub time: f32,
+    pub handled: usize,
+    pub dropped: usize,
+    // Requests somewhere between the Internet and being done
+    pub in_flight: usize,
+    // Waiting in a server's queue, not being worked on
+    pub queued: usize,
+    pub busy_servers: usize,
+    pub avg_response_time: f32,
+}
+
+/// Samples taken every `SAMPLE_INTERVAL` during the latest run
+#[derive(Resource, Default)]
+pub struct MetricsSamples(pub Vec<MetricsSample>);
+
+/// One server's numbers at the end of a run
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ServerExport {
+    // Index like in the `Blueprint`, and as #1, #2.. in the game
+    pub slot: usize,
+    pub mode: ServerMode,
+    pub processing_power: usize,
+    pub queue_size: usize,
+    pub arrived: usize,
+    pub handled: usize,
+    pub dropped: usize,
+    pub forwarded: usize,
+    pub busy_time: f32,
+    pub utilisation: f32,
+    pub max_queue_depth: usize,
+}
+
+/// Everything about a finished run that's worth analysing elsewhere
+#[derive(Serialize)]
+pub struct RunExport<'a> {
+    pub level: usize,
+    pub title: &'a str,
+    pub duration: f32,
+    pub stats: &'a GameStats,
+    pub results: &'a LevelResults,
+    pub servers: Vec<ServerExport>,
+    pub samples: &'a [MetricsSample],
+}
+
+impl RunExport<'_> {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("run exports always serialize")
+    }
+
+    pub fn summary_csv(&self) -> String {
+        let mut csv = String::from(
+            "level,title,duration,handled,dropped,handled_percentage,avg_response_time,\
+             required_percentage,required_avg_response_time,passed\n",
+        );
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{}\n",
+            self.level,
+            csv_field(self.title),
+            self.duration,
+            self.stats.handled_requests,
+            self.stats.dropped_requests,
+            self.results.current_percentage,
+            self.stats.avg_response_time,
+            self.results.pass_percentage,
+            self.results.pass_avg_response_time,
+            self.results.passed,
+        ));
+        csv
+    }
+
+    pub fn servers_csv(&self) -> String {
+        let mut csv = String::from(
+            "slot,mode,processing_power,queue_size,arrived,handled,dropped,forwarded,busy_time,\
+             utilisation,max_queue_depth\n",
+        );
+        for server in &self.servers {
+            csv.push_str(&format!(
+                "{},{:?},{},{},{},{},{},{},{},{},{}\n",
+                server.slot,
+                server.mode,
+                server.processing_power,
+                server.queue_size,
+                server.arrived,
+                server.handled,
+                server.dropped,
+                server.forwarded,
+                server.busy_time,
+                server.utilisation,
+                server.max_queue_depth,
+            ));
+        }
+        csv
+    }
+
+    pub fn samples_csv(&self) -> String {
+        let mut csv =
+            String::from("time,handled,dropped,in_flight,queued,busy_servers,avg_response_time\n");
+        for sample in self.samples {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                sample.time,
+                sample.handled,
+                sample.dropped,
+                sample.in_flight,
+                sample.queued,
+                sample.busy_servers,
+                sample.avg_response_time,
+            ));
+        }
+        csv
+    }
+
+    pub fn response_times_csv(&self) -> String {
+        let mut csv = String::from("request,response_time\n");
+        for (index, time) in self.stats.response_times.iter().enumerate() {
+            csv.push_str(&format!("{index},{time}\n"));
+        }
+        csv
+    }
+}
+
+// Level titles can have commas in them
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[derive(Event)]
+pub struct ExportRunEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for ExportRunEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        ExportRunEvent
+    }
+}
+
+fn reset_samples(mut samples: ResMut<MetricsSamples>) {
+    samples.0.clear();
+}
+
+fn sample_metrics(
+    mut samples: ResMut<MetricsSamples>,
+    clock: Res<RunClock>,
+    fixed_time: Res<Time<Fixed>>,
+    stats: Res<GameStats>,
+    q_requests: Query<(), (With<Request>, Without<DroppedRequest>, Without<ToRemove>)>,
+    q_servers: Query<&Server>,
+) {
+    let time = run_elapsed(&clock, &fixed_time);
+    let due = samples
+        .0
+        .last()
+        .map_or(true, |last| time - last.time >= SAMPLE_INTERVAL);
+    if !due {
+        return;
+    }
+    samples.0.push(MetricsSample {
+        time,
+        handled: stats.handled_requests,
+        dropped: stats.dropped_requests,
+        in_flight: q_requests.iter().count(),
+        queued: q_servers.iter().map(|s| s.queued_requests.len()).sum(),
+        busy_servers: q_servers
+            .iter()
+            .filter(|s| s.current_request.is_some())
+            .count(),
+        avg_response_time: stats.avg_response_time,
+    });
+}
+
+fn export_run(
+    stats: Res<GameStats>,
+    results: Res<LevelResults>,
+    samples: Res<MetricsSamples>,
+    game_levels: Res<GameLevels>,
+    clock: Res<RunClock>,
+    fixed_time: Res<Time<Fixed>>,
+    q_servers: Query<(Entity, &Server, &ServerStats, Option<&BlueprintSlot>)>,
+) {
+    let duration = run_elapsed(&clock, &fixed_time);
+    let mut servers: Vec<(Entity, &Server, &ServerStats, Option<&BlueprintSlot>)> =
+        q_servers.iter().collect();
+    servers.sort_by_key(|(e, _, _, slot)| (slot.map(|s| s.0), *e));
+
+    let export = RunExport {
+        level: game_levels.current,
+        title: &game_levels.active_level().title,
+        duration,
+        stats: &stats,
+        results: &results,
+        servers: servers
+            .iter()
+            .enumerate()
+            .map(|(index, (_, server, server_stats, slot))| ServerExport {
+                slot: slot.map_or(index, |s| s.0),
+                mode: server.mode,
+                processing_power: server.processing_power,
+                queue_size: server.queue_size,
+                arrived: server_stats.arrived,
+                handled: server_stats.handled,
+                dropped: server_stats.dropped,
+                forwarded: server_stats.forwarded,
+                busy_time: server_stats.busy_time,
+                utilisation: server_stats.utilisation(duration),
+                max_queue_depth: server_stats.max_queue_depth,
+            })
+            .collect(),
+        samples: &samples.0,
+    };
+
+    let name = format!("run-level-{}", export.level + 1);
+    save_file(
+        &format!("{name}.json"),
+        "application/json",
+        &export.to_json(),
+    );
+    save_file(
+        &format!("{name}-summary.csv"),
+        "text/csv",
+        &export.summary_csv(),
+    );
+    save_file(
+        &format!("{name}-servers.csv"),
+        "text/csv",
+        &export.servers_csv(),
+    );
+    save_file(
+        &format!("{name}-samples.csv"),
+        "text/csv",
+        &export.samples_csv(),
+    );
+    save_file(
+        &format!("{name}-response-times.csv"),
+        "text/csv",
+        &export.response_times_csv(),
+    );
+}
+
+/// Writes a file into the exports folder next to the save game
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(name: &str, _mime: &str, contents: &str) {
+    let dir = data_dir().join("exports");
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        println!("Couldn't create {}: {err}", dir.display());
+        return;
+    }
+    let path = dir.join(name);
+    match std::fs::write(&path, contents) {
+        Ok(_) => println!("Exported {}", path.display()),
+        Err(err) => println!("Couldn't write {}: {err}", path.display()),
+    }
+}
+
+/// Has the browser download the file
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(name: &str, mime: &str, contents: &str) {
+    use wasm_bindgen::JsCast;
+
+    let download = || -> Option<()> {
+        let document = web_sys::window()?.document()?;
+        let parts = js_sys::Array::of1(&contents.into());
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type(mime);
+        let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options).ok()?;
+        let url = web_sys::Url::create_object_url_with_blob(&blob).ok()?;
+        let anchor: web_sys::HtmlAnchorElement =
+            document.create_element("a").ok()?.dyn_into().ok()?;
+        anchor.set_href(&url);
+        anchor.set_download(name);
+        anchor.click();
+        web_sys::Url::revoke_object_url(&url).ok()
+    };
+    match download() {
+        Some(_) => println!("Exported {name}"),
+        None => println!("Couldn't download {name}"),
+    }
+}
@@ -35,27 +35,53 @@ impl Plugin for FullGamePlugin {
             UIPlugin,
             LevelsPlugin,
             StatesPlugin,
-            ResultsPlugin,
+            SimulationPlugin,
             AssetsPlugin,
             SplashPlugin,
             LevelSelectPlugin,
         ))
-        .insert_resource(GameStats::default())
+        .add_plugins((
+            IngressPlugin,
+            SavePlugin,
+            BlueprintPlugin,
+            ReplayPlugin,
+            SimSpeedPlugin,
+            OnCallPlugin,
+            HistoryPlugin,
+            TopologyPlugin,
+            CapacityPlugin,
+            ServerStatsPlugin,
+            RequestTracePlugin,
+            ExportPlugin,
+            EditorPlugin,
+            SandboxPlugin,
+            EndlessPlugin,
+        ))
+        .add_plugins(SloPlugin)
         .add_systems(Startup, startup)
         .add_systems(
             Update,
             (
-                draw_gizmos
-                    .run_if(in_state(GameState::Planning).or_else(in_state(GameState::Running))),
+                draw_gizmos.run_if(
+                    in_state(GameState::Planning)
+                        .or_else(in_state(Simulating))
+                        .or_else(in_state(GameState::Editor)),
+                ),
                 draw_selected,
             ),
         );
+
+        #[cfg(all(feature = "prometheus", not(target_arch = "wasm32")))]
+        app.add_plugins(MetricsEndpointPlugin);
+        #[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+        app.add_plugins(ScriptingPlugin);
     }
 }
 
 fn draw_gizmos(
     mut gizmos: Gizmos,
     q_servers: Query<(Entity, &Server)>,
+    q_ingress: Query<(Entity, &Ingress)>,
     q_transform: Query<&Transform>,
 ) {
     gizmos
@@ -82,6 +108,20 @@ fn draw_gizmos(
             );
         }
     }
+
+    // Draw the DNS records from the ingress to the front servers
+    for (e_ingress, ingress) in q_ingress.iter() {
+        let t_ingress = q_transform.get(e_ingress).unwrap();
+        for output in &ingress.outputs {
+            if let Ok(t_output) = q_transform.get(*output) {
+                gizmos.line_2d(
+                    t_ingress.translation.truncate(),
+                    t_output.translation.truncate(),
+                    BLUE_200,
+                );
+            }
+        }
+    }
 }
 
 fn startup(mut commands: Commands) {
@@ -1,9 +1,13 @@
 use crate::prelude::*;
+use serde::Serialize;
 
-#[derive(Resource, Debug, Reflect)]
+#[derive(Resource, Debug, Clone, Reflect, Serialize)]
 pub struct GameStats {
     // count of total dropped requests,
     pub dropped_requests: usize,
+    // how many of those never made it past the Internet, with backpressure
+    // that's where requests get turned away
+    pub dropped_at_edge: usize,
     // count of how many requests we successfully processed
     pub handled_requests: usize,
     // how fast we handled each request
@@ -23,12 +27,24 @@ impl GameStats {
             .unwrap();
         self.avg_response_time = sum / total;
     }
+
+    /// Response time that `p` (0.0 <> 1.0) of the handled requests stayed under
+    pub fn response_time_percentile(&self, p: f32) -> f32 {
+        if self.response_times.is_empty() {
+            return 0.0;
+        }
+        let mut times = self.response_times.clone();
+        times.sort_by(|a, b| a.total_cmp(b));
+        let index = ((times.len() as f32 * p).ceil() as usize).clamp(1, times.len()) - 1;
+        times[index]
+    }
 }
 
 impl Default for GameStats {
     fn default() -> Self {
         Self {
             dropped_requests: 0,
+            dropped_at_edge: 0,
             handled_requests: 0,
             response_times: vec![],
             avg_response_time: 0.0,
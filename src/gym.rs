@@ -0,0 +1,368 @@
+use crate::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+// Observations always describe this many servers, padded with zeros, so
+// agents get the same size vector on every level
+pub const MAX_SERVERS: usize = 8;
+// Numbers per server in the observation
+const SERVER_FEATURES: usize = 7;
+// Planning steps before the run starts on its own
+pub const MAX_PLANNING_STEPS: usize = 200;
+// Reward for trying something that isn't possible, like spending points we
+// don't have
+const INVALID_ACTION_REWARD: f32 = -0.01;
+// On top of the run's score, for passing the level
+const PASS_REWARD: f32 = 1.0;
+
+/// A planning move, the same things a player can do before a run.
+/// Servers are indexes into the `Blueprint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    UpgradeCpu(usize),
+    UpgradeQueue(usize),
+    SwitchMode(usize),
+    // Adds `to` as an output of `from` when it isn't one, removes it when it
+    // is. Like in the game, giving a server outputs makes it a proxy.
+    ToggleOutput { from: usize, to: usize },
+    // Adds or removes a server from the Internet's DNS records
+    ToggleIngress(usize),
+    ResetUpgrades(usize),
+    // Done planning, play the level
+    Start,
+}
+
+/// What `Env::step` hands back
+pub struct Step {
+    pub observation: Vec<f32>,
+    pub reward: f32,
+    pub done: bool,
+    // Only set on the step that played the run
+    pub outcome: Option<SimOutcome>,
+}
+
+/// Gym-style environment for the planning part of the game. An episode is
+/// a level: a number of planning actions, then a headless run of the level
+/// whose result is the reward.
+#[derive(Clone)]
+pub struct Env {
+    pub levels: Vec<Level>,
+    pub level: usize,
+    pub seed: u64,
+    pub blueprint: Blueprint,
+    pub steps: usize,
+    pub done: bool,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Self::new(GameLevels::default().levels)
+    }
+}
+
+impl Env {
+    pub fn new(levels: Vec<Level>) -> Self {
+        let blueprint = default_layout(0, &levels[0]);
+        Self {
+            levels,
+            level: 0,
+            seed: 0,
+            blueprint,
+            steps: 0,
+            done: false,
+        }
+    }
+
+    pub fn active_level(&self) -> &Level {
+        &self.levels[self.level]
+    }
+
+    /// Starts a new episode, the seed decides the requests of the run
+    pub fn reset(&mut self, level: usize, seed: u64) -> Vec<f32> {
+        self.level = level;
+        self.seed = seed;
+        self.blueprint = default_layout(level, &self.levels[level]);
+        self.steps = 0;
+        self.done = false;
+        self.observe()
+    }
+
+    pub fn points_left(&self) -> usize {
+        self.active_level()
+            .upgrade_points
+            .saturating_sub(self.blueprint.cost())
+    }
+
+    /// Every action for the current level, in a fixed order, so agents can
+    /// use the index as a discrete action
+    pub fn actions(&self) -> Vec<Action> {
+        let count = self.blueprint.servers.len();
+        let mut actions = vec![Action::Start];
+        for server in 0..count {
+            actions.push(Action::UpgradeCpu(server));
+            actions.push(Action::UpgradeQueue(server));
+            actions.push(Action::SwitchMode(server));
+            actions.push(Action::ToggleIngress(server));
+            actions.push(Action::ResetUpgrades(server));
+            for to in 0..count {
+                if to != server {
+                    actions.push(Action::ToggleOutput { from: server, to });
+                }
+            }
+        }
+        actions
+    }
+
+    /// Actions that would change something right now
+    pub fn valid_actions(&self) -> Vec<Action> {
+        self.actions()
+            .into_iter()
+            .filter(|action| self.apply(*action).is_some())
+            .collect()
+    }
+
+    // The blueprint after `action`, None if it's not possible
+    fn apply(&self, action: Action) -> Option<Blueprint> {
+        let mut blueprint = self.blueprint.clone();
+        let points_left = self.points_left();
+        match action {
+            Action::UpgradeCpu(index) => {
+                let server = blueprint.servers.get_mut(index)?;
+                if server.processing_power > points_left {
+                    return None;
+                }
+                server.processing_power += 1;
+            }
+            Action::UpgradeQueue(index) => {
+                let server = blueprint.servers.get_mut(index)?;
+                if points_left < 1 {
+                    return None;
+                }
+                server.queue_size += 1;
+            }
+            Action::SwitchMode(index) => {
+                let server = blueprint.servers.get_mut(index)?;
+                server.mode = match server.mode {
+                    ServerMode::Process => ServerMode::Proxy,
+                    ServerMode::Proxy => {
+                        server.outputs.clear();
+                        ServerMode::Process
+                    }
+                };
+            }
+            Action::ToggleOutput { from, to } => {
+                if from == to || to >= blueprint.servers.len() {
+                    return None;
+                }
+                let server = blueprint.servers.get_mut(from)?;
+                match server.outputs.iter().position(|output| *output == to) {
+                    Some(position) => {
+                        server.outputs.remove(position);
+                    }
+                    None => {
+                        server.mode = ServerMode::Proxy;
+                        server.outputs.push(to);
+                    }
+                }
+            }
+            Action::ToggleIngress(index) => {
+                if index >= blueprint.servers.len() {
+                    return None;
+                }
+                match blueprint.ingress.iter().position(|i| *i == index) {
+                    Some(position) => {
+                        blueprint.ingress.remove(position);
+                    }
+                    None => blueprint.ingress.push(index),
+                }
+            }
+            Action::ResetUpgrades(index) => {
+                let server = blueprint.servers.get_mut(index)?;
+                if server.processing_power == 1 && server.queue_size == 0 {
+                    return None;
+                }
+                server.processing_power = 1;
+                server.queue_size = 0;
+            }
+            Action::Start => {}
+        }
+        let level = self.active_level();
+        if !respects_locked_servers(level, &blueprint)
+            || !level.allows_dns_records(blueprint.ingress.len())
+        {
+            return None;
+        }
+        Some(blueprint)
+    }
+
+    /// Layout and budget as numbers, `MAX_SERVERS` servers worth plus a few
+    /// about the level. Counts are left as they are, not normalised.
+    pub fn observe(&self) -> Vec<f32> {
+        let level = self.active_level();
+        let peak_rps = level.peak_rps();
+        let plan = plan_capacity(&self.blueprint, peak_rps);
+        let mut observation = vec![
+            self.level as f32,
+            level.upgrade_points as f32,
+            self.points_left() as f32,
+            peak_rps,
+            plan.max_sustainable_rps,
+            level.required_handled_requests,
+            level.required_avg_response_time(),
+        ];
+        for index in 0..MAX_SERVERS {
+            match (self.blueprint.servers.get(index), plan.servers.get(index)) {
+                (Some(server), Some(capacity)) => observation.extend([
+                    1.0,
+                    (server.mode == ServerMode::Proxy) as u8 as f32,
+                    server.processing_power as f32,
+                    server.queue_size as f32,
+                    server.outputs.len() as f32,
+                    self.blueprint.ingress.contains(&index) as u8 as f32,
+                    capacity.utilisation,
+                ]),
+                _ => observation.extend([0.0; SERVER_FEATURES]),
+            }
+        }
+        observation
+    }
+
+    pub fn observation_size() -> usize {
+        7 + MAX_SERVERS * SERVER_FEATURES
+    }
+
+    pub fn step(&mut self, action: Action) -> Step {
+        if self.done {
+            return Step {
+                observation: self.observe(),
+                reward: 0.0,
+                done: true,
+                outcome: None,
+            };
+        }
+        self.steps += 1;
+        if action == Action::Start || self.steps >= MAX_PLANNING_STEPS {
+            return self.play();
+        }
+        let reward = match self.apply(action) {
+            Some(blueprint) => {
+                self.blueprint = blueprint;
+                0.0
+            }
+            None => INVALID_ACTION_REWARD,
+        };
+        Step {
+            observation: self.observe(),
+            reward,
+            done: false,
+            outcome: None,
+        }
+    }
+
+    // Plays the level with what's been planned, ends the episode
+    fn play(&mut self) -> Step {
+        self.done = true;
+        let outcome = simulate(self.active_level(), &self.blueprint, self.seed);
+        Step {
+            observation: self.observe(),
+            reward: score(self.active_level(), &outcome),
+            done: true,
+            outcome: Some(outcome),
+        }
+    }
+}
+
+/// Reward for a finished run: the share of requests handled, minus how far
+/// over the latency requirement it went, plus a bonus for passing
+pub fn score(level: &Level, outcome: &SimOutcome) -> f32 {
+    let handled = outcome.results.current_percentage;
+    if handled.is_nan() {
+        return 0.0;
+    }
+    let required = level.required_avg_response_time();
+    let too_slow = ((outcome.stats.avg_response_time - required) / required).clamp(0.0, 1.0);
+    let bonus = if outcome.results.passed {
+        PASS_REWARD
+    } else {
+        0.0
+    };
+    handled - too_slow + bonus
+}
+
+/// Something that picks planning actions
+pub trait Agent {
+    fn name(&self) -> &str;
+    fn act(&mut self, env: &Env) -> Action;
+}
+
+/// Random valid actions, starting the run with a small chance every step
+pub struct RandomAgent {
+    rng: StdRng,
+    start_chance: f64,
+}
+
+impl RandomAgent {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            start_chance: 0.05,
+        }
+    }
+}
+
+impl Agent for RandomAgent {
+    fn name(&self) -> &str {
+        "random"
+    }
+
+    fn act(&mut self, env: &Env) -> Action {
+        if self.rng.gen_bool(self.start_chance) {
+            return Action::Start;
+        }
+        *env.valid_actions()
+            .choose(&mut self.rng)
+            .unwrap_or(&Action::Start)
+    }
+}
+
+/// Tries every valid action, plays the level after each, and takes the one
+/// that scores best. Starts once nothing improves on what it has.
+pub struct GreedyAgent;
+
+impl Agent for GreedyAgent {
+    fn name(&self) -> &str {
+        "greedy"
+    }
+
+    fn act(&mut self, env: &Env) -> Action {
+        let evaluate = |action: Action| {
+            let mut env = env.clone();
+            if action != Action::Start {
+                env.step(action);
+            }
+            env.step(Action::Start).reward
+        };
+        let mut best = (Action::Start, evaluate(Action::Start));
+        for action in env.valid_actions() {
+            if action == Action::Start {
+                continue;
+            }
+            let reward = evaluate(action);
+            if reward > best.1 {
+                best = (action, reward);
+            }
+        }
+        best.0
+    }
+}
+
+/// Plays one episode with `agent`, returns the final step
+pub fn run_episode(env: &mut Env, agent: &mut dyn Agent, level: usize, seed: u64) -> Step {
+    env.reset(level, seed);
+    loop {
+        let action = agent.act(env);
+        let step = env.step(action);
+        if step.done {
+            return step;
+        }
+    }
+}
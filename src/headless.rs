@@ -0,0 +1,172 @@
+use crate::prelude::*;
+use bevy::ecs::system::RunSystemOnce;
+use bevy::time::TimeUpdateStrategy;
+
+// Gives up on runs that never finish, after the load is over
+const MAX_OVERTIME: f32 = 120.0;
+
+/// The servers a level starts with, lined up the same way `SetUpLevel`
+/// spawns them, followed by the level's locked servers
+pub fn default_layout(level_index: usize, level: &Level) -> Blueprint {
+    let free = (0..level.available_servers).map(|i| BlueprintServer {
+        x: new_server_position(i).x,
+        y: new_server_position(i).y,
+        mode: ServerMode::Process,
+        processing_power: 1,
+        queue_size: 0,
+        discipline: QueueDiscipline::Fifo,
+        outputs: vec![],
+    });
+    let locked = level.locked_servers.iter().map(|locked| BlueprintServer {
+        x: locked.x,
+        y: locked.y,
+        mode: locked.mode,
+        processing_power: 1,
+        queue_size: 0,
+        discipline: QueueDiscipline::Fifo,
+        outputs: vec![],
+    });
+    Blueprint {
+        level: level_index,
+        servers: free.chain(locked).collect(),
+        ingress: vec![],
+        ingress_algorithm: LoadBalancingAlgorithm::RoundRobin,
+        backpressure: false,
+    }
+}
+
+/// How a headless run went, same numbers the results screen uses
+pub struct SimOutcome {
+    pub stats: GameStats,
+    pub results: LevelResults,
+    // Simulated seconds from start until the last request was gone
+    pub duration: f32,
+    // Ran out of time rather than finishing, requests still in flight count
+    // as dropped
+    pub timed_out: bool,
+}
+
+/// Plays a level against a blueprint on the game's own systems (everything in
+/// `SimulationPlugin`), in an `App` without a window. Every `step` is one
+/// fixed timestep, and the seed goes into `SimRng`, so the same seed gets
+/// the same run.
+pub struct HeadlessRun {
+    app: App,
+    // When the last schedule sends its last request
+    load_duration: f32,
+}
+
+impl HeadlessRun {
+    pub fn new(level: &Level, blueprint: &Blueprint, seed: u64) -> Self {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            bevy::state::app::StatesPlugin,
+            StatesPlugin,
+            SimulationPlugin,
+        ))
+        // Each update moves time on by exactly one fixed timestep
+        .insert_resource(TimeUpdateStrategy::ManualDuration(
+            Time::<Fixed>::default().timestep(),
+        ))
+        .init_resource::<ImageAssets>()
+        .insert_resource(SimRng::from_seed(seed))
+        .insert_resource(GameLevels {
+            current: 0,
+            levels: vec![level.clone()],
+        });
+        app.finish();
+        app.cleanup();
+
+        // Same as picking the level, planning it like the blueprint and
+        // pressing start
+        SetUpLevel.apply(app.world_mut());
+        ApplyBlueprint(blueprint.clone()).apply(app.world_mut());
+        app.world_mut()
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Planning);
+        app.update();
+        app.world_mut().send_event(StartLoadScenarios);
+        app.update();
+
+        Self {
+            app,
+            load_duration: level
+                .schedules
+                .iter()
+                .map(|schedule| schedule.duration())
+                .fold(0.0, f32::max),
+        }
+    }
+
+    fn state(&self) -> GameState {
+        *self.app.world().resource::<State<GameState>>().get()
+    }
+
+    pub fn elapsed(&self) -> f32 {
+        let world = self.app.world();
+        run_elapsed(
+            world.resource::<RunClock>(),
+            world.resource::<Time<Fixed>>(),
+        )
+    }
+
+    pub fn stats(&self) -> &GameStats {
+        self.app.world().resource::<GameStats>()
+    }
+
+    /// Requests that are neither handled nor dropped yet
+    pub fn in_flight(&mut self) -> usize {
+        self.app
+            .world_mut()
+            .query_filtered::<(), (With<Request>, Without<DroppedRequest>)>()
+            .iter(self.app.world())
+            .count()
+    }
+
+    /// Whether the run is over, all load sent and every request gone
+    pub fn is_finished(&self) -> bool {
+        self.state() == GameState::Results || self.elapsed() - self.load_duration > MAX_OVERTIME
+    }
+
+    /// One fixed timestep
+    pub fn step(&mut self) {
+        self.app.update();
+    }
+
+    pub fn run(mut self) -> SimOutcome {
+        while !self.is_finished() {
+            self.step();
+        }
+        self.finish()
+    }
+
+    fn finish(mut self) -> SimOutcome {
+        let duration = self.elapsed();
+        let timed_out = self.state() != GameState::Results;
+        if timed_out {
+            // Whatever's still around counts as dropped, then the same
+            // results the game would've given
+            let in_flight = self.in_flight();
+            let world = self.app.world_mut();
+            world.resource_mut::<GameStats>().dropped_requests += in_flight;
+            let mut slo = world.resource_mut::<SloTracker>();
+            for _ in 0..in_flight {
+                slo.record(duration, None);
+            }
+            world.run_system_once(calculate_pass_or_not);
+        }
+        let world = self.app.world_mut();
+        SimOutcome {
+            stats: world.remove_resource::<GameStats>().unwrap(),
+            results: world.remove_resource::<LevelResults>().unwrap(),
+            duration,
+            timed_out,
+        }
+    }
+}
+
+/// Plays a whole run of `level` with the servers set up like `blueprint`
+pub fn simulate(level: &Level, blueprint: &Blueprint, seed: u64) -> SimOutcome {
+    HeadlessRun::new(level, blueprint, seed).run()
+}
@@ -0,0 +1,167 @@
+use crate::prelude::*;
+
+// Plenty for a planning session, and keeps memory in check
+const MAX_HISTORY: usize = 100;
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlanningHistory>()
+            .add_event::<UndoEvent>()
+            .add_event::<RedoEvent>()
+            .add_systems(
+                Update,
+                (
+                    history_shortcuts,
+                    undo.run_if(on_event::<UndoEvent>()),
+                    redo.run_if(on_event::<RedoEvent>()),
+                    record_history,
+                )
+                    .chain()
+                    .run_if(in_state(EditMode::Upgrade)),
+            );
+    }
+}
+
+/// Undo/redo for planning. Rather than tracking every kind of edit, we
+/// snapshot the whole layout as a `Blueprint` whenever it settles into
+/// something new, and undoing puts the previous snapshot back. Points come
+/// back with it, since `ApplyBlueprint` recalculates what's been spent.
+#[derive(Resource, Default)]
+pub struct PlanningHistory {
+    undo: Vec<Blueprint>,
+    redo: Vec<Blueprint>,
+    // What the layout looked like last time we checked
+    current: Option<Blueprint>,
+    // Snapshots only make sense for the servers they were taken from, so
+    // when these change (new level, reset) we start over
+    servers: Vec<Entity>,
+}
+
+impl PlanningHistory {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[derive(Event)]
+pub struct UndoEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for UndoEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        UndoEvent
+    }
+}
+
+#[derive(Event)]
+pub struct RedoEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for RedoEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        RedoEvent
+    }
+}
+
+// Ctrl+Z to undo, Ctrl+Shift+Z or Ctrl+Y to redo (or Cmd on mac)
+fn history_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut evs_undo: EventWriter<UndoEvent>,
+    mut evs_redo: EventWriter<RedoEvent>,
+) {
+    let modifier = keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    if !modifier {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keys.just_pressed(KeyCode::KeyZ) {
+        if shift {
+            evs_redo.send(RedoEvent);
+        } else {
+            evs_undo.send(UndoEvent);
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyY) {
+        evs_redo.send(RedoEvent);
+    }
+}
+
+fn undo(mut commands: Commands, mut history: ResMut<PlanningHistory>) {
+    let Some(previous) = history.undo.pop() else {
+        println!("Nothing to undo");
+        return;
+    };
+    if let Some(current) = history.current.replace(previous.clone()) {
+        history.redo.push(current);
+    }
+    commands.add(ApplyBlueprint(previous));
+}
+
+fn redo(mut commands: Commands, mut history: ResMut<PlanningHistory>) {
+    let Some(next) = history.redo.pop() else {
+        println!("Nothing to redo");
+        return;
+    };
+    if let Some(current) = history.current.replace(next.clone()) {
+        history.undo.push(current);
+    }
+    commands.add(ApplyBlueprint(next));
+}
+
+fn record_history(
+    mut history: ResMut<PlanningHistory>,
+    q_servers: Query<(
+        Entity,
+        &Transform,
+        &Server,
+        Option<&Animator<Transform>>,
+        Has<IsBeingDragged>,
+    )>,
+    q_slot: SlotOrderQuery,
+    q_ingress: Query<&Ingress>,
+    game_levels: Res<GameLevels>,
+) {
+    // Wait for drags and snapping to finish, otherwise every frame of the
+    // movement would end up as its own step
+    let settled = q_servers.iter().all(|(_, _, _, animator, dragged)| {
+        !dragged && animator.map_or(true, |a| a.tweenable().progress() >= 1.0)
+    });
+    if !settled {
+        return;
+    }
+
+    let mut servers: Vec<(Entity, Vec3, &Server)> = q_servers
+        .iter()
+        .map(|(e, t, server, _, _)| (e, t.translation, server))
+        .collect();
+    servers.sort_by_key(|(e, _, _)| slot_order(&q_slot, *e));
+    let entities: Vec<Entity> = servers.iter().map(|(e, _, _)| *e).collect();
+    let snapshot = capture_blueprint(game_levels.current, &servers, q_ingress.get_single().ok());
+
+    if entities != history.servers {
+        *history = PlanningHistory {
+            current: Some(snapshot),
+            servers: entities,
+            ..default()
+        };
+        return;
+    }
+    if history.current.as_ref() == Some(&snapshot) {
+        return;
+    }
+    if let Some(previous) = history.current.replace(snapshot) {
+        history.undo.push(previous);
+        if history.undo.len() > MAX_HISTORY {
+            history.undo.remove(0);
+        }
+    }
+    history.redo.clear();
+}
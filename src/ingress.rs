@@ -0,0 +1,221 @@
+use crate::prelude::*;
+
+// Where the "Internet" sits, new requests enter the topology from here
+pub const INGRESS_POSITION: Vec3 = Vec3::new(0.0, 300.0, 5.0);
+
+pub struct IngressPlugin;
+
+impl Plugin for IngressPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ToggleBackpressureEvent>()
+            .add_systems(
+                Update,
+                (handle_change_ingress_algorithm, draw_ingress_ui)
+                    .run_if(in_state(GameState::Planning).or_else(in_state(Simulating))),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_backpressure.run_if(on_event::<ToggleBackpressureEvent>()),
+                    update_backpressure_button,
+                )
+                    .run_if(in_state(GameState::Planning)),
+            );
+    }
+}
+
+#[derive(Component)]
+struct IngressInfoText;
+
+/// The "Internet / DNS" node. Every new request asks it where to go, and it
+/// answers with one of the front servers the player wired it to.
+#[derive(Component, Debug)]
+pub struct Ingress {
+    pub algorithm: LoadBalancingAlgorithm,
+    // When on, full servers tell whoever sends them requests to hold off:
+    // proxies keep requests in their own queue and the Internet drops them
+    // at the edge, instead of servers dropping them once they arrive
+    pub backpressure: bool,
+    // Which servers the DNS records currently point at
+    pub outputs: Vec<Entity>,
+    // Which index we're currently on in our round-robin
+    next_output_index: usize,
+    // Running weights for the smooth weighted round-robin, one per output
+    current_weights: Vec<isize>,
+}
+
+impl Default for Ingress {
+    fn default() -> Self {
+        Self {
+            algorithm: LoadBalancingAlgorithm::RoundRobin,
+            backpressure: false,
+            outputs: vec![],
+            next_output_index: 0,
+            current_weights: vec![],
+        }
+    }
+}
+
+impl Ingress {
+    pub fn set_outputs(&mut self, outputs: Vec<Entity>) {
+        self.outputs = outputs;
+        self.next_output_index = 0;
+        self.current_weights = vec![0; self.outputs.len()];
+    }
+
+    /// Picks the server the next request should be sent to. `weights` has one
+    /// entry per output and is only used by `LoadBalancingAlgorithm::Weighted`
+    pub fn next_output(&mut self, weights: &[usize]) -> Option<Entity> {
+        if self.outputs.is_empty() {
+            return None;
+        }
+        match self.algorithm {
+            LoadBalancingAlgorithm::RoundRobin => {
+                if self.next_output_index >= self.outputs.len() {
+                    self.next_output_index = 0;
+                }
+                let ret = self.outputs[self.next_output_index];
+                self.next_output_index += 1;
+                Some(ret)
+            }
+            LoadBalancingAlgorithm::Weighted => {
+                // Same "smooth" weighted round-robin as nginx, so a 3:1 split
+                // is spread out as a-a-b-a rather than a-a-a-b
+                if self.current_weights.len() != self.outputs.len() {
+                    self.current_weights = vec![0; self.outputs.len()];
+                }
+                let total: isize = weights.iter().map(|w| *w as isize).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut best = 0;
+                for (index, weight) in weights.iter().enumerate() {
+                    self.current_weights[index] += *weight as isize;
+                    if self.current_weights[index] > self.current_weights[best] {
+                        best = index;
+                    }
+                }
+                self.current_weights[best] -= total;
+                Some(self.outputs[best])
+            }
+        }
+    }
+}
+
+pub struct SpawnIngress;
+
+impl Command for SpawnIngress {
+    fn apply(self, world: &mut World) {
+        let handle = world.resource_scope(|_world, ass: Mut<ImageAssets>| {
+            let handle: Handle<Image> = ass.ingress.clone();
+            handle
+        });
+
+        world
+            .spawn((
+                Name::new("Ingress"),
+                SpriteBundle {
+                    texture: handle,
+                    transform: Transform::from_translation(INGRESS_POSITION)
+                        .with_scale(Vec3::splat(0.25)),
+                    ..default()
+                },
+                Ingress::default(),
+                PickableBundle::default(),
+                NoDeselect,
+            ))
+            .with_children(|subcommands| {
+                subcommands.spawn((
+                    Text2dBundle {
+                        transform: Transform::from_translation(Vec3::new(150.0, 60.0, 10.0)),
+                        text: Text::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 22.0 * 2.0, // Because of parent scale
+                                ..default()
+                            },
+                        )
+                        .with_justify(JustifyText::Left),
+                        text_anchor: bevy::sprite::Anchor::TopLeft,
+                        ..default()
+                    },
+                    IngressInfoText,
+                    Pickable::IGNORE,
+                ));
+            });
+    }
+}
+
+// "Change Mode" on the ingress switches between the DNS strategies instead
+fn handle_change_ingress_algorithm(
+    mut evs: EventReader<ChangeServerModeEvent>,
+    mut query: Query<(&mut Ingress, &PickSelection)>,
+) {
+    for _ev in evs.read() {
+        for (mut ingress, pick_selection) in query.iter_mut() {
+            if pick_selection.is_selected {
+                ingress.algorithm = match ingress.algorithm {
+                    LoadBalancingAlgorithm::RoundRobin => LoadBalancingAlgorithm::Weighted,
+                    LoadBalancingAlgorithm::Weighted => LoadBalancingAlgorithm::RoundRobin,
+                };
+                println!("Switched ingress to {:?}", ingress.algorithm);
+            }
+        }
+    }
+}
+
+fn draw_ingress_ui(q_ingress: Query<(&Ingress, &Children)>, mut q_child: Query<&mut Text>) {
+    for (ingress, children) in q_ingress.iter() {
+        let algorithm = match ingress.algorithm {
+            LoadBalancingAlgorithm::RoundRobin => "DNS Round-robin",
+            LoadBalancingAlgorithm::Weighted => "DNS Weighted",
+        };
+        let connected_servers = ingress.outputs.len();
+        let backpressure = if ingress.backpressure {
+            "\nBackpressure"
+        } else {
+            ""
+        };
+
+        for &child in children.iter() {
+            if let Ok(mut text) = q_child.get_mut(child) {
+                text.sections[0].value =
+                    format!("Internet\n{algorithm}\nRecords: {connected_servers}{backpressure}");
+            }
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct ToggleBackpressureEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for ToggleBackpressureEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        ToggleBackpressureEvent
+    }
+}
+
+fn toggle_backpressure(mut query: Query<&mut Ingress>) {
+    for mut ingress in query.iter_mut() {
+        ingress.backpressure = !ingress.backpressure;
+        println!("Backpressure: {}", ingress.backpressure);
+    }
+}
+
+fn update_backpressure_button(
+    q_ingress: Query<&Ingress>,
+    q_button: Query<&Children, With<BackpressureButton>>,
+    mut q_text: Query<&mut Text>,
+) {
+    let label = match q_ingress.get_single() {
+        Ok(ingress) if ingress.backpressure => "Backpressure: On",
+        _ => "Backpressure: Off",
+    };
+    for children in q_button.iter() {
+        if let Ok(mut text) = q_text.get_mut(children[0]) {
+            if text.sections[0].value != label {
+                text.sections[0].value = label.to_string();
+            }
+        }
+    }
+}
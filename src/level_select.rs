@@ -6,7 +6,20 @@ pub struct LevelSelectPlugin;
 
 impl Plugin for LevelSelectPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::LevelSelect), spawn_ui)
+        app.add_event::<SelectLevelEvent>()
+            .add_event::<ShowLevelSelect>()
+            .add_systems(
+                OnEnter(GameState::LevelSelect),
+                (leave_test_play, leave_endless, clear_level, spawn_ui).chain(),
+            )
+            .add_systems(OnExit(GameState::LevelSelect), clear_level_select)
+            .add_systems(
+                Update,
+                (
+                    start_level.run_if(in_state(GameState::LevelSelect)),
+                    show_level_select.run_if(on_event::<ShowLevelSelect>()),
+                ),
+            )
             //.add_systems(
             //    Update,
             //    handle_tween_complete.run_if(in_state(GameState::LevelSelect)),
@@ -26,19 +39,83 @@ impl Plugin for LevelSelectPlugin {
 #[derive(Component)]
 struct LevelSelectStuff;
 
-fn start_game(
+#[derive(Component)]
+struct LevelCard(usize);
+
+#[derive(Event)]
+pub struct SelectLevelEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<Click>>> for SelectLevelEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        SelectLevelEvent(event.target)
+    }
+}
+
+#[derive(Event)]
+pub struct ShowLevelSelect;
+
+impl From<ListenerInput<Pointer<Click>>> for ShowLevelSelect {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        ShowLevelSelect
+    }
+}
+
+fn show_level_select(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::LevelSelect);
+}
+
+// Coming back from a level, get rid of whatever was left on the board
+fn clear_level(
     mut commands: Commands,
-    query: Query<Entity, With<LevelSelectStuff>>,
-    mut next_state: ResMut<NextState<GameState>>,
+    query: Query<Entity, Or<(With<Server>, With<Ingress>, With<LevelOwned>)>>,
 ) {
-    println!("Starting game");
     for e in query.iter() {
         commands.entity(e).despawn_recursive();
     }
-    next_state.set(GameState::Planning);
 }
 
-fn spawn_ui(mut commands: Commands, image_assets: Res<ImageAssets>) {
+fn clear_level_select(mut commands: Commands, query: Query<Entity, With<LevelSelectStuff>>) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn start_level(
+    mut evs: EventReader<SelectLevelEvent>,
+    q_cards: Query<&LevelCard>,
+    save: Res<SaveGame>,
+    mut game_levels: ResMut<GameLevels>,
+    mut evs_reset: EventWriter<ResetCurrentLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for ev in evs.read() {
+        let Ok(card) = q_cards.get(ev.0) else {
+            continue;
+        };
+        if !save.is_unlocked(card.0) {
+            println!("Level {} is still locked", card.0 + 1);
+            continue;
+        }
+        println!("Starting level {}", card.0 + 1);
+        // Reset even when it's the same level, so it starts from scratch
+        game_levels.current = card.0;
+        evs_reset.send(ResetCurrentLevel);
+        next_state.set(GameState::Planning);
+    }
+}
+
+fn stars_text(stars: usize, max_stars: usize) -> String {
+    (0..max_stars)
+        .map(|i| if i < stars { '*' } else { '-' })
+        .collect()
+}
+
+fn spawn_ui(
+    mut commands: Commands,
+    image_assets: Res<ImageAssets>,
+    game_levels: Res<GameLevels>,
+    save: Res<SaveGame>,
+) {
     let logo_start = Vec3::new(0.0, 1000.0, 100.0);
     let logo_in_tween = Tween::new(
         EaseFunction::QuadraticOut,
@@ -76,62 +153,159 @@ fn spawn_ui(mut commands: Commands, image_assets: Res<ImageAssets>) {
         std::time::Duration::from_secs(1),
         TransformPositionLens {
             start: tutorial_start,
-            end: Vec3::new(0.0, -50.0, 10.0),
+            end: Vec3::new(0.0, 0.0, 10.0),
         },
     );
     commands.spawn((
         LevelSelectStuff,
         SpriteBundle {
             texture: image_assets.tutorial.clone(),
-            transform: Transform::from_translation(tutorial_start).with_scale(Vec3::splat(0.7)),
+            transform: Transform::from_translation(tutorial_start).with_scale(Vec3::splat(0.5)),
             ..default()
         },
         Animator::new(Delay::new(Duration::from_secs_f32(4.)).then(tutorial_tween)),
     ));
 
-    let button_tween = Tween::new(
+    let cards_tween = Tween::new(
         EaseFunction::BounceOut,
         std::time::Duration::from_secs(1),
         bevy_tweening::lens::UiPositionLens {
             start: UiRect::new(Val::Auto, Val::Auto, Val::Auto, Val::Px(-300.0)),
-            end: UiRect::new(Val::Auto, Val::Auto, Val::Auto, Val::Px(50.0)),
+            end: UiRect::new(Val::Auto, Val::Auto, Val::Auto, Val::Px(30.0)),
         },
     );
 
     commands
         .spawn((
             LevelSelectStuff,
-            ButtonBundle {
+            NodeBundle {
+                // Wraps into more rows as levels get added, growing upwards
+                // since it's pinned to the bottom
                 style: Style {
-                    bottom: Val::Px(-200.0),
+                    bottom: Val::Px(-300.0),
                     width: Val::Percent(100.0),
-                    height: Val::Px(50.0),
                     position_type: PositionType::Absolute,
-                    // bottom: Val::Px(20.0),
+                    flex_direction: FlexDirection::Row,
+                    flex_wrap: FlexWrap::Wrap,
                     justify_content: JustifyContent::Center,
                     align_items: AlignItems::Center,
+                    column_gap: Val::Px(20.0),
+                    row_gap: Val::Px(20.0),
+                    padding: UiRect::horizontal(Val::Px(20.0)),
                     ..default()
                 },
-                background_color: GREEN_400.into(),
                 ..default()
             },
-            PickableBundle::default(),
-            On::<Pointer<Click>>::run(start_game),
-            Hoverable(GREEN_600, GREEN_500, GREEN_400),
-            // On::<Pointer<Click>>::send_event::<E>(),
-            // PlanningUI,
-            // NoDeselect,
+            Pickable::IGNORE,
         ))
         .insert(Animator::new(
-            Delay::new(Duration::from_secs_f32(4.)).then(button_tween),
+            Delay::new(Duration::from_secs_f32(4.)).then(cards_tween),
+        ))
+        .with_children(|parent| {
+            for (index, level) in game_levels.levels.iter().enumerate() {
+                let unlocked = save.is_unlocked(index);
+                let best = save.best_result(index);
+
+                let title = format!("Level #{}: {}", index + 1, level.title);
+                let (status, stars) = match (unlocked, best) {
+                    (false, _) => ("Locked".to_string(), 0),
+                    (true, None) => ("Not played yet".to_string(), 0),
+                    (true, Some(best)) => (
+                        format!(
+                            "Best: {:.0}% / {:.2}ms",
+                            best.handled_percentage * 100.0,
+                            best.avg_response_time * 10.0
+                        ),
+                        level.stars(&best),
+                    ),
+                };
+                let (bg_color, hover_color, text_color) = if unlocked {
+                    (GREEN_400, GREEN_500, WHITE_SMOKE)
+                } else {
+                    (GRAY_600, GRAY_600, GRAY_400)
+                };
+
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(250.0),
+                                height: Val::Px(130.0),
+                                flex_direction: FlexDirection::Column,
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                row_gap: Val::Px(8.0),
+                                ..default()
+                            },
+                            background_color: bg_color.into(),
+                            ..default()
+                        },
+                        LevelCard(index),
+                        PickableBundle::default(),
+                        On::<Pointer<Click>>::send_event::<SelectLevelEvent>(),
+                        Hoverable(bg_color, hover_color, hover_color),
+                    ))
+                    .with_children(|parent| {
+                        for (text, font_size) in [
+                            (title, 20.0),
+                            (status, 16.0),
+                            (stars_text(stars, level.max_stars()), 28.0),
+                        ] {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    text,
+                                    TextStyle {
+                                        font_size,
+                                        color: text_color.into(),
+                                        ..default()
+                                    },
+                                ),
+                                Pickable::IGNORE,
+                            ));
+                        }
+                    });
+            }
+
+            // Other ways to play
+            spawn_mode_card::<OpenEditorEvent, _>(parent, "Level Editor", OpenEditorButton);
+            spawn_mode_card::<OpenSandboxEvent, _>(parent, "Sandbox", OpenSandboxButton);
+            let endless_label = match save.endless_scores.first() {
+                Some(best) => format!("Endless\nBest: {} waves", best.waves_survived),
+                None => "Endless".to_string(),
+            };
+            spawn_mode_card::<OpenEndlessEvent, _>(parent, &endless_label, OpenEndlessButton);
+        });
+}
+
+fn spawn_mode_card<E, C>(parent: &mut ChildBuilder, label: &str, component: C)
+where
+    E: Event + From<ListenerInput<Pointer<Click>>>,
+    C: Bundle,
+{
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(150.0),
+                    height: Val::Px(130.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: PURPLE_400.into(),
+                ..default()
+            },
+            component,
+            PickableBundle::default(),
+            On::<Pointer<Click>>::send_event::<E>(),
+            Hoverable(PURPLE_400, PURPLE_500, PURPLE_500),
         ))
         .with_children(|parent| {
             parent.spawn((
                 TextBundle::from_section(
-                    "Start",
+                    label,
                     TextStyle {
-                        font_size: 22.0,
-                        // font: font_handle,
+                        font_size: 20.0,
                         color: WHITE_SMOKE.into(),
                         ..default()
                     },
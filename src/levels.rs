@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use serde::{Deserialize, Serialize};
 
 fn strs(s: Vec<&str>) -> Vec<String> {
     let mut ret = Vec::new();
@@ -28,12 +29,21 @@ fn levels() -> Vec<Level> {
             // required_handled_requests: 1.0,
             // This is actually 1000ms in the game, but 1.0 is one second actually
             required_avg_response_time: 10.0,
+            locked_servers: vec![],
+            max_dns_records: Some(1),
+            slo: None,
+            objectives: vec![],
+            bonus_objectives: vec![Objective::MaxPointsSpent(3)],
         },
-        // PASSABLE
+        // PASSABLE, see tests/levels.rs
         Level {
             title: "Website Launch".to_string(),
-            schedules: vec![LoadSchedule::new(10.0, 5, 10.0, (1..1).into())],
-            intro_text: "Time to launch the website! Expect a lot more requests over a longer timeframe. I've gotten you some more servers too, don't forget you can change their mode to Proxy!".to_string(),
+            // Was 5 RPS and 15 points before the Internet could be wired up.
+            // With one DNS record the front server has to take it all, this is
+            // where that's too much for one server but not for a proxy in
+            // front of the others.
+            schedules: vec![LoadSchedule::new(10.0, 7, 10.0, (1..1).into())],
+            intro_text: "Time to launch the website! Expect a lot more requests over a longer timeframe. I've gotten you some more servers too, don't forget you can change their mode to Proxy! We only got the one DNS record though, so the Internet can only point at a single server.".to_string(),
             success_text: "Wow, that went great! Only time can tell what will come next...".to_string(),
             failure_texts: strs(vec![
                 "This was our only shot and you ruined it...",
@@ -42,14 +52,21 @@ fn levels() -> Vec<Level> {
                 "Sometimes I think you're not even trying",
             ]),
             available_servers: 4,
-            upgrade_points: 15,
+            upgrade_points: 20,
             required_handled_requests: 0.8,
             required_avg_response_time: 10.0,
+            locked_servers: vec![],
+            max_dns_records: Some(1),
+            slo: None,
+            objectives: vec![],
+            bonus_objectives: vec![Objective::MaxUtilisation(0.8)],
         },
-        // NOT SURE IF PASSABLE ?!
+        // PASSABLE, see tests/levels.rs
         Level {
             title: "GMTK Game Jam".to_string(),
-            schedules: vec![LoadSchedule::new(30.0, 20, 30.0, (1..1).into())],
+            // Was 20 RPS, a bit less so a proxy in front keeps up now that it
+            // takes every request
+            schedules: vec![LoadSchedule::new(30.0, 18, 30.0, (1..1).into())],
             intro_text: "This crazy YouTube person has decided to use our platform for hosting their game jam! It's gonna be a ton of fun, but prepare for an astronomical load! I've given you access to extra hardware of course".to_string(),
             success_text: "Wow, that went great! Only time can tell what will come next...".to_string(),
             failure_texts: strs(vec![
@@ -63,6 +80,11 @@ fn levels() -> Vec<Level> {
             upgrade_points: 80,
             required_handled_requests: 0.7,
             required_avg_response_time: 20.0,
+            locked_servers: vec![],
+            max_dns_records: Some(1),
+            slo: None,
+            objectives: vec![],
+            bonus_objectives: vec![Objective::MinProfit(0)],
         },
     ]
 }
@@ -102,6 +124,7 @@ pub struct LevelChange;
 #[derive(Component)]
 pub struct LevelOwned;
 
+#[derive(Clone)]
 pub struct Level {
     pub title: String,
     pub schedules: Vec<LoadSchedule>,
@@ -116,6 +139,26 @@ pub struct Level {
     pub required_handled_requests: f32,
     // in seconds (ms in game), how low the avg response time needs to be
     required_avg_response_time: f32,
+    // Servers the level places for the player, they can't be moved or upgraded
+    pub locked_servers: Vec<LockedServer>,
+    // How many DNS records the Internet can have, None for no limit. The
+    // campaign only gets one, otherwise the DNS round-robin does what the
+    // levels want a proxy for
+    pub max_dns_records: Option<usize>,
+    // On top of the requirements above, the run has to stay within it
+    pub slo: Option<Slo>,
+    // Have to pass too, see `Objective`
+    pub objectives: Vec<Objective>,
+    // Optional, one more star each
+    pub bonus_objectives: Vec<Objective>,
+}
+
+/// A server that comes with the level, on top of `available_servers`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockedServer {
+    pub x: f32,
+    pub y: f32,
+    pub mode: ServerMode,
 }
 
 #[derive(Resource)]
@@ -125,12 +168,184 @@ pub struct GameLevels {
     pub levels: Vec<Level>,
 }
 
+impl Level {
+    /// Whether the Internet can point at `count` servers
+    pub fn allows_dns_records(&self, count: usize) -> bool {
+        self.max_dns_records.map_or(true, |max| count <= max)
+    }
+
+    /// 0 stars when not passed, 1 for passing, one more for handling at least
+    /// half of the allowed slack above `required_handled_requests`, one more
+    /// for an average response time at most half of the requirement and one
+    /// more for each bonus objective
+    pub fn stars(&self, best: &LevelBest) -> usize {
+        if !best.passed {
+            return 0;
+        }
+        let mut stars = 1;
+        let comfortable_margin =
+            self.required_handled_requests + (1.0 - self.required_handled_requests) / 2.0;
+        if best.handled_percentage >= comfortable_margin {
+            stars += 1;
+        }
+        if best.avg_response_time <= self.required_avg_response_time / 2.0 {
+            stars += 1;
+        }
+        stars + best.bonus_objectives.min(self.bonus_objectives.len())
+    }
+
+    pub fn max_stars(&self) -> usize {
+        3 + self.bonus_objectives.len()
+    }
+
+    /// In seconds (ms in game), highest average response time that passes
+    pub fn required_avg_response_time(&self) -> f32 {
+        self.required_avg_response_time
+    }
+
+    /// Highest combined RPS of all schedules, they all start together
+    pub fn peak_rps(&self) -> f32 {
+        let longest = self
+            .schedules
+            .iter()
+            .map(|schedule| schedule.duration())
+            .fold(0.0, f32::max);
+        let mut peak: f32 = 0.0;
+        let mut elapsed = 0.0;
+        while elapsed <= longest {
+            let rps: f32 = self.schedules.iter().map(|s| s.rps_at(elapsed)).sum();
+            peak = peak.max(rps);
+            elapsed += 0.1;
+        }
+        peak
+    }
+
+    /// Roughly how many requests the level sends in total
+    pub fn expected_requests(&self) -> f32 {
+        self.schedules
+            .iter()
+            .map(|schedule| {
+                // Requests get spawned every 100ms, same as `spawn_requests`
+                let mut total = 0.0;
+                let mut elapsed = 0.0;
+                while elapsed <= schedule.duration() {
+                    total += schedule.rps_at(elapsed) / 10.0;
+                    elapsed += 0.1;
+                }
+                total
+            })
+            .sum()
+    }
+}
+
 impl GameLevels {
     pub fn active_level(&self) -> &Level {
         &self.levels[self.current]
     }
 }
 
+/// A level as plain data, what the level editor works on and saves to a
+/// level file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LevelFile {
+    pub title: String,
+    pub intro_text: String,
+    pub success_text: String,
+    pub failure_texts: Vec<String>,
+    pub schedules: Vec<ScheduleFile>,
+    pub available_servers: usize,
+    pub upgrade_points: usize,
+    pub required_handled_requests: f32,
+    // in seconds (ms in game)
+    pub required_avg_response_time: f32,
+    #[serde(default)]
+    pub locked_servers: Vec<LockedServer>,
+    #[serde(default)]
+    pub max_dns_records: Option<usize>,
+    #[serde(default)]
+    pub slo: Option<Slo>,
+    #[serde(default)]
+    pub objectives: Vec<Objective>,
+    #[serde(default)]
+    pub bonus_objectives: Vec<Objective>,
+}
+
+/// Same as `LoadSchedule::new` takes, in seconds
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ScheduleFile {
+    pub rampup: f32,
+    pub max_rps: usize,
+    pub rampdown: f32,
+}
+
+impl ScheduleFile {
+    pub fn to_schedule(&self) -> LoadSchedule {
+        LoadSchedule::new(self.rampup, self.max_rps, self.rampdown, (1..1).into())
+    }
+}
+
+impl From<&Level> for LevelFile {
+    fn from(level: &Level) -> Self {
+        Self {
+            title: level.title.clone(),
+            intro_text: level.intro_text.clone(),
+            success_text: level.success_text.clone(),
+            failure_texts: level.failure_texts.clone(),
+            schedules: level
+                .schedules
+                .iter()
+                .map(|schedule| ScheduleFile {
+                    rampup: schedule.rampup.as_secs_f32(),
+                    max_rps: schedule.max_rps,
+                    rampdown: schedule.rampdown.as_secs_f32(),
+                })
+                .collect(),
+            available_servers: level.available_servers,
+            upgrade_points: level.upgrade_points,
+            required_handled_requests: level.required_handled_requests,
+            required_avg_response_time: level.required_avg_response_time,
+            locked_servers: level.locked_servers.clone(),
+            max_dns_records: level.max_dns_records,
+            slo: level.slo,
+            objectives: level.objectives.clone(),
+            bonus_objectives: level.bonus_objectives.clone(),
+        }
+    }
+}
+
+impl LevelFile {
+    pub fn to_level(&self) -> Level {
+        Level {
+            title: self.title.clone(),
+            schedules: self
+                .schedules
+                .iter()
+                .map(ScheduleFile::to_schedule)
+                .collect(),
+            intro_text: self.intro_text.clone(),
+            success_text: self.success_text.clone(),
+            failure_texts: self.failure_texts.clone(),
+            available_servers: self.available_servers,
+            upgrade_points: self.upgrade_points,
+            required_handled_requests: self.required_handled_requests,
+            required_avg_response_time: self.required_avg_response_time,
+            locked_servers: self.locked_servers.clone(),
+            max_dns_records: self.max_dns_records,
+            slo: self.slo,
+            objectives: self.objectives.clone(),
+            bonus_objectives: self.bonus_objectives.clone(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string_pretty(self).expect("level files always serialize")
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text.trim()).map_err(|err| err.to_string())
+    }
+}
+
 impl Default for GameLevels {
     fn default() -> Self {
         let res = Self {
@@ -174,7 +389,13 @@ fn handle_load_next_level(
     mut game_levels: ResMut<GameLevels>,
     mut evs: EventWriter<ResetCurrentLevel>,
     mut next_state: ResMut<NextState<GameState>>,
+    test_play: Option<Res<EditorTestPlay>>,
 ) {
+    // A level from the editor isn't part of the campaign
+    if test_play.is_some() {
+        next_state.set(GameState::Editor);
+        return;
+    }
     match game_levels.levels.get(game_levels.current + 1) {
         Some(_) => {
             game_levels.current += 1;
@@ -228,63 +449,78 @@ fn handle_reload(
     next_state.set(GameState::Planning);
 }
 
-fn handle_reset(
-    mut commands: Commands,
-    q_level_owned: Query<Entity, With<LevelOwned>>,
-    q_servers: Query<Entity, With<Server>>,
-    mut evs_spawn: EventWriter<AddNewServer>,
-    game_levels: Res<GameLevels>,
-    mut results: ResMut<LevelResults>,
-    mut game_stats: ResMut<GameStats>,
-    mut points: ResMut<UpgradePoints>,
-    mut next_state: ResMut<NextState<GameState>>,
-) {
+fn handle_reset(mut commands: Commands, mut next_state: ResMut<NextState<GameState>>) {
     println!("Resetting");
     // This can be our "reload level" function
-    // Despawn everything from previous levels
-    for owned in &q_level_owned {
-        commands.entity(owned).despawn_recursive();
-    }
-    // Despawn all previous servers
-    for server in q_servers.iter() {
-        commands.entity(server).despawn_recursive();
-    }
-    // Create everything for our new level
-    let active_level = game_levels.active_level();
-    // Adding servers
-    println!("Spawning {} servers", active_level.available_servers);
-    evs_spawn.send(AddNewServer(active_level.available_servers));
-    // TODO Giving points for upgrades
-    // Adding the load scenarios
-    let ls = LoadScenario {
-        schedules: active_level.schedules.clone(),
-    };
-    commands.spawn((ls, LevelOwned));
-    // Change the required pass percentage
-    results.pass_percentage = active_level.required_handled_requests;
-    // Change the required pass avg response time
-    results.pass_avg_response_time = active_level.required_avg_response_time;
-    // Reset our game stats
-    *game_stats = GameStats::default();
-    // Reset upgrade points
-    points.total = active_level.upgrade_points;
-    points.assigned = 0;
+    commands.add(SetUpLevel);
     // Force reset to Planning
     next_state.set(GameState::Planning);
 }
 
+/// Builds the active level from scratch: its servers, the Internet, the load
+/// and the upgrade points. What a reset does, and where headless runs start.
+pub struct SetUpLevel;
+
+impl Command for SetUpLevel {
+    fn apply(self, world: &mut World) {
+        // Despawn everything from previous levels, and all previous servers
+        // (and the Internet pointing at them)
+        let previous: Vec<Entity> = world
+            .query_filtered::<Entity, Or<(With<LevelOwned>, With<Server>, With<Ingress>)>>()
+            .iter(world)
+            .collect();
+        for entity in previous {
+            // Might have gone already with its parent
+            if let Some(entity) = world.get_entity_mut(entity) {
+                entity.despawn_recursive();
+            }
+        }
+        // Create everything for our new level
+        let active_level = world.resource::<GameLevels>().active_level().clone();
+        // Adding servers
+        println!("Spawning {} servers", active_level.available_servers);
+        for index in 0..active_level.available_servers {
+            spawn_server(world, new_server_position(index));
+        }
+        for locked in &active_level.locked_servers {
+            SpawnLockedServer(locked.clone()).apply(world);
+        }
+        SpawnIngress.apply(world);
+        // Adding the load scenarios
+        let ls = LoadScenario {
+            schedules: active_level.schedules.clone(),
+        };
+        world.spawn((ls, LevelOwned));
+        // Change the required pass percentage and avg response time
+        let mut results = world.resource_mut::<LevelResults>();
+        results.pass_percentage = active_level.required_handled_requests;
+        results.pass_avg_response_time = active_level.required_avg_response_time;
+        // Reset our game stats
+        *world.resource_mut::<GameStats>() = GameStats::default();
+        // Reset upgrade points
+        let mut points = world.resource_mut::<UpgradePoints>();
+        points.total = active_level.upgrade_points;
+        points.assigned = 0;
+        points.reserved = 0;
+    }
+}
+
+// Only when a different level comes up, anything else touching `GameLevels`
+// would wipe the board. Starting the same level again sends its own reset.
 fn handle_level_change(
-    mut once_load: Local<bool>,
-    mut current_level: Local<usize>,
-    game_levels: ResMut<GameLevels>,
-    mut evs: EventWriter<ResetCurrentLevel>,
+    game_levels: Res<GameLevels>,
+    mut last: Local<Option<usize>>,
+    mut evs: ParamSet<(
+        EventReader<ResetCurrentLevel>,
+        EventWriter<ResetCurrentLevel>,
+    )>,
 ) {
-    if *current_level != game_levels.current || !*once_load {
-        // Resource value has changed, update our local first
-        *current_level = game_levels.current;
-        evs.send(ResetCurrentLevel);
+    let reset_already = evs.p0().read().count() > 0;
+    if *last == Some(game_levels.current) {
+        return;
     }
-    if !*once_load {
-        *once_load = true;
+    *last = Some(game_levels.current);
+    if !reset_already {
+        evs.p1().send(ResetCurrentLevel);
     }
 }
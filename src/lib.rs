@@ -1,18 +1,44 @@
 #![feature(new_range_api)]
 pub mod assets;
+pub mod blueprint;
+pub mod capacity;
+pub mod clipboard;
 pub mod dragging;
+pub mod editor;
+pub mod endless;
+pub mod export;
 pub mod full_game;
 pub mod game_stats;
+pub mod gym;
+pub mod headless;
+pub mod history;
+pub mod ingress;
 pub mod level_select;
 pub mod levels;
 pub mod load_balancer;
 pub mod load_scenarios;
+#[cfg(all(feature = "prometheus", not(target_arch = "wasm32")))]
+pub mod metrics_endpoint;
 pub mod misc;
+pub mod objectives;
+pub mod on_call;
 pub mod prelude;
+pub mod replay;
+pub mod request_trace;
 pub mod requests;
 pub mod results;
+pub mod sandbox;
+pub mod save;
+#[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+pub mod scripting;
 pub mod selection;
 pub mod server;
+pub mod server_stats;
+pub mod sim_speed;
+pub mod simulation;
+pub mod slo;
+pub mod solver;
 pub mod splash;
 pub mod states;
+pub mod topology;
 pub mod ui;
@@ -1,9 +1,13 @@
 use crate::prelude::*;
+use serde::{Deserialize, Serialize};
 
 pub const LOAD_BALANCER_STARTING_MILLIS: u64 = 50;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LoadBalancingAlgorithm {
     RoundRobin,
+    // Split proportional to each target's processing power
+    Weighted,
 }
 
 #[derive(Component)]
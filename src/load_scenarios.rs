@@ -1,28 +1,25 @@
 use crate::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
 
 pub struct LoadScenariosPlugin;
 
 impl Plugin for LoadScenariosPlugin {
     fn build(&self, app: &mut App) {
+        // The run itself is in `SimulationPlugin`, this is just the hotkey.
+        // Same as the Start button, so the layout gets checked first.
         app.add_systems(
             Update,
-            (
-                start_load_scenarios.run_if(input_just_pressed(KeyCode::Space)),
-                start_load_scenarios.run_if(on_event::<StartLoadScenarios>()),
-            ), // .run_if(in_state(GameState::Running)),
-        )
-        .add_systems(
-            FixedUpdate,
-            (
-                // start_load_scenarios.run_if(on_timer(Duration::from_millis(1000))),
-                spawn_requests_based_on_load_scenario.run_if(on_timer(Duration::from_millis(100))),
-            )
-                .run_if(in_state(GameState::Running)),
-        )
-        .add_event::<StartLoadScenarios>();
+            request_start
+                .run_if(input_just_pressed(KeyCode::Space))
+                .run_if(in_state(GameState::Planning)),
+        );
     }
 }
 
+fn request_start(mut evs: EventWriter<RequestStartEvent>) {
+    evs.send(RequestStartEvent);
+}
+
 #[derive(Event)]
 pub struct StartLoadScenarios;
 
@@ -32,6 +29,37 @@ impl From<ListenerInput<Pointer<Click>>> for StartLoadScenarios {
     }
 }
 
+/// Seeded RNG for everything that changes the outcome of a run, so the same
+/// seed and topology gives the same requests
+#[derive(Resource)]
+pub struct SimRng(pub StdRng);
+
+impl SimRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl Default for SimRng {
+    fn default() -> Self {
+        Self::from_seed(rand::random())
+    }
+}
+
+/// Fixed timesteps since the current run (or replay) started
+#[derive(Resource, Default)]
+pub struct RunClock {
+    pub ticks: u32,
+}
+
+pub(crate) fn tick_run_clock(mut clock: ResMut<RunClock>) {
+    clock.ticks += 1;
+}
+
+pub(crate) fn reset_run_clock(mut clock: ResMut<RunClock>) {
+    clock.ticks = 0;
+}
+
 #[derive(Clone)]
 // Defines a load of requests that happens
 pub struct LoadSchedule {
@@ -59,6 +87,13 @@ impl LoadSchedule {
             accumulated_requests: 0.0,
         }
     }
+    /// Requests per second this schedule sends `elapsed` seconds in
+    pub fn rps_at(&self, elapsed: f32) -> f32 {
+        calculate_current_rps(elapsed, self)
+    }
+    pub fn duration(&self) -> f32 {
+        self.rampup.as_secs_f32() + self.rampdown.as_secs_f32()
+    }
     pub fn start(&mut self, current_elapsed_time: f32) {
         self.active = true;
         self.start_time = current_elapsed_time;
@@ -78,6 +113,9 @@ pub fn spawn_requests_based_on_load_scenario(
     mut requests_gone: Local<bool>,
     q_requests: Query<&Request>,
     mut next_state: ResMut<NextState<GameState>>,
+    mut sim_rng: ResMut<SimRng>,
+    endless: Option<Res<EndlessRun>>,
+    mut evs_wave: EventWriter<WaveClearedEvent>,
 ) {
     for (entity, mut scenario) in query.iter_mut() {
         let mut all_schedules_completed = true;
@@ -110,7 +148,10 @@ pub fn spawn_requests_based_on_load_scenario(
                 // );
 
                 for _ in 0..requests_to_spawn {
-                    commands.add(SpawnRequest {});
+                    commands.add(SpawnRequest {
+                        offset_x: sim_rng.0.gen_range(-250.0..250.0),
+                        size: sim_rng.0.gen_range(1..32),
+                    });
                 }
             }
         }
@@ -119,8 +160,12 @@ pub fn spawn_requests_based_on_load_scenario(
                 println!("All schedules done, and no more requests!");
                 // send event that we're done!
                 commands.entity(entity).despawn_recursive();
-                // Show results
-                next_state.set(GameState::Results);
+                // Show results, unless it's endless and the next wave is up
+                if endless.is_some() {
+                    evs_wave.send(WaveClearedEvent);
+                } else {
+                    next_state.set(GameState::Results);
+                }
             } else {
                 let len = q_requests.iter().len();
                 if len == 0 {
@@ -149,7 +194,9 @@ fn calculate_current_rps(elapsed: f32, schedule: &LoadSchedule) -> f32 {
 }
 
 pub fn start_load_scenarios(
-    time: Res<Time>,
+    // The schedules are stepped in `FixedUpdate`, so they need to start on
+    // the fixed clock too, otherwise changing the sim speed skews them
+    time: Res<Time<Fixed>>,
     mut query: Query<&mut LoadScenario>,
     mut next_state: ResMut<NextState<GameState>>,
 ) {
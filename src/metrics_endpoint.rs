@@ -0,0 +1,242 @@
+// Live metrics in the Prometheus text format, so Grafana and friends can
+// watch the simulation. Native only, behind the `prometheus` feature:
+//
+//     cargo run --features prometheus
+//     curl http://127.0.0.1:9898/metrics
+//
+// Set SIMULATOR_METRICS_ADDR to listen somewhere else.
+
+use crate::prelude::*;
+use std::fmt::Write;
+use std::io::{Read, Write as _};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:9898";
+
+// Upper bounds of the response time buckets, in ms like the game shows them
+const LATENCY_BUCKETS_MS: [f32; 9] = [5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0];
+
+pub struct MetricsEndpointPlugin;
+
+impl Plugin for MetricsEndpointPlugin {
+    fn build(&self, app: &mut App) {
+        let addr = std::env::var("SIMULATOR_METRICS_ADDR").unwrap_or(DEFAULT_ADDR.to_string());
+        let page = MetricsPage::default();
+        match TcpListener::bind(&addr) {
+            Ok(listener) => {
+                println!("Serving metrics on http://{addr}/metrics");
+                let page = page.0.clone();
+                std::thread::spawn(move || serve_metrics(listener, page));
+            }
+            Err(err) => println!("Couldn't serve metrics on {addr}: {err}"),
+        }
+        app.insert_resource(page).add_systems(
+            Update,
+            render_metrics.run_if(on_timer(Duration::from_millis(500))),
+        );
+    }
+}
+
+/// The latest rendered metrics, shared with the HTTP thread
+#[derive(Resource, Default, Clone)]
+pub struct MetricsPage(pub Arc<Mutex<String>>);
+
+fn serve_metrics(listener: TcpListener, page: Arc<Mutex<String>>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(err) = respond(stream, &page) {
+                    println!("Metrics request failed: {err}");
+                }
+            }
+            Err(err) => println!("Metrics connection failed: {err}"),
+        }
+    }
+}
+
+// Every path gets the metrics, scrapers don't care and neither do we
+fn respond(mut stream: TcpStream, page: &Mutex<String>) -> std::io::Result<()> {
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request)?;
+    let body = page.lock().map(|page| page.clone()).unwrap_or_default();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+}
+
+fn render_metrics(
+    page: Res<MetricsPage>,
+    state: Res<State<GameState>>,
+    game_levels: Res<GameLevels>,
+    stats: Res<GameStats>,
+    q_servers: Query<(Entity, &Server, &ServerStats, Option<&BlueprintSlot>)>,
+    q_requests: Query<(), (With<Request>, Without<DroppedRequest>, Without<ToRemove>)>,
+) {
+    let mut out = String::new();
+    describe(
+        &mut out,
+        "simulator_level",
+        "gauge",
+        "Level being played, from 1",
+    );
+    describe(
+        &mut out,
+        "simulator_running",
+        "gauge",
+        "1 while a run is being simulated",
+    );
+    describe(
+        &mut out,
+        "simulator_requests_in_flight",
+        "gauge",
+        "Requests between the Internet and being done",
+    );
+    describe(
+        &mut out,
+        "simulator_requests_handled_total",
+        "counter",
+        "Requests handled this run",
+    );
+    describe(
+        &mut out,
+        "simulator_requests_dropped_total",
+        "counter",
+        "Requests dropped this run",
+    );
+    let running = matches!(
+        state.get(),
+        GameState::Running | GameState::Replay | GameState::Sandbox
+    );
+    let _ = writeln!(out, "simulator_level {}", game_levels.current + 1);
+    let _ = writeln!(out, "simulator_running {}", running as u8);
+    let _ = writeln!(
+        out,
+        "simulator_requests_in_flight {}",
+        q_requests.iter().count()
+    );
+    let _ = writeln!(
+        out,
+        "simulator_requests_handled_total {}",
+        stats.handled_requests
+    );
+    let _ = writeln!(
+        out,
+        "simulator_requests_dropped_total {}",
+        stats.dropped_requests
+    );
+
+    let mut servers: Vec<_> = q_servers.iter().collect();
+    servers.sort_by_key(|(e, _, _, slot)| (slot.map(|s| s.0), *e));
+    let per_server: [(&str, &str, &str, fn(&Server, &ServerStats) -> usize); 7] = [
+        (
+            "simulator_server_requests_arrived_total",
+            "counter",
+            "Requests that showed up at the server",
+            |_, s| s.arrived,
+        ),
+        (
+            "simulator_server_requests_handled_total",
+            "counter",
+            "Requests the server processed",
+            |_, s| s.handled,
+        ),
+        (
+            "simulator_server_requests_dropped_total",
+            "counter",
+            "Requests the server dropped",
+            |_, s| s.dropped,
+        ),
+        (
+            "simulator_server_requests_forwarded_total",
+            "counter",
+            "Requests the server proxied elsewhere",
+            |_, s| s.forwarded,
+        ),
+        (
+            "simulator_server_queue_depth",
+            "gauge",
+            "Requests waiting in the queue",
+            |server, _| server.queued_requests.len(),
+        ),
+        (
+            "simulator_server_queue_size",
+            "gauge",
+            "Most requests the queue can hold",
+            |server, _| server.queue_size,
+        ),
+        (
+            "simulator_server_processing_power",
+            "gauge",
+            "CPU upgrades on the server",
+            |server, _| server.processing_power,
+        ),
+    ];
+    for (name, kind, help, value) in per_server {
+        describe(&mut out, name, kind, help);
+        for (index, (_, server, server_stats, slot)) in servers.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "{name}{{server=\"{}\",mode=\"{:?}\"}} {}",
+                slot.map_or(index, |s| s.0) + 1,
+                server.mode,
+                value(server, server_stats)
+            );
+        }
+    }
+    describe(
+        &mut out,
+        "simulator_server_busy_seconds_total",
+        "counter",
+        "Simulated seconds spent working on requests",
+    );
+    for (index, (_, server, server_stats, slot)) in servers.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "simulator_server_busy_seconds_total{{server=\"{}\",mode=\"{:?}\"}} {}",
+            slot.map_or(index, |s| s.0) + 1,
+            server.mode,
+            server_stats.busy_time
+        );
+    }
+
+    // Response times of handled requests, same ms the game shows
+    describe(
+        &mut out,
+        "simulator_response_time_ms",
+        "histogram",
+        "Time from arriving to being processed",
+    );
+    let times: Vec<f32> = stats.response_times.iter().map(|age| age * 10.0).collect();
+    for bucket in LATENCY_BUCKETS_MS {
+        let count = times.iter().filter(|ms| **ms <= bucket).count();
+        let _ = writeln!(
+            out,
+            "simulator_response_time_ms_bucket{{le=\"{bucket}\"}} {count}"
+        );
+    }
+    let _ = writeln!(
+        out,
+        "simulator_response_time_ms_bucket{{le=\"+Inf\"}} {}",
+        times.len()
+    );
+    let _ = writeln!(
+        out,
+        "simulator_response_time_ms_sum {}",
+        times.iter().sum::<f32>()
+    );
+    let _ = writeln!(out, "simulator_response_time_ms_count {}", times.len());
+
+    if let Ok(mut page) = page.0.lock() {
+        *page = out;
+    }
+}
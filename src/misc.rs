@@ -8,7 +8,6 @@ pub struct MiscPlugin;
 impl Plugin for MiscPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(TweeningPlugin)
-            .add_systems(FixedLast, handle_removals)
             .add_systems(OnEnter(GameState::GameCompleted), clear_transforms)
             .add_systems(
                 Update,
@@ -29,7 +28,7 @@ impl Plugin for MiscPlugin {
 #[derive(Component)]
 pub struct ToRemove;
 
-fn handle_removals(mut commands: Commands, query: Query<Entity, With<ToRemove>>) {
+pub(crate) fn handle_removals(mut commands: Commands, query: Query<Entity, With<ToRemove>>) {
     for e in query.iter() {
         commands.entity(e).despawn_recursive();
     }
@@ -0,0 +1,141 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Every handled request earns this much
+const REVENUE_PER_REQUEST: i64 = 1;
+// Every upgrade point spent costs this much
+const COST_PER_POINT: i64 = 5;
+
+/// Something a level asks of the player on top of the handled requests and
+/// average response time. Required ones have to pass, bonus ones give stars.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    MaxPointsSpent(usize),
+    // Servers that got any requests at all
+    MaxServersUsed(usize),
+    // Proxies that forwarded any requests
+    MinProxiesUsed(usize),
+    // 0.0 <> 1.0, for the busiest server
+    MaxUtilisation(f32),
+    // See `RunSummary::profit`
+    MinProfit(i64),
+    // in seconds (ms in game)
+    MaxP99ResponseTime(f32),
+}
+
+/// What the objectives get checked against, gathered once a run is over
+pub struct RunSummary<'a> {
+    pub stats: &'a GameStats,
+    pub points_spent: usize,
+    pub servers: Vec<(ServerMode, ServerStats)>,
+    // Seconds the run took
+    pub elapsed: f32,
+}
+
+impl RunSummary<'_> {
+    pub fn servers_used(&self) -> usize {
+        self.servers
+            .iter()
+            .filter(|(_, stats)| stats.arrived > 0)
+            .count()
+    }
+
+    pub fn proxies_used(&self) -> usize {
+        self.servers
+            .iter()
+            .filter(|(mode, stats)| *mode == ServerMode::Proxy && stats.forwarded > 0)
+            .count()
+    }
+
+    pub fn max_utilisation(&self) -> f32 {
+        self.servers
+            .iter()
+            .map(|(_, stats)| stats.utilisation(self.elapsed))
+            .fold(0.0, f32::max)
+    }
+
+    /// Handled requests earn money, upgrades cost money
+    pub fn profit(&self) -> i64 {
+        self.stats.handled_requests as i64 * REVENUE_PER_REQUEST
+            - self.points_spent as i64 * COST_PER_POINT
+    }
+}
+
+/// How a single objective went, for the results screen
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ObjectiveResult {
+    pub objective: Objective,
+    pub bonus: bool,
+    pub description: String,
+    pub achieved: String,
+    pub passed: bool,
+}
+
+impl Objective {
+    pub fn description(&self) -> String {
+        match self {
+            Objective::MaxPointsSpent(points) => format!("Spend at most {points} upgrade points"),
+            Objective::MaxServersUsed(servers) => format!("Use at most {servers} servers"),
+            Objective::MinProxiesUsed(proxies) => format!("Use at least {proxies} proxies"),
+            Objective::MaxUtilisation(utilisation) => {
+                format!("Keep every server under {:.0}% busy", utilisation * 100.0)
+            }
+            Objective::MinProfit(profit) => format!("Make a profit of at least ${profit}"),
+            Objective::MaxP99ResponseTime(time) => {
+                format!("Keep the p99 response time under {:.0}ms", time * 10.0)
+            }
+        }
+    }
+
+    pub fn check(&self, run: &RunSummary, bonus: bool) -> ObjectiveResult {
+        let (achieved, passed) = match *self {
+            Objective::MaxPointsSpent(points) => (
+                format!("{} spent", run.points_spent),
+                run.points_spent <= points,
+            ),
+            Objective::MaxServersUsed(servers) => {
+                let used = run.servers_used();
+                (format!("{used} used"), used <= servers)
+            }
+            Objective::MinProxiesUsed(proxies) => {
+                let used = run.proxies_used();
+                (format!("{used} used"), used >= proxies)
+            }
+            Objective::MaxUtilisation(utilisation) => {
+                let busiest = run.max_utilisation();
+                (
+                    format!("busiest at {:.0}%", busiest * 100.0),
+                    busiest <= utilisation,
+                )
+            }
+            Objective::MinProfit(profit) => {
+                let made = run.profit();
+                (format!("${made}"), made >= profit)
+            }
+            Objective::MaxP99ResponseTime(time) => {
+                let p99 = run.stats.response_time_percentile(0.99);
+                (format!("{:.2}ms", p99 * 10.0), p99 <= time)
+            }
+        };
+        ObjectiveResult {
+            objective: *self,
+            bonus,
+            description: self.description(),
+            achieved,
+            passed,
+        }
+    }
+}
+
+/// Checks the level's required objectives, then its bonus ones
+pub fn check_objectives(level: &Level, run: &RunSummary) -> Vec<ObjectiveResult> {
+    let required = level
+        .objectives
+        .iter()
+        .map(|objective| objective.check(run, false));
+    let bonus = level
+        .bonus_objectives
+        .iter()
+        .map(|objective| objective.check(run, true));
+    required.chain(bonus).collect()
+}
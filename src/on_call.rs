@@ -0,0 +1,412 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// What the "On-call" button cycles through, in seconds. None is off.
+const ON_CALL_DELAYS: [Option<f32>; 4] = [None, Some(1.0), Some(3.0), Some(5.0)];
+
+pub struct OnCallPlugin;
+
+impl Plugin for OnCallPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OnCallWiring>()
+            .add_event::<ToggleOnCallEvent>()
+            .add_event::<OnCallButtonEvent>()
+            .add_systems(
+                Update,
+                (
+                    toggle_on_call.run_if(on_event::<ToggleOnCallEvent>()),
+                    update_on_call_button,
+                    reserve_on_call_points.run_if(on_call_enabled),
+                )
+                    .run_if(in_state(GameState::Planning)),
+            )
+            .add_systems(
+                OnEnter(GameState::Running),
+                spawn_on_call_ui.run_if(on_call_enabled),
+            )
+            .add_systems(
+                OnExit(GameState::Running),
+                (clear_entity_with::<OnCallUI>, stop_wiring),
+            )
+            // Always on call in the sandbox, that's how servers get changed there
+            .add_systems(OnEnter(GameState::Sandbox), spawn_on_call_ui)
+            .add_systems(
+                OnExit(GameState::Sandbox),
+                (clear_entity_with::<OnCallUI>, stop_wiring),
+            )
+            .add_systems(
+                Update,
+                (handle_on_call_buttons, show_hide_on_call_ui)
+                    .chain()
+                    .run_if(in_state(GameState::Running).or_else(in_state(GameState::Sandbox))),
+            );
+    }
+}
+
+/// Marks a server that's being changed mid-run. It can't take or work on
+/// requests until the timer runs out.
+#[derive(Component)]
+pub struct Reconfiguring(pub Timer);
+
+/// A change the on-call player can make to a running server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Reconfiguration {
+    UpgradeCpu,
+    UpgradeQueue,
+    SwitchMode,
+    // Indexes like `BlueprintServer::outputs`, see `BlueprintSlot`
+    SetOutputs(Vec<usize>),
+}
+
+impl Reconfiguration {
+    fn cost(&self, server: &Server) -> usize {
+        match self {
+            Reconfiguration::UpgradeCpu => server.processing_power.max(1),
+            Reconfiguration::UpgradeQueue => 1,
+            Reconfiguration::SwitchMode | Reconfiguration::SetOutputs(_) => 0,
+        }
+    }
+}
+
+/// Which server we're picking new outputs for mid-run, if any
+#[derive(Resource, Default)]
+pub struct OnCallWiring(pub Option<Entity>);
+
+#[derive(Event)]
+pub struct ToggleOnCallEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for ToggleOnCallEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        ToggleOnCallEvent
+    }
+}
+
+/// The buttons on the on-call panel, each one maps to a `Reconfiguration`
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub enum OnCallAction {
+    UpgradeCpu,
+    UpgradeQueue,
+    SwitchMode,
+    SetOutputs,
+}
+
+#[derive(Event)]
+pub struct OnCallButtonEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<Click>>> for OnCallButtonEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        OnCallButtonEvent(event.target)
+    }
+}
+
+/// Changes a server in the middle of a run and takes it offline for `delay`
+/// seconds. Also what replays use to repeat the changes made during a run.
+pub struct Reconfigure {
+    // `BlueprintSlot` of the server to change
+    pub slot: usize,
+    pub change: Reconfiguration,
+    pub delay: f32,
+    // Replays already know the change went through, no need to check points
+    pub enforce_budget: bool,
+}
+
+impl Command for Reconfigure {
+    fn apply(self, world: &mut World) {
+        let slots: Vec<(Entity, usize)> = world
+            .query::<(Entity, &BlueprintSlot)>()
+            .iter(world)
+            .map(|(entity, slot)| (entity, slot.0))
+            .collect();
+        let entity_for = |slot: usize| {
+            slots
+                .iter()
+                .find(|(_, s)| *s == slot)
+                .map(|(entity, _)| *entity)
+        };
+        let Some(e_server) = entity_for(self.slot) else {
+            println!("No server in slot {} to reconfigure", self.slot);
+            return;
+        };
+        let (proxy_image, server_image) = {
+            let image_assets = world.resource::<ImageAssets>();
+            (
+                image_assets.server_proxy.clone(),
+                image_assets.server.clone(),
+            )
+        };
+
+        let rewiring = matches!(self.change, Reconfiguration::SetOutputs(_));
+        if world.get::<Locked>(e_server).is_some() {
+            if !rewiring {
+                println!(
+                    "Server in slot {} came with the level, it can't be changed",
+                    self.slot
+                );
+                return;
+            }
+            // Wiring outputs makes it a proxy, only locked proxies can have them
+            let processing = world
+                .get::<Server>(e_server)
+                .is_some_and(|server| server.mode == ServerMode::Process);
+            if processing {
+                println!("Locked server can't become a proxy");
+                return;
+            }
+        }
+
+        let cost = match world.get::<Server>(e_server) {
+            Some(server) => self.change.cost(server),
+            None => return,
+        };
+        // The sandbox has no planning to reserve points in, it's all on call
+        let sandbox = *world.resource::<State<GameState>>().get() == GameState::Sandbox;
+        if self.enforce_budget && sandbox {
+            let mut upgrade_points = world.resource_mut::<UpgradePoints>();
+            if !upgrade_points.can_spend_points(cost) {
+                println!(
+                    "Couldn't reconfigure! Current assigned points: {}, max points: {}",
+                    upgrade_points.assigned, upgrade_points.total
+                );
+                return;
+            }
+            upgrade_points.assigned += cost;
+        } else if self.enforce_budget {
+            let mut upgrade_points = world.resource_mut::<UpgradePoints>();
+            if !upgrade_points.can_spend_on_call(cost) {
+                println!(
+                    "Couldn't reconfigure! Reserved for on-call: {}, needed: {cost}",
+                    upgrade_points.reserved
+                );
+                return;
+            }
+            upgrade_points.spend_on_call(cost);
+        }
+
+        let mut entity = world.entity_mut(e_server);
+        let mut server = entity.get_mut::<Server>().unwrap();
+        match &self.change {
+            Reconfiguration::UpgradeCpu => {
+                server.processing_power += 1;
+                server.reset_progress();
+            }
+            Reconfiguration::UpgradeQueue => {
+                server.queue_size += 1;
+            }
+            Reconfiguration::SwitchMode => {
+                server.mode = match server.mode {
+                    ServerMode::Process => ServerMode::Proxy,
+                    ServerMode::Proxy => {
+                        server.outputs = vec![];
+                        ServerMode::Process
+                    }
+                };
+                server.reset_progress();
+            }
+            Reconfiguration::SetOutputs(outputs) => {
+                // Same as in planning, wiring outputs makes it a proxy
+                server.mode = ServerMode::Proxy;
+                server.outputs = outputs.iter().filter_map(|o| entity_for(*o)).collect();
+                server.reset_progress();
+            }
+        }
+        let mode = server.mode;
+        *entity.get_mut::<Handle<Image>>().unwrap() = match mode {
+            ServerMode::Process => server_image,
+            ServerMode::Proxy => proxy_image,
+        };
+        entity.insert(Reconfiguring(Timer::from_seconds(
+            self.delay,
+            TimerMode::Once,
+        )));
+        println!(
+            "Reconfiguring server in slot {}: {:?}, offline for {}s",
+            self.slot, self.change, self.delay
+        );
+
+        let tick = world.resource::<RunClock>().ticks;
+        if let Some(mut recorder) = world.get_resource_mut::<RunRecorder>() {
+            recorder.push(RecordedEvent::Reconfigured {
+                tick,
+                server: self.slot,
+                change: self.change,
+                delay: self.delay,
+            });
+        }
+    }
+}
+
+pub fn on_call_enabled(save: Res<SaveGame>) -> bool {
+    save.settings.on_call_delay.is_some()
+}
+
+// Off -> 1s -> 3s -> 5s -> Off
+fn toggle_on_call(mut save: ResMut<SaveGame>, mut points: ResMut<UpgradePoints>) {
+    let current = ON_CALL_DELAYS
+        .iter()
+        .position(|delay| *delay == save.settings.on_call_delay)
+        .unwrap_or(0);
+    save.settings.on_call_delay = ON_CALL_DELAYS[(current + 1) % ON_CALL_DELAYS.len()];
+    println!("On-call delay: {:?}", save.settings.on_call_delay);
+    save.write();
+    // Nobody's on call to spend them
+    if save.settings.on_call_delay.is_none() {
+        points.reserved = 0;
+    }
+}
+
+// ] puts a point aside for the on-call player, [ gives one back
+fn reserve_on_call_points(keys: Res<ButtonInput<KeyCode>>, mut points: ResMut<UpgradePoints>) {
+    if keys.just_pressed(KeyCode::BracketRight) {
+        if points.can_spend_points(1) {
+            points.reserved += 1;
+        }
+        println!("Reserved for on-call: {}", points.reserved);
+    }
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        points.reserved = points.reserved.saturating_sub(1);
+        println!("Reserved for on-call: {}", points.reserved);
+    }
+}
+
+fn update_on_call_button(
+    save: Res<SaveGame>,
+    q_button: Query<&Children, With<OnCallButton>>,
+    mut q_text: Query<&mut Text>,
+) {
+    let label = match save.settings.on_call_delay {
+        Some(delay) => format!("On-call: {delay}s"),
+        None => "On-call: Off".to_string(),
+    };
+    for children in q_button.iter() {
+        if let Ok(mut text) = q_text.get_mut(children[0]) {
+            if text.sections[0].value != label {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+}
+
+fn handle_on_call_buttons(
+    mut commands: Commands,
+    mut evs: EventReader<OnCallButtonEvent>,
+    q_buttons: Query<&OnCallAction>,
+    mut q_servers: Query<(Entity, &BlueprintSlot, &mut PickSelection), Without<Reconfiguring>>,
+    mut wiring: ResMut<OnCallWiring>,
+    save: Res<SaveGame>,
+    state: Res<State<GameState>>,
+) {
+    // The sandbox doesn't need on-call turned on, changes are instant there
+    // unless it is
+    let delay = match (save.settings.on_call_delay, state.get()) {
+        (Some(delay), _) => delay,
+        (None, GameState::Sandbox) => 0.0,
+        (None, _) => return,
+    };
+    for ev in evs.read() {
+        let Ok(action) = q_buttons.get(ev.0) else {
+            continue;
+        };
+        let selected: Vec<(Entity, usize)> = q_servers
+            .iter()
+            .filter(|(_, _, selection)| selection.is_selected)
+            .map(|(entity, slot, _)| (entity, slot.0))
+            .collect();
+
+        let reconfigure = |slot: usize, change: Reconfiguration| Reconfigure {
+            slot,
+            change,
+            delay,
+            enforce_budget: true,
+        };
+        match (action, wiring.0) {
+            (OnCallAction::SetOutputs, None) => {
+                // First press picks the server, the second one connects it
+                if let Some((entity, _)) = selected.first() {
+                    wiring.0 = Some(*entity);
+                    for (_, _, mut selection) in q_servers.iter_mut() {
+                        selection.is_selected = false;
+                    }
+                }
+            }
+            (OnCallAction::SetOutputs, Some(e_source)) => {
+                wiring.0 = None;
+                if let Ok((_, source_slot, _)) = q_servers.get(e_source) {
+                    let outputs = selected.iter().map(|(_, slot)| *slot).collect();
+                    commands.add(reconfigure(
+                        source_slot.0,
+                        Reconfiguration::SetOutputs(outputs),
+                    ));
+                }
+            }
+            (action, _) => {
+                let change = match action {
+                    OnCallAction::UpgradeCpu => Reconfiguration::UpgradeCpu,
+                    OnCallAction::UpgradeQueue => Reconfiguration::UpgradeQueue,
+                    _ => Reconfiguration::SwitchMode,
+                };
+                for (_, slot) in &selected {
+                    commands.add(reconfigure(*slot, change.clone()));
+                }
+            }
+        }
+    }
+}
+
+fn show_hide_on_call_ui(
+    selection: Query<&PickSelection, With<Server>>,
+    wiring: Res<OnCallWiring>,
+    points: Res<UpgradePoints>,
+    state: Res<State<GameState>>,
+    mut q_ui: Query<&mut Visibility, With<OnCallSelectionUI>>,
+    mut q_label: Query<&mut Text, With<OnCallLabel>>,
+) {
+    let any_selected = selection.iter().any(|selection| selection.is_selected);
+    if let Ok(mut visibility) = q_ui.get_single_mut() {
+        let wanted = if any_selected || wiring.0.is_some() {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+    if let Ok(mut text) = q_label.get_single_mut() {
+        let label = if wiring.0.is_some() {
+            "Pick the new outputs, then press Set Outputs again".to_string()
+        } else if *state.get() == GameState::Sandbox {
+            "On-call".to_string()
+        } else {
+            format!("On-call: {} points reserved", points.reserved)
+        };
+        if text.sections[0].value != label {
+            text.sections[0].value = label;
+        }
+    }
+}
+
+fn stop_wiring(mut wiring: ResMut<OnCallWiring>) {
+    wiring.0 = None;
+}
+
+pub(crate) fn tick_reconfigurations(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Reconfiguring)>,
+) {
+    for (entity, mut reconfiguring) in query.iter_mut() {
+        if reconfiguring.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Reconfiguring>();
+        }
+    }
+}
+
+// Nothing should still be offline when the next run starts
+pub(crate) fn finish_all_reconfigurations(
+    mut commands: Commands,
+    query: Query<Entity, With<Reconfiguring>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).remove::<Reconfiguring>();
+    }
+}
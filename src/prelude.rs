@@ -3,19 +3,45 @@ pub use std::collections::HashMap;
 pub use std::{collections::VecDeque, net::Incoming, time::Duration};
 
 pub use crate::assets::*;
+pub use crate::blueprint::*;
+pub use crate::capacity::*;
+pub use crate::clipboard::*;
 pub use crate::dragging::*;
+pub use crate::editor::*;
+pub use crate::endless::*;
+pub use crate::export::*;
 pub use crate::game_stats::*;
+pub use crate::gym::*;
+pub use crate::headless::*;
+pub use crate::history::*;
+pub use crate::ingress::*;
 pub use crate::level_select::*;
 pub use crate::levels::*;
 pub use crate::load_balancer::*;
 pub use crate::load_scenarios::*;
+#[cfg(all(feature = "prometheus", not(target_arch = "wasm32")))]
+pub use crate::metrics_endpoint::*;
 pub use crate::misc::*;
+pub use crate::objectives::*;
+pub use crate::on_call::*;
+pub use crate::replay::*;
+pub use crate::request_trace::*;
 pub use crate::requests::*;
 pub use crate::results::*;
+pub use crate::sandbox::*;
+pub use crate::save::*;
+#[cfg(all(feature = "scripting", not(target_arch = "wasm32")))]
+pub use crate::scripting::*;
 pub use crate::selection::*;
 pub use crate::server::*;
+pub use crate::server_stats::*;
+pub use crate::sim_speed::*;
+pub use crate::simulation::*;
+pub use crate::slo::*;
+pub use crate::solver::*;
 pub use crate::splash::*;
 pub use crate::states::*;
+pub use crate::topology::*;
 pub use crate::ui::*;
 
 // Macros
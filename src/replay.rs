@@ -0,0 +1,748 @@
+use crate::prelude::*;
+use bevy::color::palettes::{css::WHITE_SMOKE, tailwind::*};
+use serde::{Deserialize, Serialize};
+
+// How much faster than real time we go while seeking
+const SEEK_SPEED: f32 = 8.0;
+// How far the skip buttons jump
+const SKIP_SECONDS: f32 = 10.0;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunRecorder>()
+            .add_event::<WatchReplayEvent>()
+            .add_event::<CopyReplayEvent>()
+            .add_event::<ReplayButtonEvent>()
+            .add_event::<ReplayControlEvent>()
+            .add_event::<RestartReplayEvent>()
+            // Recording
+            .add_systems(OnEnter(GameState::Running), start_recording)
+            .add_systems(OnExit(GameState::Running), stop_recording)
+            .add_systems(
+                FixedLast,
+                record_outcomes.run_if(in_state(GameState::Running)),
+            )
+            .add_systems(
+                Update,
+                (
+                    watch_replay.run_if(on_event::<WatchReplayEvent>()),
+                    copy_replay.run_if(on_event::<CopyReplayEvent>()),
+                )
+                    .run_if(in_state(GameState::Results)),
+            )
+            .add_systems(
+                Update,
+                paste_replay.run_if(in_state(GameState::LevelSelect)),
+            )
+            // Playback
+            .add_systems(
+                OnEnter(GameState::Replay),
+                (restart_replay, spawn_replay_ui),
+            )
+            .add_systems(
+                OnExit(GameState::Replay),
+                (exit_replay, clear_entity_with::<ReplayUI>),
+            )
+            .add_systems(
+                OnExit(GameState::Results),
+                forget_replay.run_if(resource_exists::<BackFromReplay>),
+            )
+            // Same spot in the step as the live run spawns requests
+            .add_systems(
+                FixedUpdate,
+                play_recorded_spawns
+                    .before(count_requests_in_flight)
+                    .run_if(in_state(GameState::Replay)),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_replay_buttons,
+                    replay_shortcuts,
+                    apply_replay_controls,
+                    restart_replay.run_if(on_event::<RestartReplayEvent>()),
+                    finish_seeking,
+                    update_replay_ui,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Replay)),
+            );
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RecordedEvent {
+    Spawned {
+        tick: u32,
+        offset_x: f32,
+        size: usize,
+    },
+    Handled {
+        tick: u32,
+        count: usize,
+    },
+    Dropped {
+        tick: u32,
+        count: usize,
+    },
+    // Something the on-call player changed mid-run
+    Reconfigured {
+        tick: u32,
+        server: usize,
+        change: Reconfiguration,
+        delay: f32,
+    },
+}
+
+impl RecordedEvent {
+    pub fn tick(&self) -> u32 {
+        match self {
+            RecordedEvent::Spawned { tick, .. } => *tick,
+            RecordedEvent::Handled { tick, .. } => *tick,
+            RecordedEvent::Dropped { tick, .. } => *tick,
+            RecordedEvent::Reconfigured { tick, .. } => *tick,
+        }
+    }
+}
+
+/// Everything needed to play a run back: the topology it started with, the
+/// seed, and every request that came in. The simulation itself is re-run
+/// during playback on the same fixed steps, with the same seed and the
+/// on-call changes at the same ticks, so it plays out like the recorded run.
+/// Only the animations in between can look a little different.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunRecording {
+    pub level: usize,
+    pub seed: u64,
+    pub blueprint: Blueprint,
+    pub events: Vec<RecordedEvent>,
+    // In fixed timesteps, how long the run went on for
+    pub duration: u32,
+}
+
+impl RunRecording {
+    pub fn recorded_totals(&self) -> (usize, usize) {
+        let mut handled = 0;
+        let mut dropped = 0;
+        for event in &self.events {
+            match event {
+                RecordedEvent::Handled { count, .. } => handled += count,
+                RecordedEvent::Dropped { count, .. } => dropped += count,
+                RecordedEvent::Spawned { .. } | RecordedEvent::Reconfigured { .. } => {}
+            }
+        }
+        (handled, dropped)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct RunRecorder {
+    // Only true while a live run is going on
+    pub active: bool,
+    // The current run, or the last one once it's over
+    pub recording: Option<RunRecording>,
+    handled_so_far: usize,
+    dropped_so_far: usize,
+}
+
+impl RunRecorder {
+    pub fn push(&mut self, event: RecordedEvent) {
+        if !self.active {
+            return;
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.events.push(event);
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub recording: RunRecording,
+    // Index into `recording.events` of the next one to play
+    next_event: usize,
+    // Set while fast-forwarding to a tick
+    seek_to: Option<u32>,
+    // Where to go when leaving the replay, and what to put back then
+    return_to: GameState,
+    before: Option<BeforeReplay>,
+}
+
+/// The player's own run from before watching a replay
+pub struct BeforeReplay {
+    pub stats: GameStats,
+    pub points: UpgradePoints,
+    pub board: Blueprint,
+    // In blueprint order, like `board.servers`
+    pub server_stats: Vec<ServerStats>,
+    pub ticks: u32,
+    pub traces: TraceLog,
+}
+
+/// Set while the results screen is back up after a replay. The run was
+/// already scored and saved, the replay only borrowed the board.
+#[derive(Resource)]
+pub struct BackFromReplay;
+
+impl ReplayPlayback {
+    pub fn new(
+        recording: RunRecording,
+        return_to: GameState,
+        before: Option<BeforeReplay>,
+    ) -> Self {
+        Self {
+            recording,
+            next_event: 0,
+            seek_to: None,
+            return_to,
+            before,
+        }
+    }
+}
+
+/// Lays the board out from scratch like `blueprint`: the Internet, the
+/// level's locked servers and the rest
+struct RebuildBoard(Blueprint);
+
+impl Command for RebuildBoard {
+    fn apply(self, world: &mut World) {
+        let board: Vec<Entity> = world
+            .query_filtered::<Entity, Or<(With<Server>, With<Ingress>, With<LevelOwned>)>>()
+            .iter(world)
+            .collect();
+        for entity in board {
+            // Might have gone already with its parent
+            if let Some(entity) = world.get_entity_mut(entity) {
+                entity.despawn_recursive();
+            }
+        }
+        SpawnIngress.apply(world);
+        let locked_servers = world
+            .resource::<GameLevels>()
+            .levels
+            .get(self.0.level)
+            .map(|level| level.locked_servers.clone())
+            .unwrap_or_default();
+        for locked in locked_servers {
+            SpawnLockedServer(locked).apply(world);
+        }
+        ApplyBlueprint(self.0).apply(world);
+    }
+}
+
+#[derive(Event)]
+pub struct WatchReplayEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for WatchReplayEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        WatchReplayEvent
+    }
+}
+
+#[derive(Event)]
+pub struct CopyReplayEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for CopyReplayEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        CopyReplayEvent
+    }
+}
+
+#[derive(Event)]
+pub struct RestartReplayEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayControl {
+    SkipBack,
+    SkipForward,
+    Restart,
+    Exit,
+}
+
+#[derive(Event)]
+pub struct ReplayControlEvent(pub ReplayControl);
+
+#[derive(Component)]
+struct ReplayControlButton(ReplayControl);
+
+#[derive(Event)]
+pub struct ReplayButtonEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<Click>>> for ReplayButtonEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        ReplayButtonEvent(event.target)
+    }
+}
+
+#[derive(Component)]
+pub struct ReplayUI;
+
+#[derive(Component)]
+struct ReplayTimelineText;
+
+//
+
+fn start_recording(
+    mut commands: Commands,
+    mut recorder: ResMut<RunRecorder>,
+    mut sim_rng: ResMut<SimRng>,
+    q_servers: Query<(Entity, &Transform, &Server)>,
+    q_slot: SlotOrderQuery,
+    q_ingress: Query<&Ingress>,
+    game_levels: Res<GameLevels>,
+) {
+    let seed = rand::random();
+    *sim_rng = SimRng::from_seed(seed);
+
+    let mut servers: Vec<(Entity, Vec3, &Server)> = q_servers
+        .iter()
+        .map(|(e, t, server)| (e, t.translation, server))
+        .collect();
+    servers.sort_by_key(|(e, _, _)| slot_order(&q_slot, *e));
+    for (index, (e_server, _, _)) in servers.iter().enumerate() {
+        commands.entity(*e_server).insert(BlueprintSlot(index));
+    }
+
+    recorder.active = true;
+    recorder.handled_so_far = 0;
+    recorder.dropped_so_far = 0;
+    recorder.recording = Some(RunRecording {
+        level: game_levels.current,
+        seed,
+        blueprint: capture_blueprint(game_levels.current, &servers, q_ingress.get_single().ok()),
+        events: vec![],
+        duration: 0,
+    });
+}
+
+fn stop_recording(mut recorder: ResMut<RunRecorder>, clock: Res<RunClock>) {
+    recorder.active = false;
+    if let Some(recording) = &mut recorder.recording {
+        recording.duration = clock.ticks;
+        println!(
+            "Recorded {} events over {} ticks",
+            recording.events.len(),
+            recording.duration
+        );
+    }
+}
+
+fn record_outcomes(mut recorder: ResMut<RunRecorder>, stats: Res<GameStats>, clock: Res<RunClock>) {
+    let tick = clock.ticks;
+    if stats.handled_requests > recorder.handled_so_far {
+        let count = stats.handled_requests - recorder.handled_so_far;
+        recorder.handled_so_far = stats.handled_requests;
+        recorder.push(RecordedEvent::Handled { tick, count });
+    }
+    if stats.dropped_requests > recorder.dropped_so_far {
+        let count = stats.dropped_requests - recorder.dropped_so_far;
+        recorder.dropped_so_far = stats.dropped_requests;
+        recorder.push(RecordedEvent::Dropped { tick, count });
+    }
+}
+
+fn watch_replay(
+    mut commands: Commands,
+    recorder: Res<RunRecorder>,
+    stats: Res<GameStats>,
+    points: Res<UpgradePoints>,
+    clock: Res<RunClock>,
+    mut traces: ResMut<TraceLog>,
+    q_servers: Query<(Entity, &Transform, &Server, &ServerStats)>,
+    q_slot: SlotOrderQuery,
+    q_ingress: Query<&Ingress>,
+    game_levels: Res<GameLevels>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(recording) = recorder.recording.clone() else {
+        println!("Nothing has been recorded yet");
+        return;
+    };
+    // The replay takes over the board, keep the player's to put back after
+    let mut sorted: Vec<_> = q_servers.iter().collect();
+    sorted.sort_by_key(|(e, _, _, _)| slot_order(&q_slot, *e));
+    let servers: Vec<(Entity, Vec3, &Server)> = sorted
+        .iter()
+        .map(|(e, t, server, _)| (*e, t.translation, *server))
+        .collect();
+    let before = BeforeReplay {
+        stats: stats.clone(),
+        points: points.clone(),
+        board: capture_blueprint(game_levels.current, &servers, q_ingress.get_single().ok()),
+        server_stats: sorted
+            .iter()
+            .map(|(_, _, _, stats)| (*stats).clone())
+            .collect(),
+        ticks: clock.ticks,
+        // Gets cleared for the replay's own traces
+        traces: std::mem::take(&mut *traces),
+    };
+    commands.insert_resource(ReplayPlayback::new(
+        recording,
+        GameState::Results,
+        Some(before),
+    ));
+    next_state.set(GameState::Replay);
+}
+
+fn copy_replay(recorder: Res<RunRecorder>) {
+    let Some(recording) = &recorder.recording else {
+        println!("Nothing has been recorded yet");
+        return;
+    };
+    match serde_json::to_string(recording) {
+        Ok(text) => {
+            if copy_text("Copy your replay", &text) {
+                println!("Copied replay");
+            }
+        }
+        Err(err) => println!("Couldn't serialize replay: {err}"),
+    }
+}
+
+// Ctrl+V on the level select watches a replay someone shared
+fn paste_replay(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    game_levels: Res<GameLevels>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let modifier = keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    if !modifier || !keys.just_pressed(KeyCode::KeyV) {
+        return;
+    }
+    let Some(text) = paste_text("Paste a replay") else {
+        return;
+    };
+    let recording: RunRecording = match serde_json::from_str(text.trim()) {
+        Ok(recording) => recording,
+        Err(err) => {
+            println!("Not a valid replay: {err}");
+            return;
+        }
+    };
+    let Some(level) = game_levels.levels.get(recording.level) else {
+        println!(
+            "Replay is for level {} which doesn't exist",
+            recording.level + 1
+        );
+        return;
+    };
+    if let Err(err) = recording.blueprint.validate(level) {
+        println!("Replay has an invalid topology: {err}");
+        return;
+    }
+    commands.insert_resource(ReplayPlayback::new(recording, GameState::LevelSelect, None));
+    next_state.set(GameState::Replay);
+}
+
+// Puts the board back to how it was when the recorded run started
+fn restart_replay(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut stats: ResMut<GameStats>,
+    mut clock: ResMut<RunClock>,
+    mut sim_rng: ResMut<SimRng>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    println!("Restarting replay");
+    commands.add(RebuildBoard(playback.recording.blueprint.clone()));
+    *sim_rng = SimRng::from_seed(playback.recording.seed);
+    *stats = GameStats::default();
+    clock.ticks = 0;
+    playback.next_event = 0;
+    speed.paused = false;
+}
+
+fn play_recorded_spawns(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    clock: Res<RunClock>,
+    q_requests: Query<(), With<Request>>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    while let Some(event) = playback.recording.events.get(playback.next_event) {
+        // On-call changes were made in between steps, after the one they're
+        // recorded on
+        let due = match event {
+            RecordedEvent::Reconfigured { tick, .. } => *tick < clock.ticks,
+            _ => event.tick() <= clock.ticks,
+        };
+        if !due {
+            break;
+        }
+        match event {
+            RecordedEvent::Spawned { offset_x, size, .. } => {
+                commands.add(SpawnRequest {
+                    offset_x: *offset_x,
+                    size: *size,
+                });
+            }
+            RecordedEvent::Reconfigured {
+                server,
+                change,
+                delay,
+                ..
+            } => {
+                commands.add(Reconfigure {
+                    slot: *server,
+                    change: change.clone(),
+                    delay: *delay,
+                    enforce_budget: false,
+                });
+            }
+            _ => {}
+        }
+        playback.next_event += 1;
+    }
+
+    // Hold on the last frame once everything has played out
+    let all_played = playback.next_event >= playback.recording.events.len();
+    if all_played && clock.ticks >= playback.recording.duration && q_requests.is_empty() {
+        playback.seek_to = None;
+        speed.fast_forward = None;
+        speed.paused = true;
+    }
+}
+
+fn handle_replay_buttons(
+    mut evs: EventReader<ReplayButtonEvent>,
+    q_buttons: Query<&ReplayControlButton>,
+    mut evs_control: EventWriter<ReplayControlEvent>,
+) {
+    for ev in evs.read() {
+        if let Ok(button) = q_buttons.get(ev.0) {
+            evs_control.send(ReplayControlEvent(button.0));
+        }
+    }
+}
+
+fn replay_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut evs_control: EventWriter<ReplayControlEvent>,
+) {
+    let shortcuts = [
+        (KeyCode::ArrowLeft, ReplayControl::SkipBack),
+        (KeyCode::ArrowRight, ReplayControl::SkipForward),
+        (KeyCode::Escape, ReplayControl::Exit),
+    ];
+    for (key, control) in shortcuts {
+        if keys.just_pressed(key) {
+            evs_control.send(ReplayControlEvent(control));
+        }
+    }
+}
+
+fn apply_replay_controls(
+    mut evs: EventReader<ReplayControlEvent>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut speed: ResMut<SimulationSpeed>,
+    fixed_time: Res<Time<Fixed>>,
+    clock: Res<RunClock>,
+    mut evs_restart: EventWriter<RestartReplayEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let skip_ticks = (SKIP_SECONDS / fixed_time.timestep().as_secs_f32()) as u32;
+    for ev in evs.read() {
+        match ev.0 {
+            ReplayControl::SkipForward => {
+                playback.seek_to = Some(clock.ticks + skip_ticks);
+                speed.fast_forward = Some(SEEK_SPEED);
+            }
+            ReplayControl::SkipBack => {
+                // We can't run the simulation backwards, so start over and
+                // fast-forward to where we want to be
+                let target = clock.ticks.saturating_sub(skip_ticks);
+                evs_restart.send(RestartReplayEvent);
+                if target > 0 {
+                    playback.seek_to = Some(target);
+                    speed.fast_forward = Some(SEEK_SPEED);
+                }
+            }
+            ReplayControl::Restart => {
+                evs_restart.send(RestartReplayEvent);
+                playback.seek_to = None;
+                speed.fast_forward = None;
+            }
+            ReplayControl::Exit => {
+                next_state.set(playback.return_to);
+            }
+        }
+    }
+}
+
+fn finish_seeking(
+    mut playback: ResMut<ReplayPlayback>,
+    clock: Res<RunClock>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    if let Some(target) = playback.seek_to {
+        if clock.ticks >= target {
+            playback.seek_to = None;
+            speed.fast_forward = None;
+        }
+    }
+}
+
+fn exit_replay(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut stats: ResMut<GameStats>,
+    mut clock: ResMut<RunClock>,
+    mut traces: ResMut<TraceLog>,
+    q_board: Query<Entity, Or<(With<Server>, With<Ingress>, With<LevelOwned>)>>,
+) {
+    // Speed gets reset by the `SimSpeedPlugin` when leaving the simulation
+    match playback.before.take() {
+        Some(before) => {
+            commands.add(RebuildBoard(before.board));
+            // After the blueprint, which counts its own points and gives
+            // every server fresh stats
+            let (before_points, server_stats) = (before.points, before.server_stats);
+            commands.add(move |world: &mut World| {
+                *world.resource_mut::<UpgradePoints>() = before_points;
+                let mut q_stats = world.query::<(&BlueprintSlot, &mut ServerStats)>();
+                for (slot, mut stats) in q_stats.iter_mut(world) {
+                    if let Some(before) = server_stats.get(slot.0) {
+                        *stats = before.clone();
+                    }
+                }
+            });
+            *stats = before.stats;
+            clock.ticks = before.ticks;
+            *traces = before.traces;
+            commands.insert_resource(BackFromReplay);
+        }
+        None => {
+            for e in q_board.iter() {
+                commands.entity(e).despawn_recursive();
+            }
+        }
+    }
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+fn forget_replay(mut commands: Commands) {
+    commands.remove_resource::<BackFromReplay>();
+}
+
+fn spawn_replay_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands.spawn((
+        ReplayUI,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font_assets.titles.clone(),
+                font_size: 36.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            right: Val::Px(10.0),
+            ..default()
+        }),
+        ReplayTimelineText,
+    ));
+
+    let buttons = [
+        ("Restart", ReplayControl::Restart),
+        ("-10s", ReplayControl::SkipBack),
+        ("+10s", ReplayControl::SkipForward),
+        ("Exit", ReplayControl::Exit),
+    ];
+
+    commands
+        .spawn((
+            ReplayUI,
+            NodeBundle {
+                style: Style {
+                    // Sits right above the speed controls
+                    bottom: Val::Px(60.0),
+                    width: Val::Percent(100.0),
+                    height: Val::Px(50.0),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Row,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            for (label, control) in buttons {
+                let bg_color = match control {
+                    ReplayControl::Exit => ORANGE_400,
+                    _ => GREEN_400,
+                };
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(110.0),
+                                height: Val::Px(50.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: bg_color.into(),
+                            ..default()
+                        },
+                        ReplayControlButton(control),
+                        PickableBundle::default(),
+                        On::<Pointer<Click>>::send_event::<ReplayButtonEvent>(),
+                        Hoverable(bg_color, GREEN_600, GREEN_600),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section(
+                                label,
+                                TextStyle {
+                                    font_size: 20.0,
+                                    color: WHITE_SMOKE.into(),
+                                    ..default()
+                                },
+                            ),
+                            Pickable::IGNORE,
+                        ));
+                    });
+            }
+        });
+}
+
+fn update_replay_ui(
+    playback: Res<ReplayPlayback>,
+    clock: Res<RunClock>,
+    speed: Res<SimulationSpeed>,
+    fixed_time: Res<Time<Fixed>>,
+    mut q_text: Query<&mut Text, With<ReplayTimelineText>>,
+) {
+    let tick_seconds = fixed_time.timestep().as_secs_f32();
+    let (handled, dropped) = playback.recording.recorded_totals();
+    let status = if playback.seek_to.is_some() {
+        "seeking".to_string()
+    } else if speed.paused {
+        "paused".to_string()
+    } else {
+        format!("{}x", speed.speed)
+    };
+    if let Ok(mut text) = q_text.get_single_mut() {
+        text.sections[0].value = format!(
+            "Replay of level #{}\n{:.1}s / {:.1}s ({status})\nRecorded: {handled} handled, {dropped} dropped",
+            playback.recording.level + 1,
+            clock.ticks as f32 * tick_seconds,
+            playback.recording.duration as f32 * tick_seconds,
+        );
+    }
+}
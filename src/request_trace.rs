@@ -0,0 +1,565 @@
+use crate::prelude::*;
+use bevy::color::palettes::{css::WHITE_SMOKE, tailwind::*};
+
+// How many of the slowest requests the results screen lists
+pub const SLOWEST_TRACES: usize = 5;
+
+// Finished requests kept around for the inspector, the sandbox and endless
+// runs go on for a long time so the log can't keep all of them
+const RECENT_TRACES: usize = 200;
+
+// Width of the bar area in the waterfall
+const WATERFALL_WIDTH: f32 = 300.0;
+
+pub struct RequestTracePlugin;
+
+impl Plugin for RequestTracePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TraceLog>()
+            .init_resource::<InspectedTrace>()
+            .add_event::<InspectTraceEvent>()
+            .add_event::<CloseTraceEvent>()
+            .add_systems(OnEnter(GameState::Running), reset_traces)
+            .add_systems(OnEnter(GameState::Replay), reset_traces)
+            // After the FixedUpdate systems mark requests as done, before
+            // `handle_removals` despawns them in FixedLast
+            .add_systems(
+                FixedPostUpdate,
+                (
+                    reset_traces.run_if(on_event::<RestartReplayEvent>()),
+                    archive_traces,
+                )
+                    .chain()
+                    .run_if(in_state(Simulating)),
+            )
+            .add_systems(OnEnter(GameState::Results), spawn_slowest_traces_ui)
+            .add_systems(
+                OnExit(GameState::Results),
+                clear_entity_with::<SlowestTracesUI>,
+            )
+            .add_systems(OnExit(Simulating), close_inspector)
+            .add_systems(OnExit(GameState::Results), close_inspector)
+            .add_systems(
+                Update,
+                (
+                    inspect_trace.run_if(on_event::<InspectTraceEvent>()),
+                    close_inspector.run_if(on_event::<CloseTraceEvent>()),
+                    draw_waterfall,
+                )
+                    .chain()
+                    .run_if(in_state(Simulating).or_else(in_state(GameState::Results))),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanOutcome {
+    Processed,
+    Forwarded,
+    Dropped,
+}
+
+/// A request's time at one server, all times are the request's age
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceSpan {
+    pub server: Entity,
+    pub slot: Option<usize>,
+    pub mode: ServerMode,
+    pub arrived: f32,
+    // When the server started working on it, None while queued
+    pub started: Option<f32>,
+    pub finished: Option<f32>,
+    pub outcome: Option<SpanOutcome>,
+}
+
+impl TraceSpan {
+    /// Seconds spent waiting, up to `now` if it's still waiting
+    pub fn queued(&self, now: f32) -> f32 {
+        self.started.or(self.finished).unwrap_or(now) - self.arrived
+    }
+
+    /// Seconds spent being worked on, up to `now` if it still is
+    pub fn processing(&self, now: f32) -> f32 {
+        match self.started {
+            Some(started) => self.finished.unwrap_or(now) - started,
+            None => 0.0,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self.slot {
+            Some(slot) => format!("{:?} #{}", self.mode, slot + 1),
+            None => format!("{:?} {}", self.mode, self.server),
+        }
+    }
+}
+
+/// Everywhere a request has been, kept on the `Request` itself
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestTrace {
+    pub id: u64,
+    pub spans: Vec<TraceSpan>,
+    // Age when it got handled or dropped
+    pub finished: Option<f32>,
+    pub dropped: bool,
+}
+
+impl RequestTrace {
+    pub fn arrive(&mut self, server: Entity, slot: Option<usize>, mode: ServerMode, age: f32) {
+        self.spans.push(TraceSpan {
+            server,
+            slot,
+            mode,
+            arrived: age,
+            started: None,
+            finished: None,
+            outcome: None,
+        });
+    }
+
+    /// The server picked it up, does nothing if it already had
+    pub fn start(&mut self, age: f32) {
+        if let Some(span) = self.spans.last_mut() {
+            if span.started.is_none() && span.finished.is_none() {
+                span.started = Some(age);
+            }
+        }
+    }
+
+    pub fn finish_span(&mut self, outcome: SpanOutcome, age: f32) {
+        if let Some(span) = self.spans.last_mut() {
+            span.finished = Some(age);
+            span.outcome = Some(outcome);
+        }
+        if outcome != SpanOutcome::Forwarded {
+            self.finished = Some(age);
+            self.dropped = outcome == SpanOutcome::Dropped;
+        }
+    }
+
+    /// Seconds it's been sitting in the queue of the server it's at, 0.0 if
+    /// it isn't queued
+    pub fn waiting(&self, now: f32) -> f32 {
+        self.spans
+            .last()
+            .filter(|span| span.started.is_none() && span.finished.is_none())
+            .map_or(0.0, |span| span.queued(now))
+    }
+
+    /// Never made it to a server at all
+    pub fn drop_without_server(&mut self, age: f32) {
+        self.finished = Some(age);
+        self.dropped = true;
+    }
+
+    pub fn total(&self, now: f32) -> f32 {
+        self.finished.unwrap_or(now)
+    }
+}
+
+/// Traces of the requests that are done with, for this run. Only the
+/// slowest ones and the latest few are kept.
+#[derive(Resource, Default)]
+pub struct TraceLog {
+    pub next_id: u64,
+    // Handled ones, slowest first, at most `SLOWEST_TRACES`
+    slowest: Vec<RequestTrace>,
+    recent: VecDeque<RequestTrace>,
+}
+
+impl TraceLog {
+    pub fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn record(&mut self, trace: RequestTrace) {
+        let slower = |other: &RequestTrace| trace.total(0.0) > other.total(0.0);
+        if !trace.dropped
+            && (self.slowest.len() < SLOWEST_TRACES || self.slowest.last().is_some_and(slower))
+        {
+            let at = self
+                .slowest
+                .iter()
+                .position(slower)
+                .unwrap_or(self.slowest.len());
+            self.slowest.insert(at, trace.clone());
+            self.slowest.truncate(SLOWEST_TRACES);
+        }
+        if self.recent.len() >= RECENT_TRACES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(trace);
+    }
+
+    /// Handled requests that took the longest, slowest first
+    pub fn slowest(&self, count: usize) -> Vec<&RequestTrace> {
+        self.slowest.iter().take(count).collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&RequestTrace> {
+        self.recent
+            .iter()
+            .chain(&self.slowest)
+            .find(|trace| trace.id == id)
+    }
+}
+
+/// Id of the trace shown in the waterfall, if any
+#[derive(Resource, Default)]
+pub struct InspectedTrace(pub Option<u64>);
+
+/// Sent from a clicked request, or an entry in the slowest requests list
+#[derive(Event)]
+pub struct InspectTraceEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<Click>>> for InspectTraceEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        InspectTraceEvent(event.target)
+    }
+}
+
+#[derive(Event)]
+pub struct CloseTraceEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for CloseTraceEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        CloseTraceEvent
+    }
+}
+
+#[derive(Component)]
+pub struct SlowestTracesUI;
+
+#[derive(Component)]
+pub struct TraceInspectorUI;
+
+// An entry in the slowest requests list
+#[derive(Component)]
+struct TraceEntry(u64);
+
+fn reset_traces(mut log: ResMut<TraceLog>, mut inspected: ResMut<InspectedTrace>) {
+    *log = TraceLog::default();
+    inspected.0 = None;
+}
+
+fn archive_traces(
+    mut log: ResMut<TraceLog>,
+    query: Query<
+        (&Request, Option<Ref<DroppedRequest>>),
+        Or<(Added<ToRemove>, Added<DroppedRequest>)>,
+    >,
+) {
+    for (request, dropped) in query.iter() {
+        // Dropped requests get removed a while after they fall, by then
+        // we've already kept them
+        if dropped.map_or(true, |dropped| dropped.is_added()) {
+            log.record(request.trace.clone());
+        }
+    }
+}
+
+fn inspect_trace(
+    mut evs: EventReader<InspectTraceEvent>,
+    q_requests: Query<&Request>,
+    q_entries: Query<&TraceEntry>,
+    mut inspected: ResMut<InspectedTrace>,
+) {
+    for ev in evs.read() {
+        if let Ok(request) = q_requests.get(ev.0) {
+            inspected.0 = Some(request.trace.id);
+        } else if let Ok(entry) = q_entries.get(ev.0) {
+            inspected.0 = Some(entry.0);
+        }
+        println!("Inspecting request trace {:?}", inspected.0);
+    }
+}
+
+fn close_inspector(
+    mut commands: Commands,
+    mut inspected: ResMut<InspectedTrace>,
+    query: Query<Entity, With<TraceInspectorUI>>,
+) {
+    inspected.0 = None;
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Rebuilds the waterfall when the trace changes, or every tenth of a second
+// of age while the request is still on its way
+fn draw_waterfall(
+    mut commands: Commands,
+    inspected: Res<InspectedTrace>,
+    log: Res<TraceLog>,
+    q_requests: Query<&Request>,
+    q_ui: Query<Entity, With<TraceInspectorUI>>,
+    mut shown: Local<Option<(RequestTrace, i32)>>,
+) {
+    let Some(id) = inspected.0 else {
+        *shown = None;
+        return;
+    };
+    let live = q_requests.iter().find(|request| request.trace.id == id);
+    let (trace, now) = match (live, log.get(id)) {
+        (Some(request), _) => (&request.trace, request.age),
+        (None, Some(trace)) => (trace, trace.total(0.0)),
+        (None, None) => return,
+    };
+    let step = (now * 10.0) as i32;
+    if shown.as_ref() == Some(&(trace.clone(), step)) && !q_ui.is_empty() {
+        return;
+    }
+    *shown = Some((trace.clone(), step));
+
+    for entity in q_ui.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_waterfall(&mut commands, trace, now);
+}
+
+// Same scale as the running UI, where a second of age shows as 10ms
+fn as_ms(seconds: f32) -> f32 {
+    seconds * 10.0
+}
+
+fn spawn_waterfall(commands: &mut Commands, trace: &RequestTrace, now: f32) {
+    let total = trace.total(now).max(0.001);
+    let status = match (trace.finished, trace.dropped) {
+        (None, _) => "in flight",
+        (Some(_), true) => "dropped",
+        (Some(_), false) => "handled",
+    };
+    let text_style = |size: f32| TextStyle {
+        font_size: size,
+        color: WHITE_SMOKE.into(),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            TraceInspectorUI,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(60.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(15.0)),
+                    ..default()
+                },
+                background_color: Color::srgba(0.1, 0.1, 0.1, 0.95).into(),
+                z_index: ZIndex::Global(20),
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    format!(
+                        "Request #{}: {:.0} ms, {status}",
+                        trace.id,
+                        as_ms(trace.total(now))
+                    ),
+                    text_style(20.0),
+                ),
+                Pickable::IGNORE,
+            ));
+
+            if trace.spans.is_empty() {
+                parent.spawn((
+                    TextBundle::from_section("Never reached a server", text_style(16.0)),
+                    Pickable::IGNORE,
+                ));
+            }
+
+            for span in &trace.spans {
+                let queued = span.queued(now);
+                let processing = span.processing(now);
+                let (color, verb) = match span.outcome {
+                    Some(SpanOutcome::Processed) => (GREEN_400, "processed"),
+                    Some(SpanOutcome::Forwarded) => (BLUE_400, "forwarded"),
+                    Some(SpanOutcome::Dropped) => (RED_500, "dropped"),
+                    None => (GRAY_400, "working"),
+                };
+                let percent =
+                    |seconds: f32| Val::Percent((seconds / total * 100.0).clamp(0.0, 100.0));
+
+                parent
+                    .spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(10.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        Pickable::IGNORE,
+                    ))
+                    .with_children(|row| {
+                        row.spawn((
+                            TextBundle::from_section(span.label(), text_style(16.0)).with_style(
+                                Style {
+                                    width: Val::Px(100.0),
+                                    ..default()
+                                },
+                            ),
+                            Pickable::IGNORE,
+                        ));
+                        row.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Px(WATERFALL_WIDTH),
+                                    height: Val::Px(14.0),
+                                    ..default()
+                                },
+                                background_color: Color::srgba(1.0, 1.0, 1.0, 0.05).into(),
+                                ..default()
+                            },
+                            Pickable::IGNORE,
+                        ))
+                        .with_children(|bars| {
+                            // Waiting in the queue, then being worked on
+                            for (start, length, color) in [
+                                (span.arrived, queued, AMBER_400),
+                                (span.arrived + queued, processing, color),
+                            ] {
+                                bars.spawn((
+                                    NodeBundle {
+                                        style: Style {
+                                            position_type: PositionType::Absolute,
+                                            left: percent(start),
+                                            width: percent(length),
+                                            min_width: Val::Px(2.0),
+                                            height: Val::Percent(100.0),
+                                            ..default()
+                                        },
+                                        background_color: color.into(),
+                                        ..default()
+                                    },
+                                    Pickable::IGNORE,
+                                ));
+                            }
+                        });
+                        row.spawn((
+                            TextBundle::from_section(
+                                format!(
+                                    "queued {:.0} ms, {verb} {:.0} ms",
+                                    as_ms(queued),
+                                    as_ms(processing)
+                                ),
+                                text_style(16.0),
+                            ),
+                            Pickable::IGNORE,
+                        ));
+                    });
+            }
+
+            parent.spawn((
+                TextBundle::from_section(
+                    "Amber is time in the queue, gaps are time on the wire",
+                    TextStyle {
+                        font_size: 14.0,
+                        color: GRAY_400.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+            spawn_trace_button::<CloseTraceEvent, ()>(parent, "Close", ());
+        });
+}
+
+fn spawn_trace_button<T, U>(builder: &mut ChildBuilder, label: &str, component: U)
+where
+    U: Bundle,
+    T: Event + From<ListenerInput<Pointer<Click>>>,
+{
+    builder
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(10.0), Val::Px(5.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: BLUE_800.into(),
+                ..default()
+            },
+            component,
+            PickableBundle::default(),
+            On::<Pointer<Click>>::send_event::<T>(),
+            NoDeselect,
+            Hoverable(BLUE_800, BLUE_700, BLUE_600),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    label,
+                    TextStyle {
+                        font_size: 16.0,
+                        color: WHITE_SMOKE.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+        });
+}
+
+fn spawn_slowest_traces_ui(mut commands: Commands, log: Res<TraceLog>) {
+    let slowest = log.slowest(SLOWEST_TRACES);
+    if slowest.is_empty() {
+        return;
+    }
+    commands
+        .spawn((
+            SlowestTracesUI,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                background_color: Color::srgba(0.1, 0.1, 0.1, 0.95).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Slowest requests",
+                    TextStyle {
+                        font_size: 18.0,
+                        color: WHITE_SMOKE.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+            for trace in slowest {
+                let label = format!(
+                    "#{}: {:.0} ms, {} hops",
+                    trace.id,
+                    as_ms(trace.total(0.0)),
+                    trace.spans.len()
+                );
+                spawn_trace_button::<InspectTraceEvent, TraceEntry>(
+                    parent,
+                    &label,
+                    TraceEntry(trace.id),
+                );
+            }
+        });
+}
@@ -5,18 +5,10 @@ pub struct RequestsPlugin;
 
 impl Plugin for RequestsPlugin {
     fn build(&self, app: &mut App) {
+        // Only the looks of dropped requests falling off, moving requests
+        // around is part of `SimulationPlugin`
         app.add_plugins(PhysicsPlugins::default())
-            .insert_resource(Gravity(Vec2::NEG_Y * 100.0))
-            .add_systems(
-                FixedUpdate,
-                (
-                    assign_requests_to_closest_load_balancer,
-                    move_requests_to_destination,
-                    move_dropped_requests,
-                    increment_request_elapsed_time,
-                )
-                    .run_if(in_state(GameState::Running)),
-            );
+            .insert_resource(Gravity(Vec2::NEG_Y * 100.0));
         //.add_systems(Update, draw_children_ui);
         // app.register_component_as::<dyn Request, RequestPageView>()
         //     .register_component_as::<dyn Request, RequestPurchase>()
@@ -32,6 +24,28 @@ pub struct Request {
     pub destination: Option<Entity>,
     pub age: f32,
     pub size: usize,
+    pub trace: RequestTrace,
+}
+
+// Up to this size a request counts as interactive
+const INTERACTIVE_MAX_SIZE: usize = 16;
+
+/// Small requests are interactive (page views), big ones batch work, the
+/// priority queue discipline serves interactive ones first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RequestKind {
+    Interactive,
+    Batch,
+}
+
+impl RequestKind {
+    pub fn of(size: usize) -> Self {
+        if size <= INTERACTIVE_MAX_SIZE {
+            RequestKind::Interactive
+        } else {
+            RequestKind::Batch
+        }
+    }
 }
 
 impl Default for Request {
@@ -42,6 +56,7 @@ impl Default for Request {
             destination: None,
             age: 0.0,
             size,
+            trace: RequestTrace::default(),
         }
     }
 }
@@ -94,7 +109,10 @@ struct RequestPageView;
 #[derive(Component)]
 pub struct RequestInfoText;
 
-pub struct SpawnRequest {}
+pub struct SpawnRequest {
+    pub offset_x: f32,
+    pub size: usize,
+}
 
 impl Command for SpawnRequest {
     fn apply(self, world: &mut World) {
@@ -103,12 +121,39 @@ impl Command for SpawnRequest {
             handle
         });
 
-        let mut rng = rand::thread_rng();
-        let offset_x = rng.gen_range(-250.0..250.0);
+        let offset_x = self.offset_x;
         // let offset_y = rng.gen_range(-10.0..10.0);
 
+        // Keep track of it in case this run gets replayed later
+        let tick = world.resource::<RunClock>().ticks;
+        if let Some(mut recorder) = world.get_resource_mut::<RunRecorder>() {
+            recorder.push(RecordedEvent::Spawned {
+                tick,
+                offset_x,
+                size: self.size,
+            });
+        }
+
+        // Requests come in from the Internet if we have one, otherwise from the sky
+        let origin = world
+            .query_filtered::<&Transform, With<Ingress>>()
+            .iter(world)
+            .next()
+            .map(|t| t.translation.with_z(10.0));
+        let translation = match origin {
+            Some(origin) => origin + Vec3::new(offset_x / 10.0, 0.0, 0.0),
+            None => Vec3::new(offset_x, 300.0, 10.0),
+        };
+
         let component = RequestPageView {};
 
+        let trace = RequestTrace {
+            id: world
+                .get_resource_mut::<TraceLog>()
+                .map_or(0, |mut log| log.next_id()),
+            ..default()
+        };
+
         println!("Spawning RequestPageView");
 
         world.spawn((
@@ -116,12 +161,22 @@ impl Command for SpawnRequest {
             Name::new("RequestPageView"),
             SpriteBundle {
                 texture: handle,
-                transform: Transform::from_xyz(offset_x, 300.0, 10.0).with_scale(Vec3::splat(0.1)),
+                transform: Transform::from_translation(translation).with_scale(Vec3::splat(0.1)),
                 ..default()
             },
             component,
-            Request::default(),
-            Pickable::IGNORE,
+            Request {
+                size: self.size,
+                trace,
+                ..default()
+            },
+            // Clickable to inspect its trace, without getting in the way of
+            // the server it's sitting on
+            Pickable {
+                should_block_lower: false,
+                is_hoverable: true,
+            },
+            On::<Pointer<Click>>::send_event::<InspectTraceEvent>(),
         ));
         // .with_children(|subcommands| {
         //     subcommands.spawn((
@@ -162,56 +217,142 @@ fn draw_children_ui(
     }
 }
 
-fn find_closest(origin: Vec3, items: Vec<(Entity, Transform)>) -> Vec<(Entity, Vec3, f32)> {
-    let mut closest: Vec<(Entity, Vec3, f32)> = items
-        .into_iter()
-        .map(|(entity, transform)| {
-            let distance = transform.translation.distance(origin);
-            (entity, transform.translation, distance)
-        })
-        .collect();
-
-    closest.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
-    closest.truncate(10);
-    closest
+/// How many requests are on their way to each server, counted at the start
+/// of every step. With backpressure they take up room at a server before
+/// they get there, so it doesn't get sent more than it can take.
+#[derive(Resource, Default)]
+pub struct RequestsInFlight(pub HashMap<Entity, usize>);
+
+impl RequestsInFlight {
+    pub fn to(&self, e_server: Entity) -> usize {
+        self.0.get(&e_server).copied().unwrap_or(0)
+    }
+    pub fn sent(&mut self, e_server: Entity) {
+        *self.0.entry(e_server).or_default() += 1;
+    }
+    pub fn arrived(&mut self, e_server: Entity) {
+        if let Some(count) = self.0.get_mut(&e_server) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+pub(crate) fn count_requests_in_flight(
+    mut in_flight: ResMut<RequestsInFlight>,
+    q_requests: Query<&Request, (Without<Owned>, Without<DroppedRequest>)>,
+) {
+    in_flight.0.clear();
+    for request in q_requests.iter() {
+        if let Some(e_server) = request.destination {
+            in_flight.sent(e_server);
+        }
+    }
 }
 
-fn assign_requests_to_closest_load_balancer(
-    mut q_requests: Query<(&Transform, &mut Request)>,
-    q_servers: Query<(Entity, &Transform), With<Server>>,
+pub(crate) fn assign_requests_to_ingress(
+    mut commands: Commands,
+    mut q_requests: Query<(Entity, &mut Request), Without<DroppedRequest>>,
+    q_servers: Query<(&Server, Has<Reconfiguring>)>,
+    mut q_ingress: Query<&mut Ingress>,
+    mut in_flight: ResMut<RequestsInFlight>,
+    mut stats: ResMut<GameStats>,
+    mut evs: EventWriter<PlaySound>,
 ) {
-    for (transform, mut request) in q_requests.iter_mut() {
+    // Records of servers that are gone would send requests nowhere
+    if let Ok(mut ingress) = q_ingress.get_single_mut() {
+        if ingress.outputs.iter().any(|e| !q_servers.contains(*e)) {
+            let live = ingress
+                .outputs
+                .iter()
+                .copied()
+                .filter(|e| q_servers.contains(*e))
+                .collect();
+            ingress.set_outputs(live);
+        }
+    }
+
+    for (e_request, mut request) in q_requests.iter_mut() {
         if request.destination.is_some() {
             continue; // No need to adjust destination
         }
-        let items: Vec<(Entity, Transform)> = q_servers.iter().map(|(e, t)| (e, *t)).collect();
-
-        let closest_n = find_closest(transform.translation, items);
 
-        let (e_server, _t_server, _distance) = closest_n.first().unwrap();
+        // Only the DNS records decide where requests go in
+        if let Ok(mut ingress) = q_ingress.get_single_mut() {
+            let weights: Vec<usize> = ingress
+                .outputs
+                .iter()
+                .map(|e_output| match q_servers.get(*e_output) {
+                    Ok((server, _)) => server.processing_power,
+                    Err(_) => 0,
+                })
+                .collect();
+            // Only the servers in the DNS records can push back on the
+            // Internet, it skips the ones without room like a proxy does
+            let tries = if ingress.backpressure {
+                ingress.outputs.len()
+            } else {
+                1
+            };
+            for _ in 0..tries {
+                let Some(e_server) = ingress.next_output(&weights) else {
+                    break;
+                };
+                let has_room = match q_servers.get(e_server) {
+                    Ok((server, reconfiguring)) => {
+                        !reconfiguring && server.free_slots() > in_flight.to(e_server)
+                    }
+                    Err(_) => false,
+                };
+                if has_room || !ingress.backpressure {
+                    request.destination = Some(e_server);
+                    in_flight.sent(e_server);
+                    break;
+                }
+            }
+        }
 
-        request.destination = Some(*e_server);
+        // No DNS records, or the pressure made it all the way back: turn it
+        // away here rather than at a server
+        if request.destination.is_none() {
+            let age = request.age;
+            request.trace.drop_without_server(age);
+            commands.entity(e_request).insert(DroppedRequest);
+            stats.dropped_requests += 1;
+            stats.dropped_at_edge += 1;
+            evs.send(PlaySound(Sound::DroppedRequest));
+        }
     }
 }
 
-fn move_requests_to_destination(
+pub(crate) fn move_requests_to_destination(
     mut commands: Commands,
     time: Res<Time>,
     mut q_requests: Query<
         (Entity, &mut Transform, &mut Request),
         (Without<Owned>, Without<DroppedRequest>),
     >,
-    mut q_target: Query<(Entity, &Transform, &mut Server), Without<Request>>,
+    mut q_target: Query<
+        (
+            Entity,
+            &Transform,
+            &mut Server,
+            &mut ServerStats,
+            Option<&BlueprintSlot>,
+            Has<Reconfiguring>,
+        ),
+        Without<Request>,
+    >,
+    mut in_flight: ResMut<RequestsInFlight>,
     mut stats: ResMut<GameStats>,
     mut evs: EventWriter<PlaySound>,
 ) {
-    for (e_request, mut t_request, request) in q_requests.iter_mut() {
+    for (e_request, mut t_request, mut request) in q_requests.iter_mut() {
         if request.destination.is_none() {
             continue; // We don't have any destination ?!
         }
         let speed = 128.0;
 
-        let (e_target, t_target, mut server) =
+        let (e_target, t_target, mut server, mut server_stats, slot, reconfiguring) =
             q_target.get_mut(request.destination.unwrap()).unwrap();
 
         if t_request.translation.distance(t_target.translation) > 1.0 {
@@ -220,21 +361,33 @@ fn move_requests_to_destination(
             t_request.translation += direction * speed * time.delta_seconds();
         } else {
             println!("Move done!");
-            if server.is_busy() {
-                // Drop request
+            let age = request.age;
+            request
+                .trace
+                .arrive(e_target, slot.map(|s| s.0), server.mode, age);
+            server_stats.arrived += 1;
+            in_flight.arrived(e_target);
+            if reconfiguring || server.is_busy() {
+                request.trace.finish_span(SpanOutcome::Dropped, age);
+                // Drop request, nobody's home while a server is reconfiguring
                 commands.entity(e_request).insert(DroppedRequest);
                 stats.dropped_requests += 1;
+                server_stats.dropped += 1;
                 evs.send(PlaySound(Sound::DroppedRequest));
             } else {
                 t_request.translation = t_target.translation.with_z(10.0);
                 commands.entity(e_request).insert(Owned { by: e_target });
-                server.add_request(e_request);
+                if server.current_request.is_none() {
+                    // Goes straight in, no queueing
+                    request.trace.start(age);
+                }
+                server.add_request(e_request, request.size);
             }
         }
     }
 }
 
-fn move_dropped_requests(
+pub(crate) fn move_dropped_requests(
     time: Res<Time>,
     mut commands: Commands,
     mut query: Query<(Entity, &Transform, Option<&RigidBody>), With<DroppedRequest>>,
@@ -308,7 +461,7 @@ fn move_dropped_requests(
     }
 }
 
-fn increment_request_elapsed_time(time: Res<Time>, mut query: Query<&mut Request>) {
+pub(crate) fn increment_request_elapsed_time(time: Res<Time>, mut query: Query<&mut Request>) {
     for mut request in query.iter_mut() {
         request.age += time.delta_seconds();
     }
@@ -1,15 +1,7 @@
 use crate::prelude::*;
+use serde::Serialize;
 
-pub struct ResultsPlugin;
-
-impl Plugin for ResultsPlugin {
-    fn build(&self, app: &mut App) {
-        app.init_resource::<LevelResults>()
-            .add_systems(OnEnter(GameState::Results), calculate_pass_or_not);
-    }
-}
-
-#[derive(Resource, Default)]
+#[derive(Resource, Default, Serialize)]
 pub struct LevelResults {
     //
     pub pass_percentage: f32,
@@ -17,11 +9,39 @@ pub struct LevelResults {
     //
     pub pass_avg_response_time: f32,
     pub current_avg_response_time: f32,
+    // Only for levels with an SLO
+    pub slo: Option<SloReport>,
+    // Required ones first, then the bonus ones
+    pub objectives: Vec<ObjectiveResult>,
     //
     pub passed: bool,
 }
 
-fn calculate_pass_or_not(game_stats: Res<GameStats>, mut level_results: ResMut<LevelResults>) {
+impl LevelResults {
+    pub fn required_objectives_met(&self) -> bool {
+        self.objectives
+            .iter()
+            .all(|objective| objective.bonus || objective.passed)
+    }
+
+    pub fn bonus_objectives_met(&self) -> usize {
+        self.objectives
+            .iter()
+            .filter(|objective| objective.bonus && objective.passed)
+            .count()
+    }
+}
+
+pub fn calculate_pass_or_not(
+    game_stats: Res<GameStats>,
+    slo_tracker: Res<SloTracker>,
+    game_levels: Res<GameLevels>,
+    points: Res<UpgradePoints>,
+    clock: Res<RunClock>,
+    fixed_time: Res<Time<Fixed>>,
+    q_servers: Query<(&Server, &ServerStats)>,
+    mut level_results: ResMut<LevelResults>,
+) {
     let handled = game_stats.handled_requests;
     let dropped = game_stats.dropped_requests;
     let total_requests = handled + dropped;
@@ -33,13 +53,45 @@ fn calculate_pass_or_not(game_stats: Res<GameStats>, mut level_results: ResMut<L
     let passed_avg_response_times =
         game_stats.avg_response_time <= level_results.pass_avg_response_time;
 
-    level_results.passed = passed_handled_percentage && passed_avg_response_times;
+    level_results.slo = slo_tracker.report();
+    let passed_slo = level_results.slo.as_ref().map_or(true, |report| report.met);
+
+    let run = RunSummary {
+        stats: &game_stats,
+        points_spent: points.assigned,
+        servers: q_servers
+            .iter()
+            .map(|(server, stats)| (server.mode, stats.clone()))
+            .collect(),
+        elapsed: run_elapsed(&clock, &fixed_time),
+    };
+    level_results.objectives = check_objectives(game_levels.active_level(), &run);
+    let passed_objectives = level_results.required_objectives_met();
+
+    level_results.passed =
+        passed_handled_percentage && passed_avg_response_times && passed_slo && passed_objectives;
 
     println!(
         "Avg resposne times: achieved/required {:.2}/{:.2}",
         game_stats.avg_response_time, level_results.pass_avg_response_time
     );
 
+    if let Some(report) = &level_results.slo {
+        for line in report.explain() {
+            println!("{line}");
+        }
+    }
+
+    for objective in &level_results.objectives {
+        println!(
+            "{}{}: {} ({})",
+            if objective.bonus { "Bonus: " } else { "" },
+            objective.description,
+            if objective.passed { "passed" } else { "failed" },
+            objective.achieved
+        );
+    }
+
     if level_results.passed {
         println!(
             "Level passed! Current percentage: {:.2}%, Required: {:.2}%",
@@ -0,0 +1,675 @@
+use crate::prelude::*;
+use bevy::color::palettes::{
+    css::{BLACK, WHITE_SMOKE},
+    tailwind::*,
+};
+
+const MAX_SANDBOX_SERVERS: usize = 30;
+const MAX_SANDBOX_BUDGET: usize = 200;
+// Where the RPS slider tops out
+const MAX_SANDBOX_RPS: usize = 60;
+// Servers per row when laying out the board
+const SERVERS_PER_ROW: usize = 10;
+// Chaos: a random server goes down every so often
+const OUTAGE_EVERY: u64 = 8;
+const OUTAGE_LENGTH: f32 = 3.0;
+// Chaos: traffic triples for a bit every so often, in seconds
+const SPIKE_EVERY: f32 = 15.0;
+const SPIKE_LENGTH: f32 = 3.0;
+const SPIKE_MULTIPLIER: f32 = 3.0;
+
+pub struct SandboxPlugin;
+
+impl Plugin for SandboxPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SandboxSettings>()
+            .init_resource::<SandboxLoad>()
+            .add_event::<OpenSandboxEvent>()
+            .add_event::<SandboxButtonEvent>()
+            .add_event::<SandboxSliderEvent>()
+            .add_event::<RebuildSandboxEvent>()
+            .add_systems(
+                OnEnter(GameState::Sandbox),
+                (spawn_sandbox_ui, start_sandbox),
+            )
+            .add_systems(
+                OnExit(GameState::Sandbox),
+                (clear_entity_with::<SandboxUI>, clear_sandbox),
+            )
+            .add_systems(
+                Update,
+                open_sandbox
+                    .run_if(on_event::<OpenSandboxEvent>())
+                    .run_if(in_state(GameState::LevelSelect)),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_sandbox_buttons,
+                    handle_rps_slider,
+                    rebuild_sandbox.run_if(on_event::<RebuildSandboxEvent>()),
+                    update_sandbox_ui,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Sandbox)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    spawn_sandbox_requests.run_if(on_timer(Duration::from_millis(100))),
+                    take_random_server_down
+                        .run_if(on_timer(Duration::from_secs(OUTAGE_EVERY)))
+                        .run_if(|settings: Res<SandboxSettings>| settings.outages),
+                )
+                    .run_if(in_state(GameState::Sandbox)),
+            );
+    }
+}
+
+/// How the load changes over time, the RPS slider sets the top of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadShape {
+    Constant,
+    // Up and down every 20s
+    Wave,
+    // Climbs to the top over 30s, then starts over
+    Sawtooth,
+    // Full load for 2s every 10s, a trickle otherwise
+    Bursts,
+}
+
+impl LoadShape {
+    /// Share of the top RPS sent `elapsed` seconds in
+    pub fn factor(&self, elapsed: f32) -> f32 {
+        match self {
+            LoadShape::Constant => 1.0,
+            LoadShape::Wave => 0.5 + 0.5 * (elapsed * std::f32::consts::TAU / 20.0).sin(),
+            LoadShape::Sawtooth => (elapsed % 30.0) / 30.0,
+            LoadShape::Bursts => {
+                if elapsed % 10.0 < 2.0 {
+                    1.0
+                } else {
+                    0.2
+                }
+            }
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            LoadShape::Constant => LoadShape::Wave,
+            LoadShape::Wave => LoadShape::Sawtooth,
+            LoadShape::Sawtooth => LoadShape::Bursts,
+            LoadShape::Bursts => LoadShape::Constant,
+        }
+    }
+}
+
+/// What kind of requests come in, by size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestMix {
+    Small,
+    Mixed,
+    Large,
+}
+
+impl RequestMix {
+    pub fn sizes(&self) -> Range<usize> {
+        match self {
+            RequestMix::Small => 1..8,
+            // Same as the campaign
+            RequestMix::Mixed => 1..32,
+            RequestMix::Large => 16..32,
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            RequestMix::Small => RequestMix::Mixed,
+            RequestMix::Mixed => RequestMix::Large,
+            RequestMix::Large => RequestMix::Small,
+        }
+    }
+}
+
+/// The knobs of the sandbox. Everything but the server count applies to
+/// the running simulation, changing that rebuilds the board.
+#[derive(Resource, Debug, Clone)]
+pub struct SandboxSettings {
+    pub servers: usize,
+    pub budget: usize,
+    pub rps: usize,
+    pub shape: LoadShape,
+    pub mix: RequestMix,
+    pub outages: bool,
+    pub spikes: bool,
+}
+
+impl Default for SandboxSettings {
+    fn default() -> Self {
+        Self {
+            servers: 4,
+            budget: 20,
+            rps: 10,
+            shape: LoadShape::Constant,
+            mix: RequestMix::Mixed,
+            outages: false,
+            spikes: false,
+        }
+    }
+}
+
+impl SandboxSettings {
+    /// Requests per second sent `elapsed` seconds in
+    pub fn rps_at(&self, elapsed: f32) -> f32 {
+        let rps = self.rps as f32 * self.shape.factor(elapsed);
+        if self.spikes && elapsed % SPIKE_EVERY > SPIKE_EVERY - SPIKE_LENGTH {
+            rps * SPIKE_MULTIPLIER
+        } else {
+            rps
+        }
+    }
+}
+
+// Same idea as `LoadSchedule`, minus the end
+#[derive(Resource, Default)]
+struct SandboxLoad {
+    elapsed: f32,
+    accumulated_requests: f32,
+}
+
+#[derive(Component)]
+pub struct SandboxUI;
+
+#[derive(Component)]
+struct RpsSliderFill;
+
+/// What a button on the sandbox panel does
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub enum SandboxAction {
+    LessServers,
+    MoreServers,
+    LessBudget,
+    MoreBudget,
+    CycleShape,
+    CycleMix,
+    ToggleOutages,
+    ToggleSpikes,
+    ResetStats,
+    Leave,
+}
+
+// Texts that show the current value of a knob
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+enum SandboxLabel {
+    Servers,
+    Budget,
+    Rps,
+    Shape,
+    Mix,
+    Outages,
+    Spikes,
+}
+
+impl SandboxLabel {
+    fn text(&self, settings: &SandboxSettings, points: &UpgradePoints) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        match self {
+            SandboxLabel::Servers => format!("Servers: {}", settings.servers),
+            SandboxLabel::Budget => {
+                format!("Budget: {} ({} left)", settings.budget, points.available())
+            }
+            SandboxLabel::Rps => format!("Top RPS: {}", settings.rps),
+            SandboxLabel::Shape => format!("Load: {:?}", settings.shape),
+            SandboxLabel::Mix => format!("Requests: {:?}", settings.mix),
+            SandboxLabel::Outages => format!("Chaos, Outages: {}", on_off(settings.outages)),
+            SandboxLabel::Spikes => format!("Chaos, Spikes: {}", on_off(settings.spikes)),
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct OpenSandboxEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for OpenSandboxEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        OpenSandboxEvent
+    }
+}
+
+#[derive(Event)]
+pub struct SandboxButtonEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<Click>>> for SandboxButtonEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        SandboxButtonEvent(event.target)
+    }
+}
+
+// Where on the slider the pointer is, on screen
+#[derive(Event)]
+pub struct SandboxSliderEvent(pub Entity, pub Vec2);
+
+impl From<ListenerInput<Pointer<Click>>> for SandboxSliderEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        SandboxSliderEvent(event.target, event.pointer_location.position)
+    }
+}
+
+impl From<ListenerInput<Pointer<Drag>>> for SandboxSliderEvent {
+    fn from(event: ListenerInput<Pointer<Drag>>) -> Self {
+        SandboxSliderEvent(event.target, event.pointer_location.position)
+    }
+}
+
+// Server count changed, start over with a fresh board
+#[derive(Event)]
+struct RebuildSandboxEvent;
+
+fn open_sandbox(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Sandbox);
+}
+
+/// Every server wired to the Internet, nothing upgraded yet
+pub fn sandbox_blueprint(servers: usize) -> Blueprint {
+    Blueprint {
+        level: 0,
+        servers: (0..servers)
+            .map(|i| BlueprintServer {
+                x: -450.0 + (i % SERVERS_PER_ROW) as f32 * 100.0,
+                y: -((i / SERVERS_PER_ROW) as f32) * 100.0,
+                mode: ServerMode::Process,
+                processing_power: 1,
+                queue_size: 0,
+                discipline: QueueDiscipline::Fifo,
+                outputs: vec![],
+            })
+            .collect(),
+        ingress: (0..servers).collect(),
+        ingress_algorithm: LoadBalancingAlgorithm::Weighted,
+        backpressure: false,
+    }
+}
+
+fn start_sandbox(mut evs_rebuild: EventWriter<RebuildSandboxEvent>) {
+    println!("Starting sandbox");
+    evs_rebuild.send(RebuildSandboxEvent);
+}
+
+fn rebuild_sandbox(
+    mut commands: Commands,
+    settings: Res<SandboxSettings>,
+    query: Query<Entity, Or<(With<Server>, With<Ingress>, With<Request>, With<LevelOwned>)>>,
+    mut load: ResMut<SandboxLoad>,
+    mut game_stats: ResMut<GameStats>,
+    mut points: ResMut<UpgradePoints>,
+) {
+    println!("Building sandbox with {} servers", settings.servers);
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    commands.add(SpawnIngress);
+    commands.add(ApplyBlueprint(sandbox_blueprint(settings.servers)));
+    *load = SandboxLoad::default();
+    *game_stats = GameStats::default();
+    points.total = settings.budget;
+    points.assigned = 0;
+    points.reserved = 0;
+}
+
+// Don't leave requests flying around the level select
+fn clear_sandbox(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<Server>, With<Ingress>, With<Request>)>>,
+) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn spawn_sandbox_requests(
+    mut commands: Commands,
+    settings: Res<SandboxSettings>,
+    mut load: ResMut<SandboxLoad>,
+    mut sim_rng: ResMut<SimRng>,
+) {
+    // Never completes, keeps going until the player leaves
+    load.elapsed += 0.1;
+    load.accumulated_requests += settings.rps_at(load.elapsed) / 10.0;
+    let requests_to_spawn = load.accumulated_requests.floor() as usize;
+    load.accumulated_requests -= requests_to_spawn as f32;
+
+    for _ in 0..requests_to_spawn {
+        commands.add(SpawnRequest {
+            offset_x: sim_rng.0.gen_range(-250.0..250.0),
+            size: sim_rng.0.gen_range(settings.mix.sizes()),
+        });
+    }
+}
+
+fn take_random_server_down(
+    mut commands: Commands,
+    q_servers: Query<(Entity, Option<&BlueprintSlot>), (With<Server>, Without<Reconfiguring>)>,
+    mut sim_rng: ResMut<SimRng>,
+) {
+    let servers: Vec<(Entity, Option<&BlueprintSlot>)> = q_servers.iter().collect();
+    let Some((e_server, slot)) = servers.choose(&mut sim_rng.0) else {
+        return;
+    };
+    println!(
+        "Chaos: server in slot {:?} is down for {OUTAGE_LENGTH}s",
+        slot.map(|s| s.0)
+    );
+    // Same as being reconfigured, it doesn't take or work on requests
+    commands
+        .entity(*e_server)
+        .insert(Reconfiguring(Timer::from_seconds(
+            OUTAGE_LENGTH,
+            TimerMode::Once,
+        )));
+}
+
+fn handle_sandbox_buttons(
+    mut evs: EventReader<SandboxButtonEvent>,
+    q_buttons: Query<&SandboxAction>,
+    mut settings: ResMut<SandboxSettings>,
+    mut points: ResMut<UpgradePoints>,
+    mut game_stats: ResMut<GameStats>,
+    mut evs_rebuild: EventWriter<RebuildSandboxEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for ev in evs.read() {
+        let Ok(action) = q_buttons.get(ev.0) else {
+            continue;
+        };
+        match action {
+            SandboxAction::LessServers | SandboxAction::MoreServers => {
+                let servers = match action {
+                    SandboxAction::LessServers => settings.servers.saturating_sub(1).max(1),
+                    _ => (settings.servers + 1).min(MAX_SANDBOX_SERVERS),
+                };
+                if servers != settings.servers {
+                    settings.servers = servers;
+                    evs_rebuild.send(RebuildSandboxEvent);
+                }
+            }
+            SandboxAction::LessBudget => {
+                // Can't take back points already spent
+                settings.budget = settings.budget.saturating_sub(5).max(points.assigned);
+                points.total = settings.budget;
+            }
+            SandboxAction::MoreBudget => {
+                settings.budget = (settings.budget + 5).min(MAX_SANDBOX_BUDGET);
+                points.total = settings.budget;
+            }
+            SandboxAction::CycleShape => settings.shape = settings.shape.next(),
+            SandboxAction::CycleMix => settings.mix = settings.mix.next(),
+            SandboxAction::ToggleOutages => settings.outages = !settings.outages,
+            SandboxAction::ToggleSpikes => settings.spikes = !settings.spikes,
+            SandboxAction::ResetStats => *game_stats = GameStats::default(),
+            SandboxAction::Leave => next_state.set(GameState::LevelSelect),
+        }
+    }
+}
+
+fn handle_rps_slider(
+    mut evs: EventReader<SandboxSliderEvent>,
+    q_slider: Query<(&Node, &GlobalTransform)>,
+    mut settings: ResMut<SandboxSettings>,
+) {
+    for ev in evs.read() {
+        let Ok((node, transform)) = q_slider.get(ev.0) else {
+            continue;
+        };
+        // UI transforms are the center of the node
+        let width = node.size().x;
+        let left = transform.translation().x - width / 2.0;
+        let fraction = ((ev.1.x - left) / width).clamp(0.0, 1.0);
+        let rps = (fraction * MAX_SANDBOX_RPS as f32).round() as usize;
+        if rps != settings.rps {
+            settings.rps = rps;
+        }
+    }
+}
+
+fn update_sandbox_ui(
+    settings: Res<SandboxSettings>,
+    points: Res<UpgradePoints>,
+    mut q_labels: Query<(&SandboxLabel, &mut Text)>,
+    mut q_fill: Query<&mut Style, With<RpsSliderFill>>,
+    q_new: Query<(), Added<SandboxLabel>>,
+) {
+    if !settings.is_changed() && !points.is_changed() && q_new.is_empty() {
+        return;
+    }
+    for (label, mut text) in q_labels.iter_mut() {
+        text.sections[0].value = label.text(&settings, &points);
+    }
+    if let Ok(mut style) = q_fill.get_single_mut() {
+        style.width = Val::Percent(settings.rps as f32 / MAX_SANDBOX_RPS as f32 * 100.0);
+    }
+}
+
+fn spawn_label(parent: &mut ChildBuilder, label: SandboxLabel) {
+    parent.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: WHITE_SMOKE.into(),
+                ..default()
+            },
+        ),
+        label,
+        Pickable::IGNORE,
+    ));
+}
+
+fn spawn_sandbox_button(
+    parent: &mut ChildBuilder,
+    action: SandboxAction,
+    label: Option<SandboxLabel>,
+    text: &str,
+    width: Val,
+    (bg_color, hover_color): (Srgba, Srgba),
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width,
+                    height: Val::Px(32.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: bg_color.into(),
+                ..default()
+            },
+            action,
+            PickableBundle::default(),
+            On::<Pointer<Click>>::send_event::<SandboxButtonEvent>(),
+            Hoverable(bg_color, hover_color, hover_color),
+            NoDeselect,
+        ))
+        .with_children(|parent| {
+            let mut text = parent.spawn((
+                TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font_size: 18.0,
+                        color: BLACK.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+            if let Some(label) = label {
+                text.insert(label);
+            }
+        });
+}
+
+// A label with - and + next to it
+fn spawn_stepper(
+    parent: &mut ChildBuilder,
+    label: SandboxLabel,
+    less: SandboxAction,
+    more: SandboxAction,
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(6.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            spawn_label(parent, label);
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(6.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_sandbox_button(
+                        parent,
+                        less,
+                        None,
+                        "-",
+                        Val::Px(32.0),
+                        (BLUE_200, BLUE_300),
+                    );
+                    spawn_sandbox_button(
+                        parent,
+                        more,
+                        None,
+                        "+",
+                        Val::Px(32.0),
+                        (BLUE_200, BLUE_300),
+                    );
+                });
+        });
+}
+
+fn spawn_sandbox_ui(mut commands: Commands) {
+    println!("Spawning sandbox UI");
+    commands
+        .spawn((
+            SandboxUI,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    top: Val::Px(170.0),
+                    width: Val::Px(320.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(8.0),
+                    padding: UiRect::all(Val::Px(12.0)),
+                    ..default()
+                },
+                background_color: Color::srgba(0.15, 0.15, 0.15, 0.9).into(),
+                ..default()
+            },
+            NoDeselect,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Sandbox",
+                    TextStyle {
+                        font_size: 24.0,
+                        color: WHITE_SMOKE.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+            spawn_stepper(
+                parent,
+                SandboxLabel::Servers,
+                SandboxAction::LessServers,
+                SandboxAction::MoreServers,
+            );
+            spawn_stepper(
+                parent,
+                SandboxLabel::Budget,
+                SandboxAction::LessBudget,
+                SandboxAction::MoreBudget,
+            );
+
+            // The RPS slider, click or drag anywhere on it
+            spawn_label(parent, SandboxLabel::Rps);
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Px(18.0),
+                            ..default()
+                        },
+                        background_color: GRAY_600.into(),
+                        ..default()
+                    },
+                    PickableBundle::default(),
+                    On::<Pointer<Click>>::send_event::<SandboxSliderEvent>(),
+                    On::<Pointer<Drag>>::send_event::<SandboxSliderEvent>(),
+                    NoDeselect,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: ORANGE_400.into(),
+                            ..default()
+                        },
+                        RpsSliderFill,
+                        Pickable::IGNORE,
+                    ));
+                });
+
+            let full = Val::Percent(100.0);
+            for (action, label) in [
+                (SandboxAction::CycleShape, SandboxLabel::Shape),
+                (SandboxAction::CycleMix, SandboxLabel::Mix),
+                (SandboxAction::ToggleOutages, SandboxLabel::Outages),
+                (SandboxAction::ToggleSpikes, SandboxLabel::Spikes),
+            ] {
+                spawn_sandbox_button(
+                    parent,
+                    action,
+                    Some(label),
+                    "",
+                    full,
+                    (GREEN_400, GREEN_500),
+                );
+            }
+            spawn_sandbox_button(
+                parent,
+                SandboxAction::ResetStats,
+                None,
+                "Reset Stats",
+                full,
+                (YELLOW_400, YELLOW_500),
+            );
+            spawn_sandbox_button(
+                parent,
+                SandboxAction::Leave,
+                None,
+                "Back to Levels",
+                full,
+                (ORANGE_400, ORANGE_500),
+            );
+        });
+}
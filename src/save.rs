@@ -0,0 +1,334 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Bump this whenever the format changes, and add a step to `migrate`
+pub const SAVE_VERSION: u32 = 1;
+const SAVE_KEY: &str = "save";
+// How many endless runs the high-score table keeps
+const ENDLESS_SCORES_KEPT: usize = 10;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SaveGame::load())
+            .add_systems(Startup, restore_campaign_progress)
+            .add_systems(
+                OnEnter(GameState::Results),
+                record_level_result
+                    .after(calculate_pass_or_not)
+                    // Trying out a level from the editor isn't campaign progress,
+                    // neither is an endless run
+                    .run_if(not(resource_exists::<EditorTestPlay>))
+                    .run_if(not(resource_exists::<EndlessRun>))
+                    // Already saved before the replay
+                    .run_if(not(resource_exists::<BackFromReplay>)),
+            )
+            .add_systems(
+                OnEnter(GameState::Results),
+                record_endless_score
+                    .run_if(resource_exists::<EndlessRun>)
+                    .run_if(not(resource_exists::<BackFromReplay>)),
+            )
+            .add_systems(
+                Update,
+                toggle_mute
+                    .run_if(input_just_pressed(KeyCode::KeyM))
+                    .run_if(not(editor_typing)),
+            );
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub volume: f32,
+    pub muted: bool,
+    // Seconds a server is offline after being changed mid-run, None when
+    // on-call mode is off
+    pub on_call_delay: Option<f32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            muted: false,
+            on_call_delay: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn effective_volume(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+}
+
+/// The best run a player has had on a level
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LevelBest {
+    pub passed: bool,
+    // 0.0 <> 1.0 how many percent of requests got handled
+    pub handled_percentage: f32,
+    // Same unit as `GameStats::avg_response_time`
+    pub avg_response_time: f32,
+    pub points_spent: usize,
+    // How many of the level's bonus objectives were met
+    #[serde(default)]
+    pub bonus_objectives: usize,
+}
+
+impl LevelBest {
+    // Passing beats failing, then more bonus objectives, then more handled,
+    // then faster, then cheaper
+    pub fn is_better_than(&self, other: &LevelBest) -> bool {
+        if self.passed != other.passed {
+            return self.passed;
+        }
+        if self.bonus_objectives != other.bonus_objectives {
+            return self.bonus_objectives > other.bonus_objectives;
+        }
+        if self.handled_percentage != other.handled_percentage {
+            return self.handled_percentage > other.handled_percentage;
+        }
+        if self.avg_response_time != other.avg_response_time {
+            return self.avg_response_time < other.avg_response_time;
+        }
+        self.points_spent < other.points_spent
+    }
+}
+
+/// How far an endless run got
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EndlessScore {
+    pub waves_survived: usize,
+    pub requests_served: usize,
+}
+
+impl EndlessScore {
+    // More waves first, then more requests
+    pub fn is_better_than(&self, other: &EndlessScore) -> bool {
+        (self.waves_survived, self.requests_served) > (other.waves_survived, other.requests_served)
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SaveGame {
+    pub version: u32,
+    // How many levels (counting from the first) the player can play
+    pub unlocked_levels: usize,
+    // Indexed the same way as `GameLevels::levels`
+    pub best_results: Vec<Option<LevelBest>>,
+    pub settings: Settings,
+    // Best first
+    pub endless_scores: Vec<EndlessScore>,
+}
+
+impl Default for SaveGame {
+    fn default() -> Self {
+        Self {
+            version: SAVE_VERSION,
+            unlocked_levels: 1,
+            best_results: vec![],
+            settings: Settings::default(),
+            endless_scores: vec![],
+        }
+    }
+}
+
+impl SaveGame {
+    pub fn load() -> Self {
+        let Some(contents) = read_storage(SAVE_KEY) else {
+            println!("No save game found, starting fresh");
+            return SaveGame::default();
+        };
+        match Self::from_json(&contents) {
+            Some(save) => save,
+            None => {
+                println!("Couldn't read save game, starting fresh");
+                SaveGame::default()
+            }
+        }
+    }
+
+    pub fn write(&self) {
+        match serde_json::to_string(self) {
+            Ok(contents) => write_storage(SAVE_KEY, &contents),
+            Err(err) => println!("Couldn't serialize save game: {err}"),
+        }
+    }
+
+    pub fn from_json(contents: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(contents).ok()?;
+        let value = migrate(value)?;
+        serde_json::from_value(value).ok()
+    }
+
+    pub fn best_result(&self, level: usize) -> Option<LevelBest> {
+        self.best_results.get(level).copied().flatten()
+    }
+
+    pub fn is_unlocked(&self, level: usize) -> bool {
+        level < self.unlocked_levels
+    }
+
+    pub fn record(&mut self, level: usize, result: LevelBest) {
+        if self.best_results.len() <= level {
+            self.best_results.resize(level + 1, None);
+        }
+        let is_better = match &self.best_results[level] {
+            Some(best) => result.is_better_than(best),
+            None => true,
+        };
+        if is_better {
+            self.best_results[level] = Some(result);
+        }
+    }
+
+    /// Adds a run to the high-score table, returns its place (0 is the
+    /// best) if it made it in
+    pub fn record_endless(&mut self, score: EndlessScore) -> Option<usize> {
+        let place = self
+            .endless_scores
+            .iter()
+            .position(|other| score.is_better_than(other))
+            .unwrap_or(self.endless_scores.len());
+        if place >= ENDLESS_SCORES_KEPT {
+            return None;
+        }
+        self.endless_scores.insert(place, score);
+        self.endless_scores.truncate(ENDLESS_SCORES_KEPT);
+        Some(place)
+    }
+}
+
+// Brings an older save up to `SAVE_VERSION` one step at a time. Returns None
+// for saves written by a newer version of the game than this one.
+fn migrate(mut value: serde_json::Value) -> Option<serde_json::Value> {
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    if version > SAVE_VERSION {
+        println!("Save game is from a newer version ({version}), ignoring it");
+        return None;
+    }
+    // Version 0 never had a version field, everything missing gets defaulted
+    value["version"] = SAVE_VERSION.into();
+    Some(value)
+}
+
+/// Where the save game and exports live on native
+#[cfg(not(target_arch = "wasm32"))]
+pub fn data_dir() -> std::path::PathBuf {
+    use std::path::PathBuf;
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("indie-games-website-simulator")
+}
+
+/// Reads `key` from the save file directory on native, `localStorage` on web
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_storage(key: &str) -> Option<String> {
+    std::fs::read_to_string(data_dir().join(format!("{key}.json"))).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_storage(key: &str, contents: &str) {
+    let dir = data_dir();
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        println!("Couldn't create {}: {err}", dir.display());
+        return;
+    }
+    if let Err(err) = std::fs::write(dir.join(format!("{key}.json")), contents) {
+        println!("Couldn't write {key}: {err}");
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// Reads `key` from the save file directory on native, `localStorage` on web
+#[cfg(target_arch = "wasm32")]
+pub fn read_storage(key: &str) -> Option<String> {
+    local_storage()?
+        .get_item(&format!("indie-games-website-simulator-{key}"))
+        .ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_storage(key: &str, contents: &str) {
+    match local_storage() {
+        Some(storage) => {
+            let key = format!("indie-games-website-simulator-{key}");
+            if storage.set_item(&key, contents).is_err() {
+                println!("Couldn't write {key} to localStorage");
+            }
+        }
+        None => println!("No localStorage available, can't save {key}"),
+    }
+}
+
+// Pick up the campaign from the furthest level we've unlocked
+fn restore_campaign_progress(save: Res<SaveGame>, mut game_levels: ResMut<GameLevels>) {
+    let last_level = game_levels.levels.len() - 1;
+    game_levels.current = save.unlocked_levels.saturating_sub(1).min(last_level);
+    println!(
+        "Restored campaign progress, at level {}",
+        game_levels.current + 1
+    );
+}
+
+fn record_level_result(
+    mut save: ResMut<SaveGame>,
+    game_levels: Res<GameLevels>,
+    level_results: Res<LevelResults>,
+    game_stats: Res<GameStats>,
+    points: Res<UpgradePoints>,
+) {
+    let level = game_levels.current;
+    save.record(
+        level,
+        LevelBest {
+            passed: level_results.passed,
+            handled_percentage: level_results.current_percentage,
+            avg_response_time: game_stats.avg_response_time,
+            points_spent: points.assigned,
+            bonus_objectives: level_results.bonus_objectives_met(),
+        },
+    );
+    if level_results.passed {
+        save.unlocked_levels = save
+            .unlocked_levels
+            .max(level + 2)
+            .min(game_levels.levels.len());
+    }
+    save.write();
+}
+
+pub fn record_endless_score(
+    mut save: ResMut<SaveGame>,
+    mut run: ResMut<EndlessRun>,
+    game_stats: Res<GameStats>,
+) {
+    let score = EndlessScore {
+        waves_survived: run.wave - 1,
+        requests_served: game_stats.handled_requests,
+    };
+    run.high_score_place = save.record_endless(score);
+    save.write();
+}
+
+fn toggle_mute(mut save: ResMut<SaveGame>) {
+    save.settings.muted = !save.settings.muted;
+    println!("Muted: {}", save.settings.muted);
+    save.write();
+}
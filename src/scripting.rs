@@ -0,0 +1,482 @@
+// Drive the game from another program, a line of JSON per command over a
+// local socket. Native only, behind the `scripting` feature:
+//
+//     cargo run --features scripting
+//     nc 127.0.0.1 9899
+//     {"cmd": "load_level", "level": 0}
+//     {"cmd": "upgrade_cpu", "server": 0}
+//     {"cmd": "start"}
+//
+// Set SIMULATOR_SCRIPT_ADDR to listen somewhere else, `unix:/some/path` for
+// a Unix socket. Servers are numbered from 0 in the same order as blueprints.
+// Every command gets a `{"ok": ..}` reply, and while a run is going every
+// client also gets `{"event": "stats", ..}` lines, then `{"event": "results"}`.
+
+use crate::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:9899";
+
+pub struct ScriptingPlugin;
+
+impl Plugin for ScriptingPlugin {
+    fn build(&self, app: &mut App) {
+        let addr = std::env::var("SIMULATOR_SCRIPT_ADDR").unwrap_or(DEFAULT_ADDR.to_string());
+        let (sender, receiver) = channel();
+        let scripting = ScriptingServer {
+            inbox: Mutex::new(receiver),
+            clients: Arc::default(),
+            pending: VecDeque::new(),
+            selection_before: None,
+        };
+        listen(&addr, sender, scripting.clients.clone());
+
+        app.insert_resource(scripting)
+            .add_systems(
+                Update,
+                (receive_script_commands, run_script_command)
+                    .chain()
+                    .before(handle_upgrade_server_cpu)
+                    .before(handle_upgrade_queue_size)
+                    .before(handle_change_server_mode),
+            )
+            .add_systems(
+                Update,
+                restore_selection
+                    .after(handle_upgrade_server_cpu)
+                    .after(handle_upgrade_queue_size)
+                    .after(handle_change_server_mode),
+            )
+            .add_systems(
+                Update,
+                (
+                    stream_state_changes,
+                    stream_stats
+                        .run_if(in_state(GameState::Running))
+                        .run_if(on_timer(Duration::from_millis(500))),
+                ),
+            )
+            .add_systems(
+                OnEnter(GameState::Results),
+                stream_results
+                    .after(calculate_pass_or_not)
+                    .run_if(not(resource_exists::<BackFromReplay>)),
+            );
+    }
+}
+
+/// Everything a script can ask for
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum ScriptCommand {
+    // From 0, like `GameLevels::current`
+    LoadLevel { level: usize },
+    AddServers { count: usize },
+    Move { server: usize, x: f32, y: f32 },
+    UpgradeCpu { server: usize },
+    UpgradeQueue { server: usize },
+    SwitchMode { server: usize },
+    // Makes `server` a proxy to `outputs`
+    Connect { server: usize, outputs: Vec<usize> },
+    // DNS records of the Internet
+    ConnectIngress { outputs: Vec<usize> },
+    // A code from the Export button
+    Blueprint { code: String },
+    Start,
+    State,
+}
+
+type ClientId = usize;
+
+struct Client {
+    id: ClientId,
+    writer: Box<dyn Write + Send>,
+}
+
+#[derive(Resource)]
+pub struct ScriptingServer {
+    inbox: Mutex<Receiver<(ClientId, String)>>,
+    clients: Arc<Mutex<Vec<Client>>>,
+    // One runs per frame, since most of them go through the selection
+    pending: VecDeque<(ClientId, ScriptCommand)>,
+    // What the player had selected before a command borrowed the selection
+    selection_before: Option<Vec<(Entity, bool)>>,
+}
+
+impl ScriptingServer {
+    fn send(&self, to: Option<ClientId>, message: &Value) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        let line = format!("{message}\n");
+        // Anyone we can't write to has gone away
+        clients.retain_mut(|client| {
+            if to.is_some_and(|id| id != client.id) {
+                return true;
+            }
+            client.writer.write_all(line.as_bytes()).is_ok()
+        });
+    }
+
+    fn reply(&self, to: ClientId, result: Result<Value, String>) {
+        let message = match result {
+            Ok(Value::Object(mut fields)) => {
+                fields.insert("ok".to_string(), true.into());
+                Value::Object(fields)
+            }
+            Ok(_) => json!({ "ok": true }),
+            Err(error) => json!({ "ok": false, "error": error }),
+        };
+        self.send(Some(to), &message);
+    }
+
+    fn broadcast(&self, message: &Value) {
+        self.send(None, message);
+    }
+}
+
+fn listen(addr: &str, sender: Sender<(ClientId, String)>, clients: Arc<Mutex<Vec<Client>>>) {
+    #[cfg(unix)]
+    if let Some(path) = addr.strip_prefix("unix:") {
+        // Left over from last time otherwise
+        let _ = std::fs::remove_file(path);
+        match std::os::unix::net::UnixListener::bind(path) {
+            Ok(listener) => {
+                println!("Scripting API listening on {addr}");
+                std::thread::spawn(move || {
+                    accept(listener.incoming(), |s| s.try_clone(), sender, clients)
+                });
+            }
+            Err(err) => println!("Couldn't listen for scripts on {addr}: {err}"),
+        }
+        return;
+    }
+    match std::net::TcpListener::bind(addr) {
+        Ok(listener) => {
+            println!("Scripting API listening on {addr}");
+            std::thread::spawn(move || {
+                accept(listener.incoming(), |s| s.try_clone(), sender, clients)
+            });
+        }
+        Err(err) => println!("Couldn't listen for scripts on {addr}: {err}"),
+    }
+}
+
+fn accept<S>(
+    incoming: impl Iterator<Item = std::io::Result<S>>,
+    try_clone: fn(&S) -> std::io::Result<S>,
+    sender: Sender<(ClientId, String)>,
+    clients: Arc<Mutex<Vec<Client>>>,
+) where
+    S: Read + Write + Send + 'static,
+{
+    for (id, stream) in incoming.enumerate() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let Ok(writer) = try_clone(&stream) else {
+            continue;
+        };
+        println!("Script {id} connected");
+        if let Ok(mut clients) = clients.lock() {
+            clients.push(Client {
+                id,
+                writer: Box::new(writer),
+            });
+        }
+        let sender = sender.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send((id, line)).is_err() {
+                    break;
+                }
+            }
+            println!("Script {id} disconnected");
+        });
+    }
+}
+
+fn receive_script_commands(mut scripting: ResMut<ScriptingServer>) {
+    let lines: Vec<(ClientId, String)> = match scripting.inbox.lock() {
+        Ok(inbox) => inbox.try_iter().collect(),
+        Err(_) => return,
+    };
+    for (client, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<ScriptCommand>(&line) {
+            Ok(command) => scripting.pending.push_back((client, command)),
+            Err(err) => scripting.reply(client, Err(format!("Bad command: {err}"))),
+        }
+    }
+}
+
+fn run_script_command(world: &mut World) {
+    let Some((client, command)) = world.resource_mut::<ScriptingServer>().pending.pop_front()
+    else {
+        return;
+    };
+    println!("Script {client}: {command:?}");
+    let result = run_command(world, command);
+    world.resource::<ScriptingServer>().reply(client, result);
+}
+
+// Same order as blueprints and the topology checks
+fn sorted_servers(world: &mut World) -> Vec<Entity> {
+    let mut servers: Vec<Entity> = world
+        .query_filtered::<Entity, With<Server>>()
+        .iter(world)
+        .collect();
+    servers.sort_by_key(|e_server| {
+        slot_key(
+            world.get::<Locked>(*e_server).is_some(),
+            world.get::<SpawnOrder>(*e_server),
+        )
+    });
+    servers
+}
+
+fn server_at(servers: &[Entity], index: usize) -> Result<Entity, String> {
+    servers
+        .get(index)
+        .copied()
+        .ok_or(format!("No server {index}, there are {}", servers.len()))
+}
+
+// Selects just `target`, the planning events act on whatever is selected.
+// The player's selection comes back once the handlers have run.
+fn select_only(world: &mut World, target: Entity) {
+    let mut query = world.query_filtered::<(Entity, &mut PickSelection), With<Server>>();
+    let mut before = vec![];
+    for (entity, mut selection) in query.iter_mut(world) {
+        before.push((entity, selection.is_selected));
+        selection.is_selected = entity == target;
+    }
+    world.resource_mut::<ScriptingServer>().selection_before = Some(before);
+}
+
+fn restore_selection(
+    mut scripting: ResMut<ScriptingServer>,
+    mut q_selection: Query<&mut PickSelection, With<Server>>,
+) {
+    let Some(before) = scripting.selection_before.take() else {
+        return;
+    };
+    for (entity, was_selected) in before {
+        if let Ok(mut selection) = q_selection.get_mut(entity) {
+            selection.is_selected = was_selected;
+        }
+    }
+}
+
+// The handlers skip these quietly, so check before replying ok
+fn can_upgrade(world: &World, target: Entity, cost: usize) -> Result<(), String> {
+    if world.get::<Locked>(target).is_some() {
+        return Err("Server came with the level, it can't be changed".to_string());
+    }
+    let points = world.resource::<UpgradePoints>();
+    if !points.can_spend_points(cost) {
+        return Err(format!(
+            "Not enough points, {} of {} assigned and this needs {cost}",
+            points.assigned, points.total
+        ));
+    }
+    Ok(())
+}
+
+fn run_command(world: &mut World, command: ScriptCommand) -> Result<Value, String> {
+    let state = *world.resource::<State<GameState>>().get();
+    let planning = state == GameState::Planning;
+    let needs_planning = !matches!(
+        command,
+        ScriptCommand::LoadLevel { .. } | ScriptCommand::State
+    );
+    if needs_planning && !planning {
+        return Err(format!("Only possible while planning, currently {state:?}"));
+    }
+    let servers = sorted_servers(world);
+
+    match command {
+        ScriptCommand::LoadLevel { level } => {
+            let mut game_levels = world.resource_mut::<GameLevels>();
+            if level >= game_levels.levels.len() {
+                return Err(format!("No level {level}"));
+            }
+            // Same as the level select, starts over even on the same level
+            game_levels.current = level;
+            world.send_event(ResetCurrentLevel);
+            world
+                .resource_mut::<NextState<GameState>>()
+                .set(GameState::Planning);
+        }
+        ScriptCommand::AddServers { count } => {
+            world.send_event(AddNewServer(count));
+        }
+        ScriptCommand::Move { server, x, y } => {
+            let target = server_at(&servers, server)?;
+            if let Some(mut transform) = world.get_mut::<Transform>(target) {
+                transform.translation.x = x;
+                transform.translation.y = y;
+            }
+        }
+        ScriptCommand::UpgradeCpu { server } => {
+            let target = server_at(&servers, server)?;
+            // Same cost as `handle_upgrade_server_cpu`
+            let cost = world.get::<Server>(target).unwrap().processing_power.max(1);
+            can_upgrade(world, target, cost)?;
+            select_only(world, target);
+            world.send_event(UpgradeServerCPUEvent(target));
+        }
+        ScriptCommand::UpgradeQueue { server } => {
+            let target = server_at(&servers, server)?;
+            can_upgrade(world, target, 1)?;
+            select_only(world, target);
+            world.send_event(UpgradeServerQueueEvent(target));
+        }
+        ScriptCommand::SwitchMode { server } => {
+            let target = server_at(&servers, server)?;
+            can_upgrade(world, target, 0)?;
+            select_only(world, target);
+            world.send_event(ChangeServerModeEvent(target));
+        }
+        ScriptCommand::Connect { server, outputs } => {
+            let source = server_at(&servers, server)?;
+            let outputs = outputs
+                .iter()
+                .map(|output| server_at(&servers, *output))
+                .collect::<Result<Vec<Entity>, String>>()?;
+            let proxy_image = world.resource::<ImageAssets>().server_proxy.clone();
+            let mut entity = world.entity_mut(source);
+            // Same as `make_connections`, locked servers that process stay that way
+            let processing = entity.get::<Server>().unwrap().mode == ServerMode::Process;
+            if entity.contains::<Locked>() && processing {
+                return Err("Locked server can't become a proxy".to_string());
+            }
+            // Same as `make_connections`, wiring outputs makes it a proxy
+            *entity.get_mut::<Handle<Image>>().unwrap() = proxy_image;
+            let mut server = entity.get_mut::<Server>().unwrap();
+            server.mode = ServerMode::Proxy;
+            server.outputs = outputs;
+        }
+        ScriptCommand::ConnectIngress { outputs } => {
+            let outputs = outputs
+                .iter()
+                .map(|output| server_at(&servers, *output))
+                .collect::<Result<Vec<Entity>, String>>()?;
+            let game_levels = world.resource::<GameLevels>();
+            if !game_levels.active_level().allows_dns_records(outputs.len()) {
+                return Err("Level doesn't allow that many DNS records".to_string());
+            }
+            let mut q_ingress = world.query::<&mut Ingress>();
+            let mut ingress = q_ingress
+                .get_single_mut(world)
+                .map_err(|_| "No Internet to connect".to_string())?;
+            ingress.set_outputs(outputs);
+        }
+        ScriptCommand::Blueprint { code } => {
+            let blueprint = Blueprint::from_text(&code).map_err(|err| err.to_string())?;
+            let game_levels = world.resource::<GameLevels>();
+            blueprint
+                .validate(game_levels.active_level())
+                .map_err(|err| err.to_string())?;
+            ApplyBlueprint(blueprint).apply(world);
+        }
+        ScriptCommand::Start => {
+            world.send_event(StartLoadScenarios);
+        }
+        ScriptCommand::State => return Ok(snapshot(world)),
+    }
+    Ok(Value::Null)
+}
+
+// What's on the board right now, in blueprint order
+fn snapshot(world: &mut World) -> Value {
+    let state = *world.resource::<State<GameState>>().get();
+    let servers = sorted_servers(world);
+    let index_of = |entity: &Entity| servers.iter().position(|e| e == entity);
+    let server_values: Vec<Value> = servers
+        .iter()
+        .filter_map(|entity| {
+            let server = world.get::<Server>(*entity)?;
+            let position = world.get::<Transform>(*entity)?.translation;
+            Some(json!({
+                "mode": format!("{:?}", server.mode),
+                "processing_power": server.processing_power,
+                "queue_size": server.queue_size,
+                "discipline": format!("{:?}", server.discipline),
+                "outputs": server.outputs.iter().filter_map(index_of).collect::<Vec<usize>>(),
+                "queued": server.queued_requests.len(),
+                "x": position.x,
+                "y": position.y,
+            }))
+        })
+        .collect();
+    let ingress: Vec<usize> = world
+        .query::<&Ingress>()
+        .iter(world)
+        .next()
+        .map(|ingress| ingress.outputs.iter().filter_map(index_of).collect())
+        .unwrap_or_default();
+    let points = world.resource::<UpgradePoints>();
+    let game_levels = world.resource::<GameLevels>();
+    json!({
+        "state": format!("{state:?}"),
+        "level": game_levels.current,
+        "title": game_levels.active_level().title,
+        "points": {
+            "total": points.total,
+            "assigned": points.assigned,
+            "reserved": points.reserved,
+        },
+        "servers": server_values,
+        "ingress": ingress,
+    })
+}
+
+fn stream_state_changes(scripting: Res<ScriptingServer>, state: Res<State<GameState>>) {
+    if state.is_changed() {
+        scripting.broadcast(&json!({ "event": "state", "state": format!("{:?}", state.get()) }));
+    }
+}
+
+fn stream_stats(
+    scripting: Res<ScriptingServer>,
+    stats: Res<GameStats>,
+    clock: Res<RunClock>,
+    fixed_time: Res<Time<Fixed>>,
+    q_requests: Query<(), (With<Request>, Without<DroppedRequest>, Without<ToRemove>)>,
+) {
+    scripting.broadcast(&json!({
+        "event": "stats",
+        "time": run_elapsed(&clock, &fixed_time),
+        "handled": stats.handled_requests,
+        "dropped": stats.dropped_requests,
+        "in_flight": q_requests.iter().count(),
+        "avg_response_time": stats.avg_response_time,
+    }));
+}
+
+fn stream_results(
+    scripting: Res<ScriptingServer>,
+    stats: Res<GameStats>,
+    results: Res<LevelResults>,
+) {
+    scripting.broadcast(&json!({
+        "event": "results",
+        "passed": results.passed,
+        "handled": stats.handled_requests,
+        "dropped": stats.dropped_requests,
+        "handled_percentage": results.current_percentage,
+        "avg_response_time": stats.avg_response_time,
+        "required_percentage": results.pass_percentage,
+        "required_avg_response_time": results.pass_avg_response_time,
+    }));
+}
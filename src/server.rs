@@ -1,7 +1,10 @@
 use crate::prelude::*;
+use serde::{Deserialize, Serialize};
 
 // const SERVER_SPACING: f32 = 100.0;
 const BASELINE_MS_PROCESSING: u64 = 2000;
+// in seconds (ms in game), CoDel drops requests that waited longer than this
+pub const CODEL_TARGET: f32 = 4.0;
 
 pub struct ServerPlugin;
 
@@ -11,11 +14,13 @@ impl Plugin for ServerPlugin {
             .add_event::<UpgradeServerQueueEvent>()
             .add_event::<AddNewServer>()
             .add_event::<ChangeServerModeEvent>()
+            .add_event::<ChangeQueueDisciplineEvent>()
             .add_event::<SetServerOutputEvent>()
             .add_event::<AlignServersEvent>()
             .add_event::<ResetUpgradesEvent>()
             .init_resource::<SelectedServerForOutputs>()
             .init_resource::<UpgradePoints>()
+            .init_resource::<NextSpawnOrder>()
             .add_systems(Update, draw_children_ui)
             .add_systems(
                 Update,
@@ -23,6 +28,7 @@ impl Plugin for ServerPlugin {
                     handle_upgrade_server_cpu,
                     handle_upgrade_queue_size,
                     handle_change_server_mode,
+                    handle_change_queue_discipline,
                     handle_reset_upgrades,
                     handle_select_outputs,
                     add_new_server,
@@ -32,9 +38,8 @@ impl Plugin for ServerPlugin {
                     align_servers_to_grid.run_if(on_event::<AlignServersEvent>()),
                     align_queued_requests,
                 )
-                    .run_if(in_state(GameState::Planning).or_else(in_state(GameState::Running))),
-            )
-            .add_systems(FixedUpdate, process_requests);
+                    .run_if(in_state(GameState::Planning).or_else(in_state(Simulating))),
+            );
     }
 }
 
@@ -55,19 +60,40 @@ struct ServerInfoText;
 #[derive(Event)]
 pub struct AddNewServer(pub usize);
 
-#[derive(Resource, Default)]
+#[derive(Resource, Default, Clone)]
 pub struct UpgradePoints {
     pub total: usize,
     pub assigned: usize,
+    // Put aside while planning, only the on-call player can spend these
+    pub reserved: usize,
 }
 
 impl UpgradePoints {
     pub fn can_spend_points(&self, to_spend: usize) -> bool {
-        self.assigned + to_spend <= self.total
+        self.assigned + self.reserved + to_spend <= self.total
+    }
+
+    /// What's left to spend while planning
+    pub fn available(&self) -> usize {
+        self.total.saturating_sub(self.assigned + self.reserved)
+    }
+
+    pub fn can_spend_on_call(&self, to_spend: usize) -> bool {
+        to_spend <= self.reserved
+    }
+
+    pub fn spend_on_call(&mut self, to_spend: usize) {
+        self.reserved -= to_spend;
+        self.assigned += to_spend;
+    }
+
+    // Whatever no longer fits after the assigned points changed goes back
+    pub fn clamp_reserved(&mut self) {
+        self.reserved = self.reserved.min(self.total.saturating_sub(self.assigned));
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ServerMode {
     Process,
     Proxy,
@@ -79,12 +105,72 @@ pub enum Filter {
     AgeGreater(f32),
 }
 
+/// In which order a server works through its queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum QueueDiscipline {
+    #[default]
+    Fifo,
+    // Newest first, under overload at least some requests stay fast
+    Lifo,
+    // Interactive requests skip ahead of batch ones
+    Priority,
+    // Smallest `size` first
+    ShortestJobFirst,
+    // Like FIFO, but drops requests that waited longer than `CODEL_TARGET`
+    CoDel,
+}
+
+impl QueueDiscipline {
+    pub fn next(&self) -> Self {
+        match self {
+            QueueDiscipline::Fifo => QueueDiscipline::Lifo,
+            QueueDiscipline::Lifo => QueueDiscipline::Priority,
+            QueueDiscipline::Priority => QueueDiscipline::ShortestJobFirst,
+            QueueDiscipline::ShortestJobFirst => QueueDiscipline::CoDel,
+            QueueDiscipline::CoDel => QueueDiscipline::Fifo,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            QueueDiscipline::Fifo => "FIFO",
+            QueueDiscipline::Lifo => "LIFO",
+            QueueDiscipline::Priority => "Priority",
+            QueueDiscipline::ShortestJobFirst => "SJF",
+            QueueDiscipline::CoDel => "CoDel",
+        }
+    }
+
+    /// Where a request of `size` goes in a queue holding requests of `queued`
+    /// sizes, the front gets worked on first
+    pub fn position(&self, queued: impl Iterator<Item = usize>, size: usize) -> usize {
+        let mut index = 0;
+        for other in queued {
+            let goes_before = match self {
+                QueueDiscipline::Fifo | QueueDiscipline::CoDel => false,
+                QueueDiscipline::Lifo => true,
+                QueueDiscipline::Priority => RequestKind::of(other) > RequestKind::of(size),
+                QueueDiscipline::ShortestJobFirst => other > size,
+            };
+            if goes_before {
+                break;
+            }
+            index += 1;
+        }
+        index
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Server {
     pub mode: ServerMode,
     pub processing_power: usize,
     pub queue_size: usize,
+    pub discipline: QueueDiscipline,
+    // Kept in the order they'll be worked on, see `QueueDiscipline`
     pub queued_requests: VecDeque<Entity>,
+    // Sizes of `queued_requests`, to know where new ones go
+    queued_sizes: VecDeque<usize>,
     pub current_request: Option<Entity>,
     // Timer that counts down to zero, and once zero, it has processed the request
     pub current_progress: Timer,
@@ -93,22 +179,24 @@ pub struct Server {
     pub outputs: Vec<Entity>,
     // Which index we're currently on in our round-robin
     current_output_index: usize,
+    // Done with its request, but every output pushed back so it waits
+    pub holding: bool,
 }
 
 impl Server {
     pub fn reset_progress(&mut self) {
-        // Proxy mode doubles our processing power
-        let duration = match self.mode {
-            ServerMode::Process => BASELINE_MS_PROCESSING / self.processing_power as u64,
-            ServerMode::Proxy => BASELINE_MS_PROCESSING / (self.processing_power * 2) as u64,
-        };
-        self.current_progress = Timer::new(Duration::from_millis(duration), TimerMode::Once);
+        let duration = processing_time(self.mode, self.processing_power);
+        self.current_progress = Timer::new(duration, TimerMode::Once);
     }
-    pub fn add_request(&mut self, request: Entity) {
+    pub fn add_request(&mut self, request: Entity, size: usize) {
         match self.current_request {
             Some(_) => {
                 // Add to queue if possible
-                self.queued_requests.push_back(request);
+                let index = self
+                    .discipline
+                    .position(self.queued_sizes.iter().copied(), size);
+                self.queued_requests.insert(index, request);
+                self.queued_sizes.insert(index, size);
             }
             None => {
                 // No current request, assign this
@@ -116,14 +204,37 @@ impl Server {
             }
         }
     }
+    // Picks up whatever is at the front of the queue, if anything
+    pub fn next_request(&mut self) {
+        self.current_request = self.queued_requests.pop_front();
+        self.queued_sizes.pop_front();
+        self.holding = false;
+    }
+    pub fn remove_queued(&mut self, request: Entity) {
+        if let Some(index) = self.queued_requests.iter().position(|e| *e == request) {
+            self.queued_requests.remove(index);
+            self.queued_sizes.remove(index);
+        }
+    }
+    pub fn clear_queue(&mut self) {
+        self.queued_requests.clear();
+        self.queued_sizes.clear();
+    }
+    // How many upgrade points have been put into this server
+    pub fn spent_points(&self) -> usize {
+        spent_points(self.processing_power, self.queue_size)
+    }
     pub fn is_busy(&self) -> bool {
-        println!("Current Request: {:?}", self.current_request);
-        println!(
-            "Queue Length: {}, Queue Size: {}",
-            self.queued_requests.len(),
-            self.queue_size
-        );
-        self.current_request.is_some() && self.queued_requests.len() >= self.queue_size
+        self.is_full()
+    }
+    // Working on a request and the queue is full too, no room for another
+    pub fn is_full(&self) -> bool {
+        self.free_slots() == 0
+    }
+    // How many more requests it can take, to work on or in the queue
+    pub fn free_slots(&self) -> usize {
+        let taken = self.current_request.is_some() as usize + self.queued_requests.len();
+        (1 + self.queue_size).saturating_sub(taken)
     }
 }
 
@@ -133,7 +244,9 @@ impl Default for Server {
             mode: ServerMode::Process,
             processing_power: 1,
             queue_size: 0,
+            discipline: QueueDiscipline::Fifo,
             queued_requests: VecDeque::new(),
+            queued_sizes: VecDeque::new(),
             current_request: None,
             current_progress: Timer::new(
                 Duration::from_millis(BASELINE_MS_PROCESSING),
@@ -142,10 +255,31 @@ impl Default for Server {
             // Proxy fields
             outputs: vec![],
             current_output_index: 0,
+            holding: false,
         }
     }
 }
 
+/// How long a server takes to get through one request
+pub fn processing_time(mode: ServerMode, processing_power: usize) -> Duration {
+    // Proxy mode doubles our processing power
+    let duration = match mode {
+        ServerMode::Process => BASELINE_MS_PROCESSING / processing_power as u64,
+        ServerMode::Proxy => BASELINE_MS_PROCESSING / (processing_power * 2) as u64,
+    };
+    Duration::from_millis(duration)
+}
+
+/// CPU upgrades cost the current power each (1 + 2 + 3...), queue slots cost 1
+pub fn spent_points(processing_power: usize, queue_size: usize) -> usize {
+    let cpu_points = if processing_power > 1 {
+        ((processing_power - 1) * processing_power) / 2
+    } else {
+        0
+    };
+    cpu_points + queue_size
+}
+
 #[derive(Event)]
 pub struct UpgradeServerCPUEvent(pub Entity);
 
@@ -174,6 +308,15 @@ impl From<ListenerInput<Pointer<Click>>> for ChangeServerModeEvent {
     }
 }
 
+#[derive(Event)]
+pub struct ChangeQueueDisciplineEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<Click>>> for ChangeQueueDisciplineEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        ChangeQueueDisciplineEvent(event.target)
+    }
+}
+
 #[derive(Event)]
 pub struct SetServerOutputEvent(pub Entity);
 
@@ -194,7 +337,7 @@ impl From<ListenerInput<Pointer<Click>>> for ResetUpgradesEvent {
 
 pub fn handle_upgrade_server_cpu(
     mut evs: EventReader<UpgradeServerCPUEvent>,
-    mut query: Query<(&mut Server, &PickSelection)>,
+    mut query: Query<(&mut Server, &PickSelection), Without<Locked>>,
     mut upgrade_points: ResMut<UpgradePoints>,
 ) {
     for _ev in evs.read() {
@@ -221,7 +364,7 @@ pub fn handle_upgrade_server_cpu(
 pub fn handle_upgrade_queue_size(
     // selection: Res<Selection>,
     mut evs: EventReader<UpgradeServerQueueEvent>,
-    mut query: Query<(&mut Server, &PickSelection)>,
+    mut query: Query<(&mut Server, &PickSelection), Without<Locked>>,
     mut upgrade_points: ResMut<UpgradePoints>,
 ) {
     for _ev in evs.read() {
@@ -248,24 +391,15 @@ pub fn handle_upgrade_queue_size(
 
 pub fn handle_reset_upgrades(
     mut evs: EventReader<ResetUpgradesEvent>,
-    mut query: Query<(&mut Server, &PickSelection)>,
+    mut query: Query<(&mut Server, &PickSelection), Without<Locked>>,
     mut upgrade_points: ResMut<UpgradePoints>,
 ) {
     for _ev in evs.read() {
         println!("Resetting upgrades");
         for (mut server, pick_selection) in query.iter_mut() {
             if pick_selection.is_selected {
-                // how many points to refund for cpu
-                let cpu_points = if server.processing_power > 1 {
-                    ((server.processing_power - 1) * server.processing_power) / 2
-                } else {
-                    0
-                };
-
-                // queue upgrades are easy
-                let queue_points = server.queue_size;
-
-                let total_points_to_return = cpu_points + queue_points;
+                // how many points to refund for cpu and queue
+                let total_points_to_return = server.spent_points();
 
                 // reset to default
                 server.processing_power = 1;
@@ -286,13 +420,13 @@ pub fn handle_reset_upgrades(
 
 pub fn handle_select_outputs(
     mut evs: EventReader<SetServerOutputEvent>,
-    mut query: Query<(Entity, &mut Server, &mut PickSelection)>,
+    mut query: Query<(Entity, &mut PickSelection), Or<(With<Server>, With<Ingress>)>>,
     mut next_state: ResMut<NextState<EditMode>>,
     mut selected_server: ResMut<SelectedServerForOutputs>,
 ) {
     for _ev in evs.read() {
         println!("Setting new server outputs");
-        for (entity, mut _server, mut pick_selection) in query.iter_mut() {
+        for (entity, mut pick_selection) in query.iter_mut() {
             // Now tell the user to select the servers to connect
             next_state.set(EditMode::Outputs);
             if pick_selection.is_selected {
@@ -306,7 +440,7 @@ pub fn handle_select_outputs(
 
 pub fn handle_change_server_mode(
     mut evs: EventReader<ChangeServerModeEvent>,
-    mut query: Query<(&mut Server, &PickSelection, &mut Handle<Image>)>,
+    mut query: Query<(&mut Server, &PickSelection, &mut Handle<Image>), Without<Locked>>,
     image_assets: Res<ImageAssets>,
 ) {
     for _ev in evs.read() {
@@ -332,41 +466,124 @@ pub fn handle_change_server_mode(
     }
 }
 
+pub fn handle_change_queue_discipline(
+    mut evs: EventReader<ChangeQueueDisciplineEvent>,
+    mut query: Query<(&mut Server, &PickSelection), Without<Locked>>,
+) {
+    for _ev in evs.read() {
+        for (mut server, pick_selection) in query.iter_mut() {
+            if pick_selection.is_selected {
+                server.discipline = server.discipline.next();
+                println!("Switched queue discipline to {:?}", server.discipline);
+            }
+        }
+    }
+}
+
+// CoDel servers let go of requests that waited in their queue for too long
+pub(crate) fn drop_stale_requests(
+    mut commands: Commands,
+    mut q_servers: Query<(&mut Server, &mut ServerStats)>,
+    mut q_request: Query<&mut Request>,
+    mut stats: ResMut<GameStats>,
+    mut evs: EventWriter<PlaySound>,
+) {
+    for (mut server, mut server_stats) in q_servers.iter_mut() {
+        if server.discipline != QueueDiscipline::CoDel {
+            continue;
+        }
+        // Oldest at the front, once one is fine the rest are too
+        while let Some(&e_request) = server.queued_requests.front() {
+            let Ok(mut request) = q_request.get_mut(e_request) else {
+                break;
+            };
+            let age = request.age;
+            if request.trace.waiting(age) <= CODEL_TARGET {
+                break;
+            }
+            request.trace.finish_span(SpanOutcome::Dropped, age);
+            commands
+                .entity(e_request)
+                .insert(DroppedRequest)
+                .remove::<Owned>();
+            server.remove_queued(e_request);
+            stats.dropped_requests += 1;
+            server_stats.dropped += 1;
+            evs.send(PlaySound(Sound::DroppedRequest));
+        }
+    }
+}
+
 pub fn handle_set_new_server_output() {
     // Get current position
 }
 
-fn process_requests(
+// Requests being worked on sit left of the server
+const PROCESSING_OFFSET_X: f32 = -48.0;
+
+pub(crate) fn process_requests(
     time: Res<Time>,
     mut commands: Commands,
-    mut q_servers: Query<(Entity, &Transform, &mut Server)>,
+    mut q_servers: Query<(
+        Entity,
+        &Transform,
+        &mut Server,
+        &mut ServerStats,
+        Has<Reconfiguring>,
+    )>,
     mut q_request: Query<
         (&mut Transform, &mut Request, Option<&Animator<Transform>>),
         Without<Server>,
     >,
+    q_ingress: Query<&Ingress>,
+    in_flight: Res<RequestsInFlight>,
     mut stats: ResMut<GameStats>,
     mut evs: EventWriter<PlaySound>,
 ) {
-    for (e_server, t_server, mut server) in q_servers.iter_mut() {
+    // Room each server has left right now, minus what's already on its way
+    // there, nothing while it's offline
+    let backpressure = q_ingress
+        .get_single()
+        .map_or(false, |ingress| ingress.backpressure);
+    let mut room: HashMap<Entity, usize> = if backpressure {
+        q_servers
+            .iter()
+            .map(|(e_server, _, server, _, reconfiguring)| {
+                let room = match reconfiguring {
+                    true => 0,
+                    false => server.free_slots().saturating_sub(in_flight.to(e_server)),
+                };
+                (e_server, room)
+            })
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    for (e_server, t_server, mut server, mut server_stats, reconfiguring) in q_servers.iter_mut() {
+        if reconfiguring {
+            // Offline while the on-call player changes it, work waits
+            continue;
+        }
         match server.current_request {
             Some(e_request) => {
                 let (mut t_request, mut request, animator) = q_request.get_mut(e_request).unwrap();
-                // Process request
-                if server.current_progress.tick(time.delta()).just_finished() {
+                let age = request.age;
+                request.trace.start(age);
+                // Process request, a proxy that's holding stays finished
+                // until it can pass it on
+                if server.current_progress.tick(time.delta()).finished() {
                     match server.mode {
                         ServerMode::Process => {
                             println!("Done processing request!");
                             // Done processing, reset!
                             commands.entity(e_request).insert(ToRemove);
+                            request.trace.finish_span(SpanOutcome::Processed, age);
 
                             // Check if there is more things to process
-                            if server.queued_requests.len() > 0 {
-                                server.current_request = server.queued_requests.pop_front();
-                            } else {
-                                server.current_request = None;
-                            }
+                            server.next_request();
                             server.reset_progress();
                             stats.handled_requests += 1;
+                            server_stats.handled += 1;
                             stats.response_times.push(request.age);
                             stats.update_avg_response_time();
 
@@ -384,20 +601,37 @@ fn process_requests(
                             // Dont processing, Proxy it somewhere
                             println!("Proxing this request to other server");
                             if server.outputs.len() == 0 {
+                                request.trace.finish_span(SpanOutcome::Dropped, age);
                                 commands.entity(e_request).insert(DroppedRequest);
                                 evs.send(PlaySound(Sound::DroppedRequest));
                                 stats.dropped_requests += 1;
-                                if server.queued_requests.len() > 0 {
-                                    server.current_request = server.queued_requests.pop_front();
-                                } else {
-                                    server.current_request = None;
-                                }
+                                server_stats.dropped += 1;
+                                server.next_request();
                                 server.reset_progress();
                                 continue;
                             }
                             // Proxy this request to one of our connections
                             //
-                            if server.current_output_index == server.outputs.len() - 1
+                            if backpressure {
+                                // Skip the outputs that are pushing back
+                                let outputs = server.outputs.len();
+                                let open = (1..=outputs)
+                                    .map(|step| (server.current_output_index + step) % outputs)
+                                    .find(|index| {
+                                        room.get(&server.outputs[*index]).map_or(true, |r| *r > 0)
+                                    });
+                                let Some(index) = open else {
+                                    // All of them are, hold on to it
+                                    server.holding = true;
+                                    continue;
+                                };
+                                server.current_output_index = index;
+                                // Taken, so the next proxy this step doesn't
+                                // count on it too
+                                if let Some(room) = room.get_mut(&server.outputs[index]) {
+                                    *room -= 1;
+                                }
+                            } else if server.current_output_index == server.outputs.len() - 1
                                 || server.current_output_index > server.outputs.len() - 1
                             {
                                 server.current_output_index = 0;
@@ -408,32 +642,36 @@ fn process_requests(
 
                             if server_to_pass_on_to == e_server {
                                 // Tryinrg to pass to ourselves? Drop it
+                                request.trace.finish_span(SpanOutcome::Dropped, age);
                                 commands
                                     .entity(e_request)
                                     .insert(DroppedRequest)
                                     .remove::<Owned>();
                                 evs.send(PlaySound(Sound::DroppedRequest));
                                 stats.dropped_requests += 1;
-                                if server.queued_requests.len() > 0 {
-                                    server.current_request = server.queued_requests.pop_front();
-                                } else {
-                                    server.current_request = None;
-                                }
+                                server_stats.dropped += 1;
+                                server.next_request();
                                 server.reset_progress();
                                 continue;
                             }
 
                             request.destination = Some(server_to_pass_on_to);
-                            commands.entity(e_request).remove::<Owned>();
+                            request.trace.finish_span(SpanOutcome::Forwarded, age);
+                            // Leaves from where the processing animation
+                            // ends, got there or not, so the trip doesn't
+                            // depend on how long the animation took
+                            t_request.translation = t_server
+                                .translation
+                                .with_x(t_server.translation.x + PROCESSING_OFFSET_X);
+                            commands
+                                .entity(e_request)
+                                .remove::<(Owned, Animator<Transform>)>();
+                            server_stats.forwarded += 1;
 
                             t_request.scale.y = 0.1;
                             // t_request.translation = t_server.translation;
 
-                            if server.queued_requests.len() > 0 {
-                                server.current_request = server.queued_requests.pop_front();
-                            } else {
-                                server.current_request = None;
-                            }
+                            server.next_request();
                             server.reset_progress();
                             evs.send(PlaySound(Sound::ProxyProcess));
                         }
@@ -452,7 +690,7 @@ fn process_requests(
                             let mut rng = rand::thread_rng();
                             let duration = rng.gen_range(500..1000);
 
-                            let new_x = t_server.translation.x - 48.0;
+                            let new_x = t_server.translation.x + PROCESSING_OFFSET_X;
 
                             let tween = Tween::new(
                                 EaseFunction::BounceOut,
@@ -477,8 +715,11 @@ fn process_requests(
     }
 }
 
-fn draw_children_ui(q_servers: Query<(Entity, &Server, &Children)>, mut q_child: Query<&mut Text>) {
-    for (entity, server, children) in q_servers.iter() {
+fn draw_children_ui(
+    q_servers: Query<(Entity, &Server, &Children, Option<&Reconfiguring>)>,
+    mut q_child: Query<&mut Text>,
+) {
+    for (entity, server, children, reconfiguring) in q_servers.iter() {
         // Extract
         let power = server.processing_power;
         let queue_size = server.queue_size;
@@ -489,13 +730,19 @@ fn draw_children_ui(q_servers: Query<(Entity, &Server, &Children)>, mut q_child:
         };
 
         let queued_requests = server.queued_requests.len();
+        let discipline = server.discipline.label();
+        let holding = if server.holding { "\nHolding" } else { "" };
         let connected_servers = server.outputs.len();
 
         // Draw
         for &child in children.iter() {
             if let Ok(mut text) = q_child.get_mut(child) {
                 text.sections[0].value =
-                    format!("Power: {power}\nMax Queue: {queue_size}\nPending:{queued_requests}",);
+                    format!("Power: {power}\nMax Queue: {queue_size} ({discipline})\nPending:{queued_requests}{holding}",);
+                if let Some(reconfiguring) = reconfiguring {
+                    text.sections[0].value +=
+                        &format!("\nReconfiguring: {:.1}s", reconfiguring.0.remaining_secs());
+                }
             }
         }
     }
@@ -507,6 +754,135 @@ pub struct ServerFilterOutput;
 #[derive(Component)]
 pub struct ServerOutput;
 
+/// When a server spawned, counting up for the whole session. Entity ids
+/// get reused after a reset, so they can't tell which came first.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpawnOrder(pub u64);
+
+#[derive(Resource, Default)]
+pub struct NextSpawnOrder(u64);
+
+impl NextSpawnOrder {
+    pub fn take(&mut self) -> SpawnOrder {
+        self.0 += 1;
+        SpawnOrder(self.0)
+    }
+}
+
+pub fn server_bundle(texture: Handle<Image>, translation: Vec3, order: SpawnOrder) -> impl Bundle {
+    (
+        order,
+        // LevelOwned, // TODO don't want to replace these each level...
+        SpriteBundle {
+            texture,
+            transform: Transform::from_translation(translation).with_scale(Vec3::splat(0.25)),
+            ..default()
+        },
+        Server::default(),
+        ServerStats::default(),
+        PickableBundle::default(),
+        NoDeselect,
+        // On::<Pointer<Click>>::send_event::<SelectEvent>(),
+        On::<Pointer<Drag>>::send_event::<DragServerEvent>(),
+        // On::<Pointer<Drag>>::target_component_mut::<Transform>(|drag, transform| {
+        //     transform.translation.x += drag.delta.x;
+        //     transform.translation.y -= drag.delta.y;
+        // }),
+        On::<Pointer<DragStart>>::send_event::<DragServerStartEvent>(),
+        On::<Pointer<DragEnd>>::send_event::<DragServerEndEvent>(),
+        // On::<Pointer<DragStart>>::target_commands_mut(|drag_start, target_commands| {
+        //     println!("Start Drag!");
+        // }),
+        // On::<Pointer<Drag>>::target_commands_mut(|drag, target_commands| {
+        //     println!("Dragging!");
+        // }),
+        // On::<Pointer<DragEnd>>::target_commands_mut(|drag_end, target_commands| {
+        //     println!("End Drag!");
+        // }),
+    )
+}
+
+fn server_info_text_bundle() -> impl Bundle {
+    (
+        Text2dBundle {
+            transform: Transform::from_translation(Vec3::new(-150.0, -160.0, 10.0)),
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 22.0 * 2.0, // Because of parent scale
+                    // font: font_handle,
+                    ..default()
+                },
+            )
+            .with_justify(JustifyText::Left),
+            text_anchor: bevy::sprite::Anchor::TopLeft,
+            ..default()
+        },
+        ServerInfoText,
+        Pickable::IGNORE,
+    )
+}
+
+/// Spawns a single default server, for when we need one right away rather
+/// than through `AddNewServer`
+pub struct SpawnServer(pub Vec3);
+
+impl Command for SpawnServer {
+    fn apply(self, world: &mut World) {
+        spawn_server(world, self.0);
+    }
+}
+
+/// Marks servers that came with the level, see `LockedServer`
+#[derive(Component)]
+pub struct Locked;
+
+pub struct SpawnLockedServer(pub LockedServer);
+
+impl Command for SpawnLockedServer {
+    fn apply(self, world: &mut World) {
+        let texture = {
+            let image_assets = world.resource::<ImageAssets>();
+            match self.0.mode {
+                ServerMode::Process => image_assets.server.clone(),
+                ServerMode::Proxy => image_assets.server_proxy.clone(),
+            }
+        };
+        let e_server = spawn_server(world, Vec3::new(self.0.x, self.0.y, 5.0));
+        let mut entity = world.entity_mut(e_server);
+        entity.insert(Locked);
+        *entity.get_mut::<Handle<Image>>().unwrap() = texture;
+        entity.get_mut::<Sprite>().unwrap().color = server_tint(true);
+        entity.get_mut::<Server>().unwrap().mode = self.0.mode;
+    }
+}
+
+/// How a server looks without any overlay on top. Locked ones are greyed
+/// out a bit so it's obvious they can't be touched.
+pub fn server_tint(locked: bool) -> Color {
+    if locked {
+        Color::srgb(0.6, 0.6, 0.6)
+    } else {
+        Color::WHITE
+    }
+}
+
+/// Where the level's free servers go, lined up left to right
+pub fn new_server_position(index: usize) -> Vec3 {
+    Vec3::new(-200.0 + index as f32 * 100.0, 0.0, 5.0)
+}
+
+pub fn spawn_server(world: &mut World, translation: Vec3) -> Entity {
+    let texture = world.resource::<ImageAssets>().server.clone();
+    let order = world.resource_mut::<NextSpawnOrder>().take();
+    world
+        .spawn(server_bundle(texture, translation, order))
+        .with_children(|subcommands| {
+            subcommands.spawn(server_info_text_bundle());
+        })
+        .id()
+}
+
 fn add_new_server(
     mut evs: EventReader<AddNewServer>,
     mut commands: Commands,
@@ -514,66 +890,26 @@ fn add_new_server(
     // mut q_load_balancer: Query<(Entity, &mut LoadBalancer)>,
     asset_server: Res<AssetServer>,
     image_assets: Res<ImageAssets>,
+    mut spawn_order: ResMut<NextSpawnOrder>,
     // mut evs_select: EventWriter<SelectEvent>,
 ) {
     for ev in evs.read() {
         #[allow(unused_assignments)]
         let mut x_offset = 0.0;
         for i in 0..ev.0 {
-            x_offset = -200.0 + (i as f32 * 100.0);
+            x_offset = new_server_position(i).x;
             // Update load balancer with reference to all existing servers
             let mut servers: Vec<Entity> = q_servers.iter().sort::<Entity>().collect();
             // let font_handle = asset_server.load("fonts/MajorMonoDisplay-Regular.ttf");
 
             let new_server = commands
-                .spawn((
-                    // LevelOwned, // TODO don't want to replace these each level...
-                    SpriteBundle {
-                        texture: image_assets.server.clone(),
-                        transform: Transform::from_xyz(x_offset, 0.0, 5.0)
-                            .with_scale(Vec3::splat(0.25)),
-                        ..default()
-                    },
-                    Server::default(),
-                    PickableBundle::default(),
-                    NoDeselect,
-                    // On::<Pointer<Click>>::send_event::<SelectEvent>(),
-                    On::<Pointer<Drag>>::send_event::<DragServerEvent>(),
-                    // On::<Pointer<Drag>>::target_component_mut::<Transform>(|drag, transform| {
-                    //     transform.translation.x += drag.delta.x;
-                    //     transform.translation.y -= drag.delta.y;
-                    // }),
-                    On::<Pointer<DragStart>>::send_event::<DragServerStartEvent>(),
-                    On::<Pointer<DragEnd>>::send_event::<DragServerEndEvent>(),
-                    // On::<Pointer<DragStart>>::target_commands_mut(|drag_start, target_commands| {
-                    //     println!("Start Drag!");
-                    // }),
-                    // On::<Pointer<Drag>>::target_commands_mut(|drag, target_commands| {
-                    //     println!("Dragging!");
-                    // }),
-                    // On::<Pointer<DragEnd>>::target_commands_mut(|drag_end, target_commands| {
-                    //     println!("End Drag!");
-                    // }),
+                .spawn(server_bundle(
+                    image_assets.server.clone(),
+                    Vec3::new(x_offset, 0.0, 5.0),
+                    spawn_order.take(),
                 ))
                 .with_children(|subcommands| {
-                    subcommands.spawn((
-                        Text2dBundle {
-                            transform: Transform::from_translation(Vec3::new(-150.0, -160.0, 10.0)),
-                            text: Text::from_section(
-                                "",
-                                TextStyle {
-                                    font_size: 22.0 * 2.0, // Because of parent scale
-                                    // font: font_handle,
-                                    ..default()
-                                },
-                            )
-                            .with_justify(JustifyText::Left),
-                            text_anchor: bevy::sprite::Anchor::TopLeft,
-                            ..default()
-                        },
-                        ServerInfoText,
-                        Pickable::IGNORE,
-                    ));
+                    subcommands.spawn(server_info_text_bundle());
                 })
                 // .with_children(|subcommands| {
                 //     let mesh = meshes.add(Circle { radius: 50.0 }).into();
@@ -660,16 +996,34 @@ fn align_servers_to_grid(
     }
 }
 
+// Lined up next to the server in the order they'll be worked on, the one
+// closest to the server is next
 fn align_queued_requests(
     mut commands: Commands,
     q_servers: Query<(&Transform, &Server), Without<Request>>,
-    mut q_request: Query<(&mut Transform, &Request, Option<&Animator<Transform>>), Without<Server>>,
+    mut q_request: Query<
+        (
+            &mut Transform,
+            &Request,
+            &mut Sprite,
+            Option<&Animator<Transform>>,
+        ),
+        Without<Server>,
+    >,
 ) {
     let y_offset = 30.0;
     for (t_server, server) in q_servers.iter() {
+        // Back to normal once it's picked up
+        if let Some(e_request) = server.current_request {
+            if let Ok((_, _, mut sprite, _)) = q_request.get_mut(e_request) {
+                sprite.color = Color::WHITE;
+            }
+        }
         if server.queued_requests.len() > 0 {
             for (index, e_request) in server.queued_requests.clone().iter_mut().enumerate() {
-                let (t_request, _request, animator) = q_request.get_mut(*e_request).unwrap();
+                let (t_request, request, mut sprite, animator) =
+                    q_request.get_mut(*e_request).unwrap();
+                sprite.color = queued_request_color(server.discipline, request);
                 match animator {
                     Some(_) => {
                         // Already moving to position...
@@ -703,3 +1057,24 @@ fn align_queued_requests(
         }
     }
 }
+
+// Shows what the discipline cares about while the request waits
+fn queued_request_color(discipline: QueueDiscipline, request: &Request) -> Color {
+    match discipline {
+        // Batch requests get dimmed, they'll have to wait their turn
+        QueueDiscipline::Priority if RequestKind::of(request.size) == RequestKind::Batch => {
+            Color::srgb(0.5, 0.6, 0.9)
+        }
+        // Smaller requests are brighter
+        QueueDiscipline::ShortestJobFirst => {
+            let shade = 1.0 - request.size as f32 / 64.0;
+            Color::srgb(shade, shade, 1.0)
+        }
+        // Redder the closer it gets to being dropped
+        QueueDiscipline::CoDel => {
+            let waited = (request.trace.waiting(request.age) / CODEL_TARGET).min(1.0);
+            Color::srgb(1.0, 1.0 - waited, 1.0 - waited)
+        }
+        _ => Color::WHITE,
+    }
+}
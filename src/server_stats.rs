@@ -0,0 +1,254 @@
+use crate::prelude::*;
+use bevy::color::palettes::{css::WHITE_SMOKE, tailwind::*};
+use bevy::window::PrimaryWindow;
+use bevy_mod_picking::prelude::PickingInteraction;
+
+pub struct ServerStatsPlugin;
+
+impl Plugin for ServerStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HeatmapOverlay>()
+            .add_event::<ToggleHeatmapEvent>()
+            .add_systems(OnEnter(Simulating), spawn_server_stats_ui)
+            .add_systems(OnEnter(GameState::Results), spawn_server_stats_ui)
+            .add_systems(OnExit(Simulating), clear_entity_with::<ServerStatsUI>)
+            .add_systems(
+                OnExit(GameState::Results),
+                clear_entity_with::<ServerStatsUI>,
+            )
+            .add_systems(OnEnter(GameState::Planning), clear_heatmap)
+            .add_systems(
+                Update,
+                (
+                    toggle_heatmap.run_if(
+                        input_just_pressed(KeyCode::KeyH).or_else(on_event::<ToggleHeatmapEvent>()),
+                    ),
+                    draw_heatmap,
+                    show_server_inspector,
+                )
+                    .chain()
+                    .run_if(in_state(Simulating).or_else(in_state(GameState::Results))),
+            );
+    }
+}
+
+/// What a single server got up to during the run
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct ServerStats {
+    // Showed up at the server, whether it had room or not
+    pub arrived: usize,
+    pub handled: usize,
+    pub dropped: usize,
+    pub forwarded: usize,
+    // Seconds spent working on a request
+    pub busy_time: f32,
+    pub max_queue_depth: usize,
+}
+
+impl ServerStats {
+    /// 0.0 <> 1.0 of `elapsed` seconds spent busy
+    pub fn utilisation(&self, elapsed: f32) -> f32 {
+        if elapsed <= 0.0 {
+            0.0
+        } else {
+            (self.busy_time / elapsed).min(1.0)
+        }
+    }
+}
+
+/// Tints servers by how busy they've been when on
+#[derive(Resource, Default)]
+pub struct HeatmapOverlay(pub bool);
+
+#[derive(Event)]
+pub struct ToggleHeatmapEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for ToggleHeatmapEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        ToggleHeatmapEvent
+    }
+}
+
+#[derive(Component)]
+pub struct ServerStatsUI;
+
+#[derive(Component)]
+struct ServerInspector;
+
+/// Seconds since the run started, on the fixed clock
+pub fn run_elapsed(clock: &RunClock, fixed_time: &Time<Fixed>) -> f32 {
+    clock.ticks as f32 * fixed_time.timestep().as_secs_f32()
+}
+
+pub(crate) fn reset_server_stats(mut query: Query<&mut ServerStats>) {
+    for mut stats in query.iter_mut() {
+        *stats = ServerStats::default();
+    }
+}
+
+pub(crate) fn track_server_activity(
+    time: Res<Time>,
+    mut query: Query<(&Server, &mut ServerStats), Without<Reconfiguring>>,
+) {
+    for (server, mut stats) in query.iter_mut() {
+        if server.current_request.is_some() {
+            stats.busy_time += time.delta_seconds();
+        }
+        stats.max_queue_depth = stats.max_queue_depth.max(server.queued_requests.len());
+    }
+}
+
+fn toggle_heatmap(mut heatmap: ResMut<HeatmapOverlay>) {
+    heatmap.0 = !heatmap.0;
+    println!("Heatmap overlay: {}", heatmap.0);
+}
+
+fn clear_heatmap(mut query: Query<(&mut Sprite, Has<Locked>), With<Server>>) {
+    for (mut sprite, locked) in query.iter_mut() {
+        sprite.color = server_tint(locked);
+    }
+}
+
+// Green when idle, through amber, to red when busy all the time
+fn heat_color(utilisation: f32) -> Color {
+    let cold = Color::from(GREEN_400);
+    let warm = Color::from(AMBER_400);
+    let hot = Color::from(RED_500);
+    if utilisation < 0.5 {
+        cold.mix(&warm, utilisation * 2.0)
+    } else {
+        warm.mix(&hot, (utilisation - 0.5) * 2.0)
+    }
+}
+
+fn draw_heatmap(
+    heatmap: Res<HeatmapOverlay>,
+    clock: Res<RunClock>,
+    fixed_time: Res<Time<Fixed>>,
+    mut query: Query<(&ServerStats, &mut Sprite, Has<Locked>)>,
+) {
+    let elapsed = run_elapsed(&clock, &fixed_time);
+    for (stats, mut sprite, locked) in query.iter_mut() {
+        let color = if heatmap.0 {
+            heat_color(stats.utilisation(elapsed))
+        } else {
+            server_tint(locked)
+        };
+        // Only touch it when it changes, off is the same colour every frame
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
+
+fn spawn_server_stats_ui(mut commands: Commands) {
+    commands
+        .spawn((
+            ServerStatsUI,
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    // Top middle, the corners are taken during replays
+                    top: Val::Px(10.0),
+                    left: Val::Percent(50.0),
+                    margin: UiRect::left(Val::Px(-80.0)),
+                    width: Val::Px(160.0),
+                    height: Val::Px(40.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: BLUE_400.into(),
+                ..default()
+            },
+            PickableBundle::default(),
+            On::<Pointer<Click>>::send_event::<ToggleHeatmapEvent>(),
+            Hoverable(BLUE_400, BLUE_500, BLUE_600),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Heatmap (H)",
+                    TextStyle {
+                        font_size: 20.0,
+                        color: WHITE_SMOKE.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+        });
+
+    commands
+        .spawn((
+            ServerStatsUI,
+            ServerInspector,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                background_color: Color::srgba(0.15, 0.15, 0.15, 0.9).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 18.0,
+                        color: WHITE_SMOKE.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+        });
+}
+
+// Tooltip with the server's numbers, next to the cursor while hovering it
+fn show_server_inspector(
+    q_servers: Query<(&Server, &ServerStats, &PickingInteraction)>,
+    mut q_inspector: Query<(&mut Style, &mut Visibility, &Children), With<ServerInspector>>,
+    mut q_text: Query<&mut Text>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    clock: Res<RunClock>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let Ok((mut style, mut visibility, children)) = q_inspector.get_single_mut() else {
+        return;
+    };
+    let hovered = q_servers
+        .iter()
+        .find(|(_, _, interaction)| **interaction != PickingInteraction::None);
+    let cursor = q_window.get_single().ok().and_then(|w| w.cursor_position());
+
+    let (Some((server, stats, _)), Some(cursor)) = (hovered, cursor) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Visible;
+    style.left = Val::Px(cursor.x + 20.0);
+    style.top = Val::Px(cursor.y + 20.0);
+
+    let elapsed = run_elapsed(&clock, &fixed_time);
+    if let Ok(mut text) = q_text.get_mut(children[0]) {
+        text.sections[0].value = format!(
+            "{:?} server\nArrived: {}\nHandled: {}\nDropped: {}\nForwarded: {}\nBusy: {:.0}% ({:.1}s)\nMax queue: {} / {}",
+            server.mode,
+            stats.arrived,
+            stats.handled,
+            stats.dropped,
+            stats.forwarded,
+            stats.utilisation(elapsed) * 100.0,
+            stats.busy_time,
+            stats.max_queue_depth,
+            server.queue_size,
+        );
+    }
+}
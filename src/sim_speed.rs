@@ -0,0 +1,244 @@
+use crate::prelude::*;
+use bevy::color::palettes::{css::WHITE_SMOKE, tailwind::*};
+
+pub const SIM_SPEEDS: [f32; 4] = [0.5, 1.0, 2.0, 4.0];
+
+pub struct SimSpeedPlugin;
+
+impl Plugin for SimSpeedPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationSpeed>()
+            .add_event::<SpeedControlEvent>()
+            .add_event::<SpeedButtonEvent>()
+            .add_systems(OnEnter(Simulating), spawn_speed_ui)
+            .add_systems(
+                OnExit(Simulating),
+                (reset_speed, clear_entity_with::<SpeedUI>),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_speed_buttons,
+                    speed_shortcuts,
+                    apply_speed_controls,
+                    update_speed_ui,
+                )
+                    .chain()
+                    .run_if(in_state(Simulating)),
+            )
+            .add_systems(Update, sync_virtual_time);
+    }
+}
+
+/// How fast the simulation runs. Everything in `FixedUpdate` (request
+/// processing, movement, load schedules) follows virtual time, so scaling
+/// that scales the whole simulation evenly.
+#[derive(Resource, Debug)]
+pub struct SimulationSpeed {
+    pub speed: f32,
+    pub paused: bool,
+    // Run at this speed for now instead, without forgetting what the player picked
+    pub fast_forward: Option<f32>,
+}
+
+impl Default for SimulationSpeed {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            paused: false,
+            fast_forward: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeedControl {
+    TogglePause,
+    SetSpeed(f32),
+}
+
+#[derive(Event)]
+pub struct SpeedControlEvent(pub SpeedControl);
+
+#[derive(Component)]
+struct SpeedControlButton(SpeedControl);
+
+#[derive(Event)]
+pub struct SpeedButtonEvent(pub Entity);
+
+impl From<ListenerInput<Pointer<Click>>> for SpeedButtonEvent {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        SpeedButtonEvent(event.target)
+    }
+}
+
+#[derive(Component)]
+pub struct SpeedUI;
+
+#[derive(Component)]
+struct SpeedText;
+
+fn sync_virtual_time(speed: Res<SimulationSpeed>, mut time: ResMut<Time<Virtual>>) {
+    if !speed.is_changed() {
+        return;
+    }
+    time.set_relative_speed(speed.fast_forward.unwrap_or(speed.speed));
+    if speed.paused && speed.fast_forward.is_none() {
+        time.pause();
+    } else {
+        time.unpause();
+    }
+}
+
+fn reset_speed(mut speed: ResMut<SimulationSpeed>) {
+    *speed = SimulationSpeed::default();
+}
+
+fn handle_speed_buttons(
+    mut evs: EventReader<SpeedButtonEvent>,
+    q_buttons: Query<&SpeedControlButton>,
+    mut evs_control: EventWriter<SpeedControlEvent>,
+) {
+    for ev in evs.read() {
+        if let Ok(button) = q_buttons.get(ev.0) {
+            evs_control.send(SpeedControlEvent(button.0));
+        }
+    }
+}
+
+fn speed_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut evs_control: EventWriter<SpeedControlEvent>,
+) {
+    if keys.just_pressed(KeyCode::Space) {
+        evs_control.send(SpeedControlEvent(SpeedControl::TogglePause));
+    }
+    let keys_for_speeds = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+    ];
+    for (key, speed) in keys_for_speeds.into_iter().zip(SIM_SPEEDS) {
+        if keys.just_pressed(key) {
+            evs_control.send(SpeedControlEvent(SpeedControl::SetSpeed(speed)));
+        }
+    }
+}
+
+fn apply_speed_controls(
+    mut evs: EventReader<SpeedControlEvent>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    for ev in evs.read() {
+        match ev.0 {
+            SpeedControl::TogglePause => {
+                speed.paused = !speed.paused;
+            }
+            SpeedControl::SetSpeed(new_speed) => {
+                speed.speed = new_speed;
+                speed.paused = false;
+            }
+        }
+        println!("Simulation speed: {:?}", *speed);
+    }
+}
+
+fn spawn_speed_ui(mut commands: Commands) {
+    let mut buttons = vec![("Pause", SpeedControl::TogglePause)];
+    for speed in SIM_SPEEDS {
+        buttons.push((
+            match speed {
+                0.5 => "0.5x",
+                1.0 => "1x",
+                2.0 => "2x",
+                _ => "4x",
+            },
+            SpeedControl::SetSpeed(speed),
+        ));
+    }
+
+    commands
+        .spawn((
+            SpeedUI,
+            NodeBundle {
+                style: Style {
+                    bottom: Val::Px(10.0),
+                    width: Val::Percent(100.0),
+                    height: Val::Px(40.0),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Row,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 22.0,
+                        color: WHITE_SMOKE.into(),
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    width: Val::Px(120.0),
+                    ..default()
+                }),
+                SpeedText,
+                Pickable::IGNORE,
+            ));
+            for (label, control) in buttons {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(80.0),
+                                height: Val::Px(40.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: BLUE_400.into(),
+                            ..default()
+                        },
+                        SpeedControlButton(control),
+                        PickableBundle::default(),
+                        On::<Pointer<Click>>::send_event::<SpeedButtonEvent>(),
+                        Hoverable(BLUE_400, BLUE_500, BLUE_600),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section(
+                                label,
+                                TextStyle {
+                                    font_size: 20.0,
+                                    color: WHITE_SMOKE.into(),
+                                    ..default()
+                                },
+                            ),
+                            Pickable::IGNORE,
+                        ));
+                    });
+            }
+        });
+}
+
+fn update_speed_ui(speed: Res<SimulationSpeed>, mut q_text: Query<&mut Text, With<SpeedText>>) {
+    if !speed.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = q_text.get_single_mut() {
+        text.sections[0].value = if speed.paused {
+            "Paused".to_string()
+        } else {
+            format!("Speed: {}x", speed.speed)
+        };
+    }
+}
@@ -0,0 +1,66 @@
+use crate::prelude::*;
+
+/// The rules of a run: requests getting spawned, moved between servers and
+/// handled or dropped, and whether the level passed at the end. Nothing
+/// here draws or reads input, so `headless.rs` runs the same systems
+/// without a window.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameStats>()
+            .init_resource::<SimRng>()
+            .init_resource::<RunClock>()
+            .init_resource::<GameLevels>()
+            .init_resource::<UpgradePoints>()
+            .init_resource::<NextSpawnOrder>()
+            .init_resource::<SloTracker>()
+            .init_resource::<LevelResults>()
+            .init_resource::<RequestsInFlight>()
+            .add_event::<StartLoadScenarios>()
+            .add_event::<PlaySound>()
+            .add_event::<WaveClearedEvent>()
+            .add_systems(
+                OnEnter(GameState::Running),
+                (reset_run_clock, reset_server_stats, reset_slo_tracker),
+            )
+            .add_systems(
+                Update,
+                start_load_scenarios.run_if(on_event::<StartLoadScenarios>()),
+            )
+            .add_systems(FixedFirst, tick_run_clock.run_if(in_state(Simulating)))
+            // One order for every step, so the same seed plays out the same
+            .add_systems(
+                FixedUpdate,
+                (
+                    spawn_requests_based_on_load_scenario
+                        .run_if(on_timer(Duration::from_millis(100)))
+                        .run_if(in_state(GameState::Running)),
+                    count_requests_in_flight,
+                    assign_requests_to_ingress.run_if(in_state(Simulating)),
+                    move_requests_to_destination.run_if(in_state(Simulating)),
+                    drop_stale_requests,
+                    process_requests,
+                    move_dropped_requests.run_if(in_state(Simulating)),
+                    increment_request_elapsed_time.run_if(in_state(Simulating)),
+                    track_server_activity.run_if(in_state(Simulating)),
+                    tick_reconfigurations.run_if(in_state(Simulating)),
+                )
+                    .chain(),
+            )
+            // Same spot as `archive_traces`, after requests are marked done
+            // and before they're despawned
+            .add_systems(
+                FixedPostUpdate,
+                track_slo
+                    .run_if(in_state(GameState::Running))
+                    .run_if(level_has_slo),
+            )
+            .add_systems(FixedLast, handle_removals)
+            .add_systems(OnExit(Simulating), finish_all_reconfigurations)
+            .add_systems(
+                OnEnter(GameState::Results),
+                calculate_pass_or_not.run_if(not(resource_exists::<BackFromReplay>)),
+            );
+    }
+}
@@ -0,0 +1,322 @@
+use crate::prelude::*;
+use bevy::color::palettes::tailwind::*;
+use serde::{Deserialize, Serialize};
+
+pub struct SloPlugin;
+
+impl Plugin for SloPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::Running),
+            spawn_slo_ui.after(reset_slo_tracker),
+        )
+        .add_systems(
+            Update,
+            update_slo_ui
+                .run_if(in_state(GameState::Running))
+                .run_if(level_has_slo),
+        );
+    }
+}
+
+/// A reliability target for a level, like "99% of requests under 20ms over
+/// any 10s window". Dropped requests always count as bad.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Slo {
+    // 0.0 <> 1.0 share of requests that have to be good
+    pub target: f32,
+    // in seconds (ms in game), anything slower counts as bad
+    pub max_response_time: f32,
+    // in seconds, the target has to hold over every window this long
+    pub window: f32,
+}
+
+// 99% of requests under 20ms over any 10s window
+impl Default for Slo {
+    fn default() -> Self {
+        Self {
+            target: 0.99,
+            max_response_time: 2.0,
+            window: 10.0,
+        }
+    }
+}
+
+impl Slo {
+    /// Share of requests that are allowed to be bad
+    pub fn error_rate(&self) -> f32 {
+        1.0 - self.target
+    }
+}
+
+/// A stretch of the run, and how its requests did
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct SloWindow {
+    // Seconds since the run started
+    pub start: f32,
+    pub end: f32,
+    pub good: usize,
+    pub total: usize,
+}
+
+impl SloWindow {
+    pub fn good_ratio(&self) -> f32 {
+        self.good as f32 / self.total.max(1) as f32
+    }
+}
+
+/// How a run did against its level's SLO
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SloReport {
+    pub slo: Slo,
+    pub met: bool,
+    // How many bad requests the run could afford, and how many it had
+    pub budget: f32,
+    pub bad_requests: usize,
+    // Seconds in when the budget ran out, if it did
+    pub exhausted_at: Option<f32>,
+    // The first full window that fell below the target, that's what blew it
+    pub first_breach: Option<SloWindow>,
+    pub worst_window: Option<SloWindow>,
+}
+
+impl SloReport {
+    /// Lines for the results screen
+    pub fn explain(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "SLO: {:.1}% of requests under {:.0}ms over any {:.0}s window",
+            self.slo.target * 100.0,
+            self.slo.max_response_time * 10.0,
+            self.slo.window
+        )];
+        match self.exhausted_at {
+            Some(at) => lines.push(format!(
+                "The error budget of {:.0} bad requests ran out {at:.1}s in, {} in total",
+                self.budget, self.bad_requests
+            )),
+            None => lines.push(format!(
+                "{} of {:.0} bad requests from the error budget used",
+                self.bad_requests, self.budget
+            )),
+        }
+        match self.first_breach {
+            Some(window) => lines.push(format!(
+                "It broke between {:.1}s and {:.1}s, only {}/{} requests ({:.1}%) were good",
+                window.start,
+                window.end,
+                window.good,
+                window.total,
+                window.good_ratio() * 100.0
+            )),
+            // The budget is for the whole run, the SLO is judged per window
+            None if self.exhausted_at.is_some() => lines.push(format!(
+                "No {:.0}s window fell below the target though, so the SLO held",
+                self.slo.window
+            )),
+            None => lines.push(format!(
+                "Every {:.0}s window stayed within the SLO",
+                self.slo.window
+            )),
+        }
+        lines
+    }
+}
+
+/// Keeps track of every finished request during a run, for the level's SLO
+#[derive(Resource, Default, Debug, Clone)]
+pub struct SloTracker {
+    pub slo: Option<Slo>,
+    // Bad requests the level can afford, based on how many it sends
+    pub budget: f32,
+    // When each request finished (seconds since the start) and if it was good
+    outcomes: Vec<(f32, bool)>,
+    bad: usize,
+    exhausted_at: Option<f32>,
+}
+
+impl SloTracker {
+    pub fn new(level: &Level) -> Self {
+        let budget = level
+            .slo
+            .map_or(0.0, |slo| slo.error_rate() * level.expected_requests());
+        Self {
+            slo: level.slo,
+            budget,
+            ..default()
+        }
+    }
+
+    /// A request finished `time` seconds in, with its response time, or
+    /// None if it got dropped
+    pub fn record(&mut self, time: f32, response_time: Option<f32>) {
+        let Some(slo) = self.slo else {
+            return;
+        };
+        let good = response_time.is_some_and(|age| age <= slo.max_response_time);
+        self.outcomes.push((time, good));
+        if !good {
+            self.bad += 1;
+            if self.exhausted_at.is_none() && self.bad as f32 > self.budget {
+                self.exhausted_at = Some(time);
+            }
+        }
+    }
+
+    /// 1.0 untouched, 0.0 all gone
+    pub fn budget_left(&self) -> f32 {
+        if self.budget <= 0.0 {
+            return if self.bad == 0 { 1.0 } else { 0.0 };
+        }
+        (1.0 - self.bad as f32 / self.budget).max(0.0)
+    }
+
+    /// How fast the budget is going over the last window, 1.0 is spending it
+    /// exactly as fast as the SLO allows
+    pub fn burn_rate(&self, now: f32) -> f32 {
+        let Some(slo) = self.slo else {
+            return 0.0;
+        };
+        let (bad, total) = self
+            .outcomes
+            .iter()
+            .rev()
+            .take_while(|(time, _)| *time >= now - slo.window)
+            .fold((0, 0), |(bad, total), (_, good)| {
+                (bad + !good as usize, total + 1)
+            });
+        if total == 0 {
+            return 0.0;
+        }
+        (bad as f32 / total as f32) / slo.error_rate().max(f32::EPSILON)
+    }
+
+    pub fn report(&self) -> Option<SloReport> {
+        let slo = self.slo?;
+        // Slide a window over the run, ending at every finished request. Only
+        // full windows get judged, a couple of early drops aren't a window's
+        // worth. A run shorter than one window gets judged as a whole.
+        let last = self.outcomes.len().saturating_sub(1);
+        let run_end = self.outcomes.last().map_or(0.0, |(time, _)| *time);
+        let mut first_breach = None;
+        let mut worst_window: Option<SloWindow> = None;
+        let mut start = 0;
+        let mut good = 0;
+        for (end, &(end_time, end_good)) in self.outcomes.iter().enumerate() {
+            good += end_good as usize;
+            while self.outcomes[start].0 < end_time - slo.window {
+                good -= self.outcomes[start].1 as usize;
+                start += 1;
+            }
+            let full = end_time >= slo.window || (end == last && run_end < slo.window);
+            if !full {
+                continue;
+            }
+            let window = SloWindow {
+                start: (end_time - slo.window).max(0.0),
+                end: end_time,
+                good,
+                total: end - start + 1,
+            };
+            if window.good_ratio() < slo.target && first_breach.is_none() {
+                first_breach = Some(window);
+            }
+            if worst_window.map_or(true, |worst| window.good_ratio() < worst.good_ratio()) {
+                worst_window = Some(window);
+            }
+        }
+        Some(SloReport {
+            slo,
+            met: first_breach.is_none(),
+            budget: self.budget,
+            bad_requests: self.bad,
+            exhausted_at: self.exhausted_at,
+            first_breach,
+            worst_window,
+        })
+    }
+}
+
+pub fn level_has_slo(tracker: Res<SloTracker>) -> bool {
+    tracker.slo.is_some()
+}
+
+#[derive(Component)]
+struct SloBudgetText;
+
+#[derive(Component)]
+struct SloBurnRateText;
+
+pub(crate) fn reset_slo_tracker(mut tracker: ResMut<SloTracker>, game_levels: Res<GameLevels>) {
+    *tracker = SloTracker::new(game_levels.active_level());
+}
+
+pub(crate) fn track_slo(
+    mut tracker: ResMut<SloTracker>,
+    clock: Res<RunClock>,
+    fixed_time: Res<Time<Fixed>>,
+    query: Query<
+        (&Request, Option<Ref<DroppedRequest>>),
+        Or<(Added<ToRemove>, Added<DroppedRequest>)>,
+    >,
+) {
+    let now = run_elapsed(&clock, &fixed_time);
+    for (request, dropped) in query.iter() {
+        match dropped {
+            // Counted when it got dropped, not again when it's removed
+            Some(dropped) if !dropped.is_added() => {}
+            Some(_) => tracker.record(now, None),
+            None => tracker.record(now, Some(request.age)),
+        }
+    }
+}
+
+fn spawn_slo_ui(mut commands: Commands, font_assets: Res<FontAssets>, tracker: Res<SloTracker>) {
+    if tracker.slo.is_none() {
+        return;
+    }
+    spawn_text(
+        RunningUI,
+        &font_assets,
+        &mut commands,
+        "Error Budget",
+        4,
+        SloBudgetText,
+    );
+    spawn_text(
+        RunningUI,
+        &font_assets,
+        &mut commands,
+        "Burn Rate",
+        5,
+        SloBurnRateText,
+    );
+}
+
+fn update_slo_ui(
+    tracker: Res<SloTracker>,
+    clock: Res<RunClock>,
+    fixed_time: Res<Time<Fixed>>,
+    mut q_budget: Query<&mut Text, (With<SloBudgetText>, Without<SloBurnRateText>)>,
+    mut q_burn_rate: Query<&mut Text, With<SloBurnRateText>>,
+) {
+    if let Ok(mut text) = q_budget.get_single_mut() {
+        let left = tracker.budget_left();
+        text.sections[1].value = format!("{:.0}% left", left * 100.0);
+        text.sections[1].style.color = match left {
+            left if left <= 0.0 => RED_400,
+            left if left < 0.5 => ORANGE_400,
+            _ => GREEN_400,
+        }
+        .into();
+    }
+    if let Ok(mut text) = q_burn_rate.get_single_mut() {
+        let burn_rate = tracker.burn_rate(run_elapsed(&clock, &fixed_time));
+        text.sections[1].value = format!("{burn_rate:.1}x");
+        // Above 1x the budget runs out before the level does
+        text.sections[1].style.color = if burn_rate > 1.0 {
+            RED_400.into()
+        } else {
+            GREEN_400.into()
+        };
+    }
+}
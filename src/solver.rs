@@ -0,0 +1,332 @@
+use crate::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+// Temperature the annealing starts and ends at, in score points. A worse
+// layout is kept with a chance of e^(difference / temperature).
+const START_TEMPERATURE: f32 = 0.3;
+const END_TEMPERATURE: f32 = 0.002;
+
+/// How hard `solve` tries
+#[derive(Debug, Clone)]
+pub struct SolverSettings {
+    // Every layout is played once per seed, it only passes if it passes all
+    pub seeds: Vec<u64>,
+    // Layouts tried per restart
+    pub iterations: usize,
+    pub restarts: usize,
+    // Without proxies, only upgrades and the DNS records can be changed
+    pub allow_proxies: bool,
+    // For the search itself, not the requests
+    pub rng_seed: u64,
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        Self {
+            seeds: vec![0, 1, 2],
+            iterations: 400,
+            restarts: 4,
+            allow_proxies: true,
+            rng_seed: 0,
+        }
+    }
+}
+
+/// How a layout did over all the seeds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    // Mean of the gym reward, see `score`
+    pub score: f32,
+    pub passed: usize,
+    pub runs: usize,
+    // Means, handled is 0.0 <> 1.0 and response times are in seconds
+    pub handled: f32,
+    pub avg_response_time: f32,
+}
+
+impl Evaluation {
+    pub fn passed_all(&self) -> bool {
+        self.runs > 0 && self.passed == self.runs
+    }
+}
+
+pub fn evaluate(level: &Level, blueprint: &Blueprint, seeds: &[u64]) -> Evaluation {
+    let mut evaluation = Evaluation {
+        score: 0.0,
+        passed: 0,
+        runs: seeds.len(),
+        handled: 0.0,
+        avg_response_time: 0.0,
+    };
+    for seed in seeds {
+        let outcome = simulate(level, blueprint, *seed);
+        evaluation.score += score(level, &outcome);
+        evaluation.passed += outcome.results.passed as usize;
+        evaluation.handled += outcome.results.current_percentage.max(0.0);
+        evaluation.avg_response_time += outcome.stats.avg_response_time;
+    }
+    let runs = seeds.len().max(1) as f32;
+    evaluation.score /= runs;
+    evaluation.handled /= runs;
+    evaluation.avg_response_time /= runs;
+    evaluation
+}
+
+/// Best layout found for a level
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub blueprint: Blueprint,
+    pub evaluation: Evaluation,
+}
+
+/// Spends `points` on one server, CPU first as far as it goes, then queue
+pub fn spend_points(server: &mut BlueprintServer, points: usize) {
+    let mut points = points;
+    while server.processing_power <= points {
+        points -= server.processing_power;
+        server.processing_power += 1;
+    }
+    server.queue_size += points;
+}
+
+/// Every server processing, as many of them in the DNS records as the level
+/// allows with weighted balancing, points shared out evenly
+pub fn spread_layout(level_index: usize, level: &Level) -> Blueprint {
+    let mut blueprint = default_layout(level_index, level);
+    let count = level.available_servers.max(1);
+    for server in blueprint.servers.iter_mut().take(level.available_servers) {
+        spend_points(server, level.upgrade_points / count);
+    }
+    blueprint.ingress = (0..blueprint.servers.len())
+        .take(level.max_dns_records.unwrap_or(usize::MAX))
+        .collect();
+    blueprint.ingress_algorithm = LoadBalancingAlgorithm::Weighted;
+    blueprint
+}
+
+/// The first server proxies to all the others, everyone gets the same
+/// points. What most players end up building.
+pub fn fan_out_layout(level_index: usize, level: &Level) -> Blueprint {
+    let mut blueprint = spread_layout(level_index, level);
+    if blueprint.servers.len() > 1 {
+        blueprint.servers[0].mode = ServerMode::Proxy;
+        blueprint.servers[0].outputs = (1..blueprint.servers.len()).collect();
+        blueprint.ingress = vec![0];
+        blueprint.ingress_algorithm = LoadBalancingAlgorithm::RoundRobin;
+    }
+    blueprint
+}
+
+/// Layouts TODO.md says shouldn't be able to pass a level: every point on
+/// one server, as CPU, as queue, or split between both, with the rest of
+/// the servers left as they are. Each is tried with the upgraded server
+/// alone in the DNS records and, if the level allows it, with all servers
+/// in them.
+pub fn trivial_strategies(level_index: usize, level: &Level) -> Vec<(String, Blueprint)> {
+    let points = level.upgrade_points;
+    // Every CPU level the points can pay for, the rest goes to the queue
+    let splits: Vec<(usize, usize)> = (1..)
+        .map(|power| (power, spent_points(power, 0)))
+        .take_while(|(_, cost)| *cost <= points)
+        .map(|(power, cost)| (power, points - cost))
+        .collect();
+
+    let mut strategies = vec![];
+    for index in 0..level.available_servers {
+        for (power, queue) in &splits {
+            let upgrades = [
+                ("CPU", *power, 0),
+                ("queue", 1, points),
+                ("CPU and queue", *power, *queue),
+            ];
+            for (name, processing_power, queue_size) in upgrades {
+                // The queue only layout is the same for every split
+                if name == "queue" && *power > 1 {
+                    continue;
+                }
+                for alone in [true, false] {
+                    if !alone && !level.allows_dns_records(level.available_servers) {
+                        continue;
+                    }
+                    let mut blueprint = default_layout(level_index, level);
+                    blueprint.servers[index].processing_power = processing_power;
+                    blueprint.servers[index].queue_size = queue_size;
+                    if alone {
+                        blueprint.ingress = vec![index];
+                    } else {
+                        blueprint.ingress = (0..level.available_servers).collect();
+                        blueprint.ingress_algorithm = LoadBalancingAlgorithm::Weighted;
+                    }
+                    let description = format!(
+                        "#{} {name} (CPU {processing_power}, queue {queue_size}){}",
+                        index + 1,
+                        if alone { ", alone" } else { "" }
+                    );
+                    strategies.push((description, blueprint));
+                }
+            }
+        }
+    }
+    strategies
+}
+
+// A small random change to the layout, None when what got picked isn't
+// possible, like upgrading without points left
+fn neighbour(
+    blueprint: &Blueprint,
+    level: &Level,
+    allow_proxies: bool,
+    rng: &mut StdRng,
+) -> Option<Blueprint> {
+    let mut blueprint = blueprint.clone();
+    let count = blueprint.servers.len();
+    if count == 0 {
+        return None;
+    }
+    let index = rng.gen_range(0..count);
+    let points_left = level.upgrade_points.saturating_sub(blueprint.cost());
+    let moves = if allow_proxies { 8 } else { 6 };
+    match rng.gen_range(0..moves) {
+        0 => {
+            let server = &mut blueprint.servers[index];
+            if server.processing_power > points_left {
+                return None;
+            }
+            server.processing_power += 1;
+        }
+        1 => {
+            if points_left == 0 {
+                return None;
+            }
+            blueprint.servers[index].queue_size += 1;
+        }
+        // Moving points from one server to another
+        2 => {
+            let server = &mut blueprint.servers[index];
+            if server.processing_power == 1 {
+                return None;
+            }
+            server.processing_power -= 1;
+            let freed = level.upgrade_points.saturating_sub(blueprint.cost());
+            let to = rng.gen_range(0..count);
+            spend_points(&mut blueprint.servers[to], freed);
+        }
+        3 => {
+            let server = &mut blueprint.servers[index];
+            if server.queue_size == 0 {
+                return None;
+            }
+            server.queue_size -= 1;
+            let to = rng.gen_range(0..count);
+            blueprint.servers[to].queue_size += 1;
+        }
+        4 => match blueprint.ingress.iter().position(|i| *i == index) {
+            Some(position) => {
+                blueprint.ingress.remove(position);
+            }
+            None => blueprint.ingress.push(index),
+        },
+        5 => {
+            blueprint.ingress_algorithm = match blueprint.ingress_algorithm {
+                LoadBalancingAlgorithm::RoundRobin => LoadBalancingAlgorithm::Weighted,
+                LoadBalancingAlgorithm::Weighted => LoadBalancingAlgorithm::RoundRobin,
+            };
+        }
+        6 => {
+            let server = &mut blueprint.servers[index];
+            server.mode = match server.mode {
+                ServerMode::Process => ServerMode::Proxy,
+                ServerMode::Proxy => {
+                    server.outputs.clear();
+                    ServerMode::Process
+                }
+            };
+        }
+        _ => {
+            let to = rng.gen_range(0..count);
+            if to == index {
+                return None;
+            }
+            let server = &mut blueprint.servers[index];
+            match server.outputs.iter().position(|output| *output == to) {
+                Some(position) => {
+                    server.outputs.remove(position);
+                }
+                None => {
+                    server.mode = ServerMode::Proxy;
+                    server.outputs.push(to);
+                }
+            }
+        }
+    }
+    if !respects_locked_servers(level, &blueprint)
+        || !level.allows_dns_records(blueprint.ingress.len())
+    {
+        return None;
+    }
+    Some(blueprint)
+}
+
+/// Simulated annealing over modes, connections, DNS records and where the
+/// upgrade points go. Each restart begins from one of the hand made layouts
+/// or a random one, the best layout over all of them wins.
+pub fn solve(level_index: usize, level: &Level, settings: &SolverSettings) -> Solution {
+    let mut rng = StdRng::seed_from_u64(settings.rng_seed);
+    let mut starts = vec![spread_layout(level_index, level)];
+    if settings.allow_proxies {
+        starts.push(fan_out_layout(level_index, level));
+    }
+
+    let mut best: Option<Solution> = None;
+    for restart in 0..settings.restarts.max(1) {
+        let mut current = match starts.get(restart) {
+            Some(blueprint) => blueprint.clone(),
+            None => {
+                let mut blueprint = default_layout(level_index, level);
+                for _ in 0..level.upgrade_points * 2 {
+                    if let Some(next) =
+                        neighbour(&blueprint, level, settings.allow_proxies, &mut rng)
+                    {
+                        blueprint = next;
+                    }
+                }
+                blueprint
+            }
+        };
+        let mut current_evaluation = evaluate(level, &current, &settings.seeds);
+        let mut restart_best = Solution {
+            blueprint: current.clone(),
+            evaluation: current_evaluation.clone(),
+        };
+
+        let cooling =
+            (END_TEMPERATURE / START_TEMPERATURE).powf(1.0 / settings.iterations.max(1) as f32);
+        let mut temperature = START_TEMPERATURE;
+        for _ in 0..settings.iterations {
+            temperature *= cooling;
+            let Some(candidate) = neighbour(&current, level, settings.allow_proxies, &mut rng)
+            else {
+                continue;
+            };
+            let evaluation = evaluate(level, &candidate, &settings.seeds);
+            let difference = evaluation.score - current_evaluation.score;
+            if difference >= 0.0 || rng.gen::<f32>() < (difference / temperature).exp() {
+                current = candidate;
+                current_evaluation = evaluation;
+                if current_evaluation.score > restart_best.evaluation.score {
+                    restart_best = Solution {
+                        blueprint: current.clone(),
+                        evaluation: current_evaluation.clone(),
+                    };
+                }
+            }
+        }
+
+        if best.as_ref().map_or(true, |best| {
+            restart_best.evaluation.score > best.evaluation.score
+        }) {
+            best = Some(restart_best);
+        }
+    }
+    best.expect("there's always at least one restart")
+}
@@ -6,6 +6,7 @@ impl Plugin for StatesPlugin {
     fn build(&self, app: &mut App) {
         app.init_state::<GameState>()
             .add_sub_state::<EditMode>()
+            .add_computed_state::<Simulating>()
             .enable_state_scoped_entities::<EditMode>()
             .add_systems(OnEnter(GameState::Loading), on_enter_loading)
             .add_systems(OnEnter(GameState::Intro), on_enter_intro)
@@ -24,6 +25,12 @@ pub enum GameState {
     Running,
     Results,
     GameCompleted,
+    // Watching a recorded run
+    Replay,
+    // Making a level, see editor.rs
+    Editor,
+    // Free play with live load knobs, see sandbox.rs
+    Sandbox,
 }
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, SubStates)]
@@ -34,6 +41,22 @@ pub enum EditMode {
     Outputs,
 }
 
+/// Whenever requests are flowing, be it a live run, a replay of one or the
+/// sandbox
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Simulating;
+
+impl ComputedStates for Simulating {
+    type SourceStates = GameState;
+
+    fn compute(sources: GameState) -> Option<Self> {
+        match sources {
+            GameState::Running | GameState::Replay | GameState::Sandbox => Some(Simulating),
+            _ => None,
+        }
+    }
+}
+
 //
 
 fn on_enter_loading(mut next_state: ResMut<NextState<GameState>>) {
@@ -47,9 +70,6 @@ fn on_enter_intro(mut next_state: ResMut<NextState<GameState>>) {
 }
 
 fn on_enter_level_select(mut next_state: ResMut<NextState<GameState>>) {
-    // Show selection of levels
-    // for level in ALL_LEVELS.iter() {}
-    // draw_level_selection_ui();
     println!("Entered LevelSelect, next Planning");
     // next_state.set(GameState::Planning);
 }
@@ -0,0 +1,423 @@
+use crate::prelude::*;
+use bevy::color::palettes::tailwind::*;
+
+// How many hops we follow traffic through proxies before giving up, only
+// matters when there's a cycle
+const MAX_HOPS: usize = 64;
+
+pub struct TopologyPlugin;
+
+impl Plugin for TopologyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TopologyReport>()
+            .add_event::<RequestStartEvent>()
+            .add_systems(
+                Update,
+                (analyse_topology, show_topology_issues)
+                    .chain()
+                    .run_if(in_state(GameState::Planning)),
+            )
+            .add_systems(
+                Update,
+                (
+                    clear_start_warning.run_if(resource_changed::<TopologyReport>),
+                    confirm_start.run_if(on_event::<RequestStartEvent>()),
+                    warn_before_start.run_if(on_event::<StartLoadScenarios>()),
+                )
+                    .chain()
+                    .after(analyse_topology)
+                    .run_if(in_state(GameState::Planning)),
+            )
+            .add_systems(OnEnter(GameState::Planning), reset_topology_report)
+            .add_systems(
+                OnExit(GameState::Planning),
+                (
+                    clear_entity_with::<TopologyIssueLabel>,
+                    clear_entity_with::<StartWarningUI>,
+                ),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Something off with the layout, servers are indexes into the `Blueprint`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopologyIssue {
+    // Nothing ever sends requests here
+    Unreachable(usize),
+    // A proxy with nowhere to send things, drops everything
+    DeadEndProxy(usize),
+    // A proxy that lists itself as an output, drops its share
+    OutputsToItself(usize),
+    // Proxies forwarding around in a circle
+    Cycle(Vec<usize>),
+    // The Internet isn't wired to anything, every request gets dropped
+    NoDnsRecords,
+    NotEnoughThroughput { max_rps: f32, peak_rps: f32 },
+}
+
+impl TopologyIssue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            TopologyIssue::Unreachable(_) => Severity::Warning,
+            TopologyIssue::NotEnoughThroughput { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn servers(&self) -> Vec<usize> {
+        match self {
+            TopologyIssue::Unreachable(index)
+            | TopologyIssue::DeadEndProxy(index)
+            | TopologyIssue::OutputsToItself(index) => vec![*index],
+            TopologyIssue::Cycle(servers) => servers.clone(),
+            TopologyIssue::NoDnsRecords | TopologyIssue::NotEnoughThroughput { .. } => vec![],
+        }
+    }
+}
+
+impl std::fmt::Display for TopologyIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TopologyIssue::Unreachable(_) => write!(f, "Never gets any requests"),
+            TopologyIssue::DeadEndProxy(_) => write!(f, "Proxy with no outputs, drops everything"),
+            TopologyIssue::OutputsToItself(_) => write!(f, "Proxy outputs to itself"),
+            TopologyIssue::Cycle(_) => write!(f, "Proxies forward in a circle"),
+            TopologyIssue::NoDnsRecords => write!(f, "No DNS records, drops everything"),
+            TopologyIssue::NotEnoughThroughput { max_rps, peak_rps } => write!(
+                f,
+                "Handles at most {max_rps:.1} RPS, level peaks at {peak_rps:.1} RPS"
+            ),
+        }
+    }
+}
+
+/// Requests per second a server gets through when it's never idle
+pub fn service_rate(mode: ServerMode, processing_power: usize) -> f32 {
+    1.0 / processing_time(mode, processing_power.max(1)).as_secs_f32()
+}
+
+// Where new requests go first, and what part of the traffic each one gets
+fn entry_shares(blueprint: &Blueprint) -> Vec<(usize, f32)> {
+    // No DNS records means every request gets dropped at the Internet
+    let weights: Vec<f32> = blueprint
+        .ingress
+        .iter()
+        .map(|index| match blueprint.ingress_algorithm {
+            LoadBalancingAlgorithm::RoundRobin => 1.0,
+            LoadBalancingAlgorithm::Weighted => blueprint.servers[*index].processing_power as f32,
+        })
+        .collect();
+    let total: f32 = weights.iter().sum();
+    blueprint
+        .ingress
+        .iter()
+        .zip(weights)
+        .map(|(index, weight)| (*index, weight / total))
+        .collect()
+}
+
+/// What fraction of the incoming traffic passes through each server, 1.0
+/// being every request. Proxies split evenly between their outputs, same as
+/// the round-robin in `process_requests`.
+pub fn load_shares(blueprint: &Blueprint) -> Vec<f32> {
+    let count = blueprint.servers.len();
+    let mut shares = vec![0.0; count];
+    let mut arriving = vec![0.0; count];
+    for (index, share) in entry_shares(blueprint) {
+        arriving[index] += share;
+    }
+    for _ in 0..MAX_HOPS {
+        let mut forwarded = vec![0.0; count];
+        for (index, amount) in arriving.iter().enumerate() {
+            shares[index] += amount;
+            let server = &blueprint.servers[index];
+            if server.mode == ServerMode::Proxy && !server.outputs.is_empty() {
+                let split = amount / server.outputs.len() as f32;
+                for output in &server.outputs {
+                    // Sending to ourselves gets dropped, it doesn't loop
+                    if *output != index {
+                        forwarded[*output] += split;
+                    }
+                }
+            }
+        }
+        if forwarded.iter().all(|amount| *amount < 0.0001) {
+            break;
+        }
+        arriving = forwarded;
+    }
+    shares
+}
+
+/// Highest RPS the layout can take before some server can't keep up
+pub fn max_throughput(blueprint: &Blueprint) -> f32 {
+    load_shares(blueprint)
+        .iter()
+        .zip(&blueprint.servers)
+        .filter(|(share, _)| **share > 0.0)
+        .map(|(share, server)| service_rate(server.mode, server.processing_power) / share)
+        .reduce(f32::min)
+        .unwrap_or(0.0)
+}
+
+// Finds proxy cycles with a depth first search, each one reported once
+fn find_cycles(blueprint: &Blueprint) -> Vec<Vec<usize>> {
+    fn visit(
+        blueprint: &Blueprint,
+        index: usize,
+        path: &mut Vec<usize>,
+        done: &mut Vec<bool>,
+        cycles: &mut Vec<Vec<usize>>,
+    ) {
+        if let Some(start) = path.iter().position(|i| *i == index) {
+            let mut cycle = path[start..].to_vec();
+            cycle.sort();
+            if !cycles.contains(&cycle) {
+                cycles.push(cycle);
+            }
+            return;
+        }
+        if done[index] {
+            return;
+        }
+        let server = &blueprint.servers[index];
+        if server.mode == ServerMode::Proxy {
+            path.push(index);
+            for output in &server.outputs {
+                // Pointing at itself is its own issue
+                if *output != index {
+                    visit(blueprint, *output, path, done, cycles);
+                }
+            }
+            path.pop();
+        }
+        done[index] = true;
+    }
+
+    let mut done = vec![false; blueprint.servers.len()];
+    let mut cycles = vec![];
+    for index in 0..blueprint.servers.len() {
+        visit(blueprint, index, &mut vec![], &mut done, &mut cycles);
+    }
+    cycles
+}
+
+/// Everything wrong with a layout that we can tell without running it
+pub fn validate_topology(blueprint: &Blueprint, peak_rps: f32) -> Vec<TopologyIssue> {
+    let mut issues = vec![];
+    if blueprint.ingress.is_empty() {
+        issues.push(TopologyIssue::NoDnsRecords);
+    }
+    let shares = load_shares(blueprint);
+    for (index, server) in blueprint.servers.iter().enumerate() {
+        if shares[index] <= 0.0 {
+            issues.push(TopologyIssue::Unreachable(index));
+        }
+        if server.mode == ServerMode::Proxy {
+            if server.outputs.is_empty() {
+                issues.push(TopologyIssue::DeadEndProxy(index));
+            } else if server.outputs.contains(&index) {
+                issues.push(TopologyIssue::OutputsToItself(index));
+            }
+        }
+    }
+    for cycle in find_cycles(blueprint) {
+        issues.push(TopologyIssue::Cycle(cycle));
+    }
+    let max_rps = max_throughput(blueprint);
+    if max_rps < peak_rps {
+        issues.push(TopologyIssue::NotEnoughThroughput { max_rps, peak_rps });
+    }
+    issues
+}
+
+/// The latest analysis of what's on the board, kept up to date in planning
+#[derive(Resource, Default)]
+pub struct TopologyReport {
+    pub blueprint: Option<Blueprint>,
+    // Same order as the blueprint's servers
+    pub servers: Vec<Entity>,
+    pub issues: Vec<TopologyIssue>,
+}
+
+#[derive(Component)]
+pub struct TopologyIssueLabel;
+
+/// What the Start button and hotkey send. Goes through to
+/// `StartLoadScenarios` unless the layout has errors, then it takes a second
+/// press to start anyway.
+#[derive(Event)]
+pub struct RequestStartEvent;
+
+impl From<ListenerInput<Pointer<Click>>> for RequestStartEvent {
+    fn from(_event: ListenerInput<Pointer<Click>>) -> Self {
+        RequestStartEvent
+    }
+}
+
+#[derive(Component)]
+pub struct StartWarningUI;
+
+// Labels got cleared on the way out, so analyse from scratch again
+fn reset_topology_report(mut report: ResMut<TopologyReport>) {
+    *report = TopologyReport::default();
+}
+
+pub fn analyse_topology(
+    mut report: ResMut<TopologyReport>,
+    q_servers: Query<(Entity, &Transform, &Server)>,
+    q_slot: SlotOrderQuery,
+    q_ingress: Query<&Ingress>,
+    game_levels: Res<GameLevels>,
+) {
+    let mut servers: Vec<(Entity, Vec3, &Server)> = q_servers
+        .iter()
+        .map(|(e, t, server)| (e, t.translation, server))
+        .collect();
+    servers.sort_by_key(|(e, _, _)| slot_order(&q_slot, *e));
+    let blueprint = capture_blueprint(game_levels.current, &servers, q_ingress.get_single().ok());
+    if report.blueprint.as_ref() == Some(&blueprint) {
+        return;
+    }
+
+    report.issues = validate_topology(&blueprint, game_levels.active_level().peak_rps());
+    report.servers = servers.iter().map(|(e, _, _)| *e).collect();
+    report.blueprint = Some(blueprint);
+}
+
+fn show_topology_issues(
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    report: Res<TopologyReport>,
+    q_transforms: Query<&Transform, With<Server>>,
+    q_labels: Query<Entity, With<TopologyIssueLabel>>,
+) {
+    // Labels only need redoing when the report does
+    if report.is_changed() {
+        for entity in q_labels.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    let mut label_rows: HashMap<usize, usize> = HashMap::new();
+    for issue in &report.issues {
+        let color = match issue.severity() {
+            Severity::Warning => AMBER_400,
+            Severity::Error => RED_500,
+        };
+        // Issues about the whole layout get shown by the Internet instead
+        let mut places = vec![];
+        for index in issue.servers() {
+            if let Some(transform) = report
+                .servers
+                .get(index)
+                .and_then(|e| q_transforms.get(*e).ok())
+            {
+                let position = transform.translation.truncate();
+                gizmos.circle_2d(position, 56.0, color);
+                places.push((index, position));
+            }
+        }
+        if issue.servers().is_empty() {
+            places.push((usize::MAX, INGRESS_POSITION.truncate()));
+        }
+
+        for (index, position) in places {
+            if report.is_changed() {
+                let row = label_rows.entry(index).or_insert(0);
+                commands.spawn((
+                    TopologyIssueLabel,
+                    Text2dBundle {
+                        text: Text::from_section(
+                            issue.to_string(),
+                            TextStyle {
+                                font_size: 16.0,
+                                color: color.into(),
+                                ..default()
+                            },
+                        ),
+                        transform: Transform::from_translation(
+                            (position + Vec2::new(0.0, 70.0 + *row as f32 * 18.0)).extend(20.0),
+                        ),
+                        ..default()
+                    },
+                    Pickable::IGNORE,
+                ));
+                *row += 1;
+            }
+        }
+    }
+}
+
+fn confirm_start(
+    mut commands: Commands,
+    report: Res<TopologyReport>,
+    mut warned_about: Local<Option<Blueprint>>,
+    mut evs: EventWriter<StartLoadScenarios>,
+) {
+    let errors: Vec<&TopologyIssue> = report
+        .issues
+        .iter()
+        .filter(|issue| issue.severity() == Severity::Error)
+        .collect();
+    // Second press on the same layout, they've seen the problems
+    if errors.is_empty() || (warned_about.is_some() && *warned_about == report.blueprint) {
+        *warned_about = None;
+        evs.send(StartLoadScenarios);
+        return;
+    }
+    *warned_about = report.blueprint.clone();
+
+    let mut text = "This layout has problems:\n".to_string();
+    for issue in errors {
+        let servers: Vec<String> = issue
+            .servers()
+            .iter()
+            .map(|index| format!("#{}", index + 1))
+            .collect();
+        text += &format!("- {issue} {}\n", servers.join(", "));
+    }
+    text += "Press Start again to run it anyway";
+    commands.spawn((
+        StartWarningUI,
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 18.0,
+                color: RED_500.into(),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+        Pickable::IGNORE,
+    ));
+}
+
+// The warning was about a layout that isn't there any more
+fn clear_start_warning(mut commands: Commands, query: Query<Entity, With<StartWarningUI>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn warn_before_start(report: Res<TopologyReport>) {
+    for issue in &report.issues {
+        let servers: Vec<String> = issue
+            .servers()
+            .iter()
+            .map(|index| format!("#{}", index + 1))
+            .collect();
+        println!("{:?}: {issue} {}", issue.severity(), servers.join(", "));
+    }
+}
@@ -13,7 +13,7 @@ impl Plugin for UIPlugin {
                 Update,
                 (
                     show_hide_selection_ui.run_if(in_state(EditMode::Upgrade)),
-                    update_ui.run_if(in_state(GameState::Running)),
+                    update_ui.run_if(in_state(Simulating)),
                     update_planning_ui.run_if(in_state(EditMode::Upgrade)),
                     button_interactivity
                 ),
@@ -21,8 +21,16 @@ impl Plugin for UIPlugin {
             // Show/hide selection UI
 
             .add_systems(OnEnter(GameState::Running), spawn_running_ui)
+            .add_systems(OnEnter(GameState::Replay), spawn_running_ui)
+            .add_systems(OnEnter(GameState::Sandbox), spawn_running_ui)
             // .add_systems(OnEnter(GameState::Planning), spawn_planning_ui)
-            .add_systems(OnEnter(GameState::Results), spawn_results_ui)
+            .add_systems(
+                OnEnter(GameState::Results),
+                // Endless runs have their own game over screen
+                spawn_results_ui
+                    .after(calculate_pass_or_not)
+                    .run_if(not(resource_exists::<EndlessRun>)),
+            )
             .add_systems(OnEnter(GameState::GameCompleted), spawn_completed_ui)
 
             // EditMode States
@@ -33,6 +41,8 @@ impl Plugin for UIPlugin {
             .add_systems(OnExit(EditMode::Outputs), clear_entity_with::<OutputsUI>)
             // 
             .add_systems(OnExit(GameState::Running), clear_entity_with::<RunningUI>)
+            .add_systems(OnExit(GameState::Replay), clear_entity_with::<RunningUI>)
+            .add_systems(OnExit(GameState::Sandbox), clear_entity_with::<RunningUI>)
             .add_systems(OnExit(GameState::Results), clear_entity_with::<ResultsUI>)
 
             // .add_systems(Startup, spawn_results_ui)
@@ -52,7 +62,7 @@ impl Plugin for UIPlugin {
     }
 }
 
-fn clear_entity_with<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+pub fn clear_entity_with<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
     // Delete all the planning UI
     for entity in query.iter() {
         commands.entity(entity).despawn_recursive();
@@ -74,12 +84,16 @@ pub struct CompletedUI;
 #[derive(Component)]
 pub struct OutputsUI;
 
+#[derive(Component)]
+pub struct OnCallUI;
+
 create_markers!(
     CurrentLevelTitleText,
     SelectedLabel,
     SetFilterOutputButton,
     SetOutputsButton,
     SwitchModeButton,
+    QueueDisciplineButton,
     ResetUpgradesButton,
     UpgradeQueueSizeButton,
     ServerSelectionUI,
@@ -91,15 +105,41 @@ create_markers!(
     LoadRPSText,
     StartButton,
     ResetButton,
+    ExportBlueprintButton,
+    ImportBlueprintButton,
+    OnCallButton,
+    BackpressureButton,
+    OnCallSelectionUI,
+    OnCallLabel,
+    UndoButton,
+    RedoButton,
     // Results UI
     ResultsVerdictText,
     ResultsRetryButton,
     ResultsNextButton,
+    ResultsLevelsButton,
+    ResultsWatchReplayButton,
+    ResultsCopyReplayButton,
+    ResultsExportStatsButton,
     ResultsPercentageText,
-    ResultsPercentageRequirementText
+    ResultsPercentageRequirementText,
+    // Level editor UI
+    OpenEditorButton,
+    OpenSandboxButton,
+    OpenEndlessButton,
+    EndlessPlayAgainButton,
+    EndlessLevelsButton,
+    BackToEditorButton,
+    EditorTestPlayButton,
+    EditorSaveButton,
+    EditorBackButton,
+    AddScheduleButton,
+    RemoveScheduleButton,
+    AddLockedServerButton,
+    RemoveLockedServerButton
 );
 
-fn spawn_text<UI, T>(
+pub(crate) fn spawn_text<UI, T>(
     ui_component: UI,
     font_assets: &Res<FontAssets>,
     commands: &mut Commands,
@@ -108,7 +148,7 @@ fn spawn_text<UI, T>(
     component: T,
 ) where
     UI: Component,
-    T: Component,
+    T: Bundle,
 {
     let vertical_spacing = 40.0;
     // let font_handle = asset_server.load("fonts/MajorMonoDisplay-Regular.ttf");
@@ -141,7 +181,7 @@ fn spawn_text<UI, T>(
     ));
 }
 
-fn spawn_button<C, E>(
+pub(crate) fn spawn_button<C, E>(
     commands: &mut Commands,
     label: &str,
     offset: usize,
@@ -237,19 +277,81 @@ fn spawn_child_button<T, U>(
         });
 }
 
+fn spawn_half_button<T, U>(builder: &mut ChildBuilder, label: &str, component: U, bg_color: Srgba)
+where
+    U: Bundle,
+    T: Event + From<ListenerInput<Pointer<Click>>>,
+{
+    builder
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    flex_grow: 1.0,
+                    height: Val::Px(50.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: bg_color.into(),
+                ..default()
+            },
+            component,
+            PickableBundle::default(),
+            On::<Pointer<Click>>::send_event::<T>(),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    label,
+                    TextStyle {
+                        font_size: 22.0,
+                        color: BLACK.into(),
+                        ..default()
+                    },
+                ),
+                Pickable::IGNORE,
+            ));
+        });
+}
+
 fn make_connections(
     mut next_state: ResMut<NextState<EditMode>>,
     // This contains the source server
     selected: Res<SelectedServerForOutputs>,
-    mut q_server: Query<(&mut Server, &mut Handle<Image>)>,
+    mut q_server: Query<(&mut Server, &mut Handle<Image>, Has<Locked>)>,
+    mut q_ingress: Query<&mut Ingress>,
     // These are the destination servers
     q_selection: Query<(Entity, &PickSelection), With<Server>>,
     image_assets: Res<ImageAssets>,
+    game_levels: Res<GameLevels>,
 ) {
     // Figure out what server we initially selected
     match selected.0 {
+        Some(current_ingress) if q_ingress.contains(current_ingress) => {
+            // Wiring the Internet, these become the DNS records
+            let outputs: Vec<Entity> = q_selection
+                .iter()
+                .filter(|(_, selection)| selection.is_selected)
+                .map(|(selected_entity, _)| selected_entity)
+                .collect();
+            if !game_levels.active_level().allows_dns_records(outputs.len()) {
+                println!("Level doesn't allow that many DNS records");
+                next_state.set(EditMode::Upgrade);
+                return;
+            }
+            q_ingress
+                .get_mut(current_ingress)
+                .unwrap()
+                .set_outputs(outputs);
+            next_state.set(EditMode::Upgrade);
+        }
         Some(current_server) => {
-            let (mut server, mut handle) = q_server.get_mut(current_server).unwrap();
+            let (mut server, mut handle, locked) = q_server.get_mut(current_server).unwrap();
+            if locked && server.mode == ServerMode::Process {
+                println!("Locked server can't become a proxy");
+                next_state.set(EditMode::Upgrade);
+                return;
+            }
             // Make sure selected server is Proxy
             // TODO duplicated logic
             match server.mode {
@@ -638,6 +740,70 @@ pub fn spawn_results_ui(
                         ),
                         Pickable::IGNORE,
                     ));
+                    for objective in &level_results.objectives {
+                        let (verdict, color) = match (objective.passed, objective.bonus) {
+                            (true, _) => ("Pass", GREEN_400),
+                            (false, false) => ("Fail", RED_400),
+                            // Missing a bonus doesn't fail the level
+                            (false, true) => ("Missed", GRAY_400),
+                        };
+                        parent.spawn((
+                            TextBundle::from_section(
+                                format!(
+                                    "\n{verdict}: {}{} ({})",
+                                    if objective.bonus { "Bonus, " } else { "" },
+                                    objective.description,
+                                    objective.achieved
+                                ),
+                                TextStyle {
+                                    font_size: 18.0,
+                                    font: font_handle.clone(),
+                                    color: color.into(),
+                                    ..default()
+                                },
+                            ),
+                            Pickable::IGNORE,
+                        ));
+                    }
+                    // Where the requests got lost, the edge is cheaper than a full server
+                    if game_stats.dropped_at_edge > 0 {
+                        parent.spawn((
+                            TextBundle::from_section(
+                                format!(
+                                    "\nDropped at the edge: {}, at the servers: {}",
+                                    game_stats.dropped_at_edge,
+                                    game_stats
+                                        .dropped_requests
+                                        .saturating_sub(game_stats.dropped_at_edge)
+                                ),
+                                TextStyle {
+                                    font_size: 18.0,
+                                    font: font_handle.clone(),
+                                    color: WHITE_SMOKE.into(),
+                                    ..default()
+                                },
+                            ),
+                            Pickable::IGNORE,
+                        ));
+                    }
+                    // Which window blew the SLO, if any
+                    if let Some(report) = &level_results.slo {
+                        let color = if report.met { GREEN_400 } else { RED_400 };
+                        for line in report.explain() {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    format!("\n{line}"),
+                                    TextStyle {
+                                        font_size: 18.0,
+                                        font: font_handle.clone(),
+                                        color: color.into(),
+                                        ..default()
+                                    },
+                                ),
+                                Pickable::IGNORE,
+                            ));
+                        }
+                    }
                     if level_results.passed {
                         spawn_child_button::<LoadNextLevel, ResultsNextButton>(
                             parent,
@@ -658,6 +824,48 @@ pub fn spawn_results_ui(
                             GREEN_400
                         },
                     );
+                    spawn_child_button::<ShowLevelSelect, ResultsLevelsButton>(
+                        parent,
+                        "Levels",
+                        ResultsLevelsButton,
+                        WHITE_SMOKE,
+                        BLUE_800,
+                    );
+                    parent
+                        .spawn((
+                            NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::Row,
+                                    column_gap: Val::Px(20.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            Pickable::IGNORE,
+                        ))
+                        .with_children(|parent| {
+                            spawn_child_button::<WatchReplayEvent, ResultsWatchReplayButton>(
+                                parent,
+                                "Watch Replay",
+                                ResultsWatchReplayButton,
+                                WHITE_SMOKE,
+                                BLUE_800,
+                            );
+                            spawn_child_button::<CopyReplayEvent, ResultsCopyReplayButton>(
+                                parent,
+                                "Copy Replay",
+                                ResultsCopyReplayButton,
+                                WHITE_SMOKE,
+                                BLUE_800,
+                            );
+                            spawn_child_button::<ExportRunEvent, ResultsExportStatsButton>(
+                                parent,
+                                "Export Stats",
+                                ResultsExportStatsButton,
+                                WHITE_SMOKE,
+                                BLUE_800,
+                            );
+                        });
                 });
         });
 }
@@ -690,6 +898,75 @@ pub fn spawn_running_ui(mut commands: Commands, asset_server: Res<FontAssets>) {
     );
 }
 
+// Same idea as the selection UI in planning, but for changes made mid-run
+pub fn spawn_on_call_ui(mut commands: Commands) {
+    commands
+        .spawn((
+            OnCallUI,
+            NodeBundle {
+                style: Style {
+                    bottom: Val::Px(60.0),
+                    width: Val::Px(350.0),
+                    height: Val::Px(300.0),
+                    right: Val::Px(10.0),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    padding: UiRect::all(Val::Px(20.0)),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                background_color: Color::srgba(0.15, 0.15, 0.15, 0.9).into(),
+                ..default()
+            },
+            OnCallSelectionUI,
+            NoDeselect,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "On-call",
+                    TextStyle {
+                        font_size: 22.0,
+                        color: Color::srgba(0.9, 0.9, 0.9, 1.0),
+                        ..default()
+                    },
+                ),
+                OnCallLabel,
+                Pickable::IGNORE,
+            ));
+            spawn_child_button::<OnCallButtonEvent, OnCallAction>(
+                parent,
+                "Change Mode",
+                OnCallAction::SwitchMode,
+                BLACK,
+                GREEN_400,
+            );
+            spawn_child_button::<OnCallButtonEvent, OnCallAction>(
+                parent,
+                "Set Outputs",
+                OnCallAction::SetOutputs,
+                BLACK,
+                BLUE_400,
+            );
+            spawn_child_button::<OnCallButtonEvent, OnCallAction>(
+                parent,
+                "Upgrade CPU",
+                OnCallAction::UpgradeCpu,
+                BLACK,
+                GREEN_400,
+            );
+            spawn_child_button::<OnCallButtonEvent, OnCallAction>(
+                parent,
+                "Upgrade Queue Size",
+                OnCallAction::UpgradeQueue,
+                BLACK,
+                GREEN_400,
+            );
+        });
+}
+
 pub fn spawn_planning_ui(
     mut commands: Commands,
     game_levels: Res<GameLevels>,
@@ -756,7 +1033,7 @@ pub fn spawn_planning_ui(
         RemainingPointsText,
     );
 
-    spawn_button::<StartButton, StartLoadScenarios>(
+    spawn_button::<StartButton, RequestStartEvent>(
         &mut commands,
         "Start",
         0,
@@ -770,6 +1047,78 @@ pub fn spawn_planning_ui(
         ResetButton,
         ORANGE_400,
     );
+    spawn_button::<ExportBlueprintButton, ExportBlueprintEvent>(
+        &mut commands,
+        "Export Blueprint",
+        2,
+        ExportBlueprintButton,
+        BLUE_400,
+    );
+    spawn_button::<ImportBlueprintButton, ImportBlueprintEvent>(
+        &mut commands,
+        "Import Blueprint",
+        3,
+        ImportBlueprintButton,
+        BLUE_400,
+    );
+
+    // On-call / Backpressure, side by side like Undo / Redo below
+    commands
+        .spawn((
+            PlanningUI,
+            NodeBundle {
+                style: Style {
+                    top: Val::Px(55.0 * 4.0 + 10.0),
+                    width: Val::Px(350.0),
+                    height: Val::Px(50.0),
+                    right: Val::Px(10.0),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            spawn_half_button::<ToggleOnCallEvent, OnCallButton>(
+                parent,
+                "On-call: Off",
+                OnCallButton,
+                ORANGE_200,
+            );
+            spawn_half_button::<ToggleBackpressureEvent, BackpressureButton>(
+                parent,
+                "Backpressure: Off",
+                BackpressureButton,
+                PURPLE_200,
+            );
+        });
+
+    // Undo / Redo, side by side where a full-width button would go
+    commands
+        .spawn((
+            PlanningUI,
+            NodeBundle {
+                style: Style {
+                    top: Val::Px(55.0 * 5.0 + 10.0),
+                    width: Val::Px(350.0),
+                    height: Val::Px(50.0),
+                    right: Val::Px(10.0),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_children(|parent| {
+            spawn_half_button::<UndoEvent, UndoButton>(parent, "Undo", UndoButton, BLUE_200);
+            spawn_half_button::<RedoEvent, RedoButton>(parent, "Redo", RedoButton, BLUE_200);
+        });
 
     // Selection UI
     commands
@@ -811,13 +1160,34 @@ pub fn spawn_planning_ui(
                 SelectedLabel,
                 Pickable::IGNORE,
             ));
-            spawn_child_button::<ChangeServerModeEvent, SwitchModeButton>(
-                parent,
-                "Change Mode",
-                SwitchModeButton,
-                BLACK,
-                GREEN_400,
-            );
+            // Side by side, like Undo / Redo, to keep the panel short
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Px(250.0),
+                            margin: UiRect::axes(Val::Px(0.0), Val::Px(15.0)),
+                            column_gap: Val::Px(10.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    Pickable::IGNORE,
+                ))
+                .with_children(|parent| {
+                    spawn_half_button::<ChangeServerModeEvent, SwitchModeButton>(
+                        parent,
+                        "Change Mode",
+                        SwitchModeButton,
+                        BLUE_200,
+                    );
+                    spawn_half_button::<ChangeQueueDisciplineEvent, QueueDisciplineButton>(
+                        parent,
+                        "Queue Order",
+                        QueueDisciplineButton,
+                        BLUE_200,
+                    );
+                });
             // Only render this if we're a proxy
             spawn_child_button::<SetServerOutputEvent, SetOutputsButton>(
                 parent,
@@ -854,8 +1224,10 @@ pub fn update_planning_ui(
     points: Res<UpgradePoints>,
     mut texts: ParamSet<(Query<&mut Text, With<RemainingPointsText>>,)>,
 ) {
-    texts.p0().get_single_mut().unwrap().sections[1].value =
-        (points.total - points.assigned).to_string();
+    texts.p0().get_single_mut().unwrap().sections[1].value = match points.reserved {
+        0 => points.available().to_string(),
+        reserved => format!("{} (+{reserved} on call)", points.available()),
+    };
 }
 
 pub fn update_ui(
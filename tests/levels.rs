@@ -0,0 +1,208 @@
+#![feature(new_range_api)]
+
+// Checks the levels still play the way TODO.md wants them to:
+//
+// - Not passable by just upgrading CPU or Queue size on one server
+// - Not passable by just upgrading one of the upgrades on one server
+// - Require some server to do the proxying
+//
+// Everything runs on the headless simulation with fixed seeds, so a balance
+// change that breaks a level breaks these. The campaign levels only get one
+// DNS record, so the only way to spread the load is through a proxy. Alpha
+// Test is the tutorial with a single server, so only the first two checks
+// make sense for it (see `alpha_test_only_has_one_server`).
+//
+// The proxy checks run a small solver budget by default, the thorough ones
+// are ignored since they take minutes. The simulation is a lot faster in
+// release:
+//
+//     cargo test --release --test levels -- --include-ignored
+
+use indie_games_website_simulator::prelude::*;
+
+const SEEDS: [u64; 3] = [0, 1, 2];
+
+const ALPHA_TEST: usize = 0;
+const WEBSITE_LAUNCH: usize = 1;
+const GMTK_GAME_JAM: usize = 2;
+
+fn level(index: usize) -> Level {
+    GameLevels::default().levels[index].clone()
+}
+
+// Known good layouts: the first server gets the DNS record and proxies to
+// all the others. (CPU, queue) for the first server, then for the rest.
+fn reference_solution(index: usize) -> Blueprint {
+    let level = level(index);
+    let mut blueprint = default_layout(index, &level);
+    let (first, rest) = match index {
+        ALPHA_TEST => ((3, 2), (1, 0)),
+        WEBSITE_LAUNCH => ((4, 5), (3, 0)),
+        GMTK_GAME_JAM => ((10, 5), (4, 0)),
+        _ => panic!("level {index} has no reference solution, add one"),
+    };
+    let count = blueprint.servers.len();
+    for (i, server) in blueprint.servers.iter_mut().enumerate() {
+        let upgrades = if i == 0 { first } else { rest };
+        server.processing_power = upgrades.0;
+        server.queue_size = upgrades.1;
+    }
+    if count > 1 {
+        blueprint.servers[0].mode = ServerMode::Proxy;
+        blueprint.servers[0].outputs = (1..count).collect();
+    }
+    blueprint.ingress = vec![0];
+    blueprint
+}
+
+fn assert_reference_solution_passes(index: usize) {
+    let level = level(index);
+    let blueprint = reference_solution(index);
+    assert!(
+        blueprint.validate(&level).is_ok(),
+        "reference solution for {} doesn't fit the level",
+        level.title
+    );
+    let evaluation = evaluate(&level, &blueprint, &SEEDS);
+    assert!(
+        evaluation.passed_all(),
+        "reference solution for {} doesn't pass: {evaluation:?}",
+        level.title
+    );
+}
+
+fn assert_default_layout_fails(index: usize) {
+    let level = level(index);
+    let evaluation = evaluate(&level, &default_layout(index, &level), &SEEDS);
+    assert_eq!(
+        evaluation.passed, 0,
+        "{} passes without doing anything: {evaluation:?}",
+        level.title
+    );
+}
+
+fn assert_single_server_strategies_fail(index: usize) {
+    let level = level(index);
+    let passing: Vec<String> = trivial_strategies(index, &level)
+        .into_iter()
+        .filter(|(_, blueprint)| evaluate(&level, blueprint, &SEEDS).passed > 0)
+        .map(|(name, _)| name)
+        .collect();
+    assert!(
+        passing.is_empty(),
+        "{} passes by upgrading one server: {passing:?}",
+        level.title
+    );
+}
+
+// A quick search by default, `thorough` is what the levels were balanced with
+fn assert_proxy_required(index: usize, thorough: bool) {
+    let level = level(index);
+    let settings = if thorough {
+        SolverSettings {
+            seeds: SEEDS.to_vec(),
+            iterations: 150,
+            restarts: 2,
+            allow_proxies: false,
+            rng_seed: 0,
+        }
+    } else {
+        SolverSettings {
+            seeds: vec![SEEDS[0]],
+            iterations: 30,
+            restarts: 1,
+            allow_proxies: false,
+            rng_seed: 0,
+        }
+    };
+    let solution = solve(index, &level, &settings);
+    assert_eq!(
+        solution.evaluation.passed,
+        0,
+        "{} passes without a proxy: {}",
+        level.title,
+        solution.blueprint.to_text()
+    );
+}
+
+#[test]
+fn alpha_test_reference_solution_passes() {
+    assert_reference_solution_passes(ALPHA_TEST);
+}
+
+#[test]
+fn alpha_test_default_layout_fails() {
+    assert_default_layout_fails(ALPHA_TEST);
+}
+
+// The tutorial: one server to upgrade, nothing to proxy to. Give it more
+// servers and it needs the single server and proxy checks too.
+#[test]
+fn alpha_test_only_has_one_server() {
+    let level = level(ALPHA_TEST);
+    assert_eq!(level.available_servers + level.locked_servers.len(), 1);
+}
+
+#[test]
+fn website_launch_reference_solution_passes() {
+    assert_reference_solution_passes(WEBSITE_LAUNCH);
+}
+
+#[test]
+fn website_launch_default_layout_fails() {
+    assert_default_layout_fails(WEBSITE_LAUNCH);
+}
+
+#[test]
+fn website_launch_single_server_strategies_fail() {
+    assert_single_server_strategies_fail(WEBSITE_LAUNCH);
+}
+
+#[test]
+fn website_launch_proxy_required() {
+    assert_proxy_required(WEBSITE_LAUNCH, false);
+}
+
+#[test]
+#[ignore = "takes minutes, run with --include-ignored"]
+fn website_launch_proxy_required_thorough() {
+    assert_proxy_required(WEBSITE_LAUNCH, true);
+}
+
+#[test]
+fn gmtk_game_jam_reference_solution_passes() {
+    assert_reference_solution_passes(GMTK_GAME_JAM);
+}
+
+#[test]
+fn gmtk_game_jam_default_layout_fails() {
+    assert_default_layout_fails(GMTK_GAME_JAM);
+}
+
+#[test]
+fn gmtk_game_jam_single_server_strategies_fail() {
+    assert_single_server_strategies_fail(GMTK_GAME_JAM);
+}
+
+#[test]
+fn gmtk_game_jam_proxy_required() {
+    assert_proxy_required(GMTK_GAME_JAM, false);
+}
+
+#[test]
+#[ignore = "takes minutes, run with --include-ignored"]
+fn gmtk_game_jam_proxy_required_thorough() {
+    assert_proxy_required(GMTK_GAME_JAM, true);
+}
+
+// New levels need a reference solution and their checks added above
+#[test]
+fn every_level_is_checked() {
+    for (index, level) in GameLevels::default().levels.iter().enumerate() {
+        assert!(
+            reference_solution(index).validate(level).is_ok(),
+            "reference solution for {} doesn't fit the level",
+            level.title
+        );
+    }
+}